@@ -1,7 +1,7 @@
 //! Performs basic text layout in Fontdue.
 
 use fontdue::layout::{
-    Block, CoordinateSystem, HorizontalAlign, Layout, LayoutSettings, Span, VerticalAlign,
+    Block, CoordinateSystem, HorizontalAlign, Layout, LayoutSettings, Renderable, Span, VerticalAlign,
     WrapStyle,
 };
 use fontdue::{Font, FontSettings};
@@ -54,44 +54,53 @@ pub fn main() -> io::Result<()> {
 fn render<'a>(layout: &Layout<'a, u8>, mut file: File, w: usize, h: usize) -> io::Result<()> {
     file.write(format!("P5\n{} {}\n255\n", w, h).as_bytes())?;
     let mut bytes: Vec<u8> = vec![0; w * h];
-    let glyphs = layout.glyphs();
-    if let Some(lines) = layout.lines() {
-        for line in lines.iter() {
-            for glyph in &glyphs[line.glyph_start..=line.glyph_end] {
-                if let Some(config) = glyph.key {
-                    let font = glyph.font;
-                    let (metrics, bitmap) = font.rasterize_config(config);
-
-                    if metrics.width == 0 || glyph.char_data.is_whitespace() || metrics.height == 0 {
-                        continue;
-                    }
-
-                    let x = (glyph.x) as i32;
-                    let y = (glyph.y) as i32;
-
-                    for (row, y) in bitmap.chunks_exact(metrics.width).zip(y..) {
-                        for (value, x) in row.iter().zip(x..) {
-                            let (x, y) = if x < 0 || y < 0 {
-                                continue;
-                            } else {
-                                (x as usize, y as usize)
-                            };
+    for renderable in layout.renderable() {
+        match renderable {
+            Renderable::Glyph {
+                config,
+                font,
+                x,
+                y,
+                alpha,
+                ..
+            } => {
+                if font.metrics_indexed(config.glyph_index, config.px).is_empty() {
+                    continue;
+                }
+                let (metrics, bitmap) = font.rasterize_config(config);
+                let x = x as i32;
+                let y = y as i32;
 
-                            let value = *value;
-                            if value == 0 {
-                                continue;
-                            }
+                for (row, y) in bitmap.chunks_exact(metrics.width).zip(y..) {
+                    for (value, x) in row.iter().zip(x..) {
+                        let (x, y) = if x < 0 || y < 0 {
+                            continue;
+                        } else {
+                            (x as usize, y as usize)
+                        };
 
-                            bytes.get_mut(y * h + x).map(|b| *b = value);
+                        let value = (*value as f32 * alpha) as u8;
+                        if value == 0 {
+                            continue;
                         }
+
+                        bytes.get_mut(y * h + x).map(|b| *b = value);
                     }
-                } else {
-                    for dy in 0..glyph.height {
-                        for dx in 0..glyph.width {
-                            let x = glyph.x as usize + dx;
-                            let y = glyph.y as usize + dy;
-                            bytes.get_mut(y * h + x).map(|b| *b = glyph.user_data);
-                        }
+                }
+            }
+            Renderable::Block {
+                x,
+                y,
+                width,
+                height,
+                user_data,
+                ..
+            } => {
+                for dy in 0..height {
+                    for dx in 0..width {
+                        let x = x as usize + dx;
+                        let y = y as usize + dy;
+                        bytes.get_mut(y * h + x).map(|b| *b = user_data);
                     }
                 }
             }
@@ -0,0 +1,862 @@
+use fontdue::layout::{CoordinateSystem, GlyphRasterConfig, Layout, Span};
+use fontdue::{Font, FontSettings, Metrics, OutlineKind, OutlineSink, Point};
+
+use crate::modules::FONTS;
+
+fn roboto() -> Font {
+    Font::from_bytes(FONTS[0], FontSettings::default()).unwrap()
+}
+
+#[test]
+fn sdf_asymmetric_spread() {
+    let font = roboto();
+    let (plain, _) = font.rasterize('l', 32.0);
+    let (metrics, sdf) = font.rasterize_sdf('l', 32.0, 1.0, 4.0);
+    // The padding follows the larger of the two spreads.
+    assert_eq!(metrics.width, plain.width + 8);
+    assert_eq!(metrics.height, plain.height + 8);
+    assert_eq!(metrics.xmin, plain.xmin - 4);
+    assert_eq!(metrics.ymin, plain.ymin - 4);
+    assert_eq!(sdf.len(), metrics.width * metrics.height);
+    // A short inside spread saturates at the center of the thin stem.
+    let row = &sdf[metrics.width * (metrics.height / 2)..][..metrics.width];
+    assert_eq!(row.iter().copied().max(), Some(255));
+    // The corners are further than the outside spread from the outline.
+    assert_eq!(sdf[0], 0);
+    assert_eq!(sdf[sdf.len() - 1], 0);
+}
+
+#[test]
+fn bevel_height_map() {
+    let font = roboto();
+    let (plain, coverage) = font.rasterize('O', 48.0);
+    let (metrics, bevel) = font.rasterize_bevel('O', 48.0, 2.0);
+    // The height map lines up with the plain coverage.
+    assert_eq!(metrics, plain);
+    assert_eq!(bevel.len(), coverage.len());
+    for (&height, &alpha) in bevel.iter().zip(&coverage) {
+        if alpha < 64 {
+            assert_eq!(height, 0);
+        }
+    }
+    // The ring of the O is thicker than the depth, so its middle is flat at the top.
+    assert!(bevel.iter().any(|&height| height == 255));
+    assert!(bevel.iter().any(|&height| height > 0 && height < 255));
+    // The counter of the O is outside the glyph.
+    assert_eq!(bevel[metrics.width * (metrics.height / 2) + metrics.width / 2], 0);
+}
+
+#[test]
+fn rasterize_exact_quantizes_to_rasterize() {
+    let font = roboto();
+    for character in ['a', 'g', '@'] {
+        let (plain, bitmap) = font.rasterize(character, 17.5);
+        let (metrics, coverage) = font.rasterize_exact(character, 17.5);
+        assert_eq!(metrics, plain);
+        assert_eq!(coverage.len(), bitmap.len());
+        for (&exact, &quantized) in coverage.iter().zip(&bitmap) {
+            assert!((0.0..=1.0).contains(&exact));
+            // The SIMD accumulation may sum in a different order, so allow a step either way.
+            assert!((exact * 255.9 - quantized as f32).abs() < 2.0);
+        }
+    }
+}
+
+#[test]
+fn glyph_contour_counts() {
+    let font = roboto();
+    let contours = |c| font.contour_count(font.lookup_glyph_index(c));
+    assert_eq!(contours('l'), 1);
+    assert_eq!(contours('o'), 2);
+    assert_eq!(contours('B'), 3);
+    assert_eq!(contours(' '), 0);
+    // Accented letters are composites of the base letter and the accent.
+    assert_eq!(contours('é'), contours('e') + 1);
+    assert_eq!(font.contour_count(u16::MAX), 0);
+}
+
+#[test]
+fn composite_glyphs_rasterize_components() {
+    let font = roboto();
+    let (e, _) = font.rasterize('e', 32.0);
+    let (e_acute, bitmap) = font.rasterize('é', 32.0);
+    // The accent component sits above the base letter component.
+    assert_eq!(e_acute.advance_width, e.advance_width);
+    assert_eq!(e_acute.ymin, e.ymin);
+    assert!(e_acute.height > e.height + 4);
+    let accent_rows = e_acute.height - e.height;
+    assert!(bitmap[..accent_rows * e_acute.width].iter().any(|&coverage| coverage == 255));
+}
+
+#[test]
+fn rasterize_config_padded() {
+    let font = roboto();
+    let config = GlyphRasterConfig {
+        glyph_index: font.lookup_glyph_index('k'),
+        px: 24.0,
+        font_hash: font.file_hash(),
+    };
+    let (plain, glyph) = font.rasterize_config(config);
+    let (metrics, bitmap) = font.rasterize_config_padded(config, [1, 2, 3, 4]);
+    assert_eq!(metrics.width, plain.width + 6);
+    assert_eq!(metrics.height, plain.height + 4);
+    assert_eq!(metrics.xmin, plain.xmin - 4);
+    assert_eq!(metrics.ymin, plain.ymin - 3);
+    assert_eq!(metrics.bounds, plain.bounds);
+    assert_eq!(metrics.advance_width, plain.advance_width);
+    for (y, row) in bitmap.chunks_exact(metrics.width).enumerate() {
+        for (x, &coverage) in row.iter().enumerate() {
+            let inside = (4..4 + plain.width).contains(&x) && (1..1 + plain.height).contains(&y);
+            let expected = if inside {
+                glyph[(y - 1) * plain.width + x - 4]
+            } else {
+                0
+            };
+            assert_eq!(coverage, expected);
+        }
+    }
+}
+
+#[test]
+fn raster_padding_setting() {
+    let plain = roboto();
+    let settings = FontSettings {
+        raster_padding: 2,
+        ..FontSettings::default()
+    };
+    let font = Font::from_bytes(FONTS[0], settings).unwrap();
+    assert_ne!(font.file_hash(), plain.file_hash());
+    let config = GlyphRasterConfig {
+        glyph_index: plain.lookup_glyph_index('k'),
+        px: 24.0,
+        font_hash: plain.file_hash(),
+    };
+    let (expected, expected_bitmap) = plain.rasterize_config_padded(config, [2; 4]);
+    let (metrics, bitmap) = font.rasterize('k', 24.0);
+    assert_eq!(font.metrics('k', 24.0), metrics);
+    assert_eq!(metrics, expected);
+    for (&a, &b) in bitmap.iter().zip(expected_bitmap.iter()) {
+        assert!((a as i32 - b as i32).abs() <= 1);
+    }
+    let (metrics, bitmap) = font.rasterize_subpixel('k', 24.0);
+    assert_eq!(bitmap.len(), metrics.width * metrics.height * 3);
+    assert!(bitmap[..metrics.width * 3 * 2].iter().all(|&coverage| coverage == 0));
+    assert!(bitmap.chunks_exact(metrics.width * 3).all(|row| row[..6].iter().all(|&c| c == 0)));
+    assert!(font.metrics(' ', 24.0).is_empty());
+}
+
+#[test]
+fn stem_darkening() {
+    let plain = roboto();
+    let settings = FontSettings {
+        stem_darkening: true,
+        ..FontSettings::default()
+    };
+    let font = Font::from_bytes(FONTS[0], settings).unwrap();
+    assert_ne!(font.file_hash(), plain.file_hash());
+    let (metrics, darkened) = font.rasterize('m', 12.0);
+    let (expected, bitmap) = plain.rasterize('m', 12.0);
+    assert_eq!(metrics, expected);
+    for (&before, &after) in bitmap.iter().zip(darkened.iter()) {
+        assert!(after >= before);
+        if before == 0 || before == 255 {
+            assert_eq!(after, before);
+        }
+    }
+    assert!(bitmap.iter().zip(darkened.iter()).any(|(before, after)| after > before));
+    assert_eq!(font.rasterize('m', 40.0), plain.rasterize('m', 40.0));
+    assert_eq!(font.rasterize_exact('m', 12.0), plain.rasterize_exact('m', 12.0));
+}
+
+#[test]
+fn rasterize_mirrored() {
+    let font = roboto();
+    let (plain, bitmap) = font.rasterize('L', 30.0);
+    let (metrics, mirrored) = font.rasterize_mirrored('L', 30.0, true);
+    assert_eq!((metrics.width, metrics.height), (plain.width, plain.height));
+    for (row, mirrored_row) in bitmap.chunks_exact(plain.width).zip(mirrored.chunks_exact(plain.width)) {
+        assert!(row.iter().eq(mirrored_row.iter().rev()));
+    }
+    // The side bearings swap.
+    let right_bearing = plain.advance_width - plain.bounds.xmin - plain.bounds.width;
+    assert!((metrics.bounds.xmin - right_bearing).abs() < 0.001);
+    let right_edge = plain.advance_width.round() as i32 - plain.xmin;
+    assert_eq!(metrics.xmin + metrics.width as i32, right_edge);
+
+    let (metrics, mirrored) = font.rasterize_mirrored('L', 30.0, false);
+    assert_eq!(metrics, plain);
+    assert!(bitmap.chunks_exact(plain.width).rev().flatten().eq(mirrored.iter()));
+    assert!(font.rasterize_mirrored(' ', 30.0, true).1.is_empty());
+}
+
+#[test]
+fn side_bearings() {
+    let font = roboto();
+    // At 2048px, Roboto's units per em, the side bearings are in font units.
+    assert_eq!(font.side_bearings('H', 2048.0), (169.0, 172.0));
+    assert_eq!(font.side_bearings('V', 2048.0), (28.0, 26.0));
+    // The j hangs left of its origin.
+    assert_eq!(font.side_bearings('j', 2048.0), (-65.0, 144.0));
+    let (lsb, rsb) = font.side_bearings('o', 20.48);
+    assert!((lsb - 0.91).abs() < 1e-4 && (rsb - 0.92).abs() < 1e-4);
+}
+
+#[test]
+fn rasterize_into_region() {
+    let font = roboto();
+    let config = GlyphRasterConfig {
+        glyph_index: font.lookup_glyph_index('g'),
+        px: 24.0,
+        font_hash: font.file_hash(),
+    };
+    let (plain, glyph) = font.rasterize_config(config);
+    let (atlas_width, atlas_height) = (40, 30);
+    let mut atlas = vec![7; atlas_width * atlas_height];
+    let metrics = font.rasterize_into_region(config, &mut atlas, atlas_width, 5, 3).unwrap();
+    assert_eq!(metrics, plain);
+    for (y, row) in atlas.chunks_exact(atlas_width).enumerate() {
+        for (x, &coverage) in row.iter().enumerate() {
+            if (5..5 + plain.width).contains(&x) && (3..3 + plain.height).contains(&y) {
+                let expected = glyph[(y - 3) * plain.width + x - 5];
+                assert!((coverage as i32 - expected as i32).abs() <= 1);
+            } else {
+                // Pixels outside the region are untouched.
+                assert_eq!(coverage, 7);
+            }
+        }
+    }
+    // Regions reaching past the atlas are rejected.
+    assert!(font.rasterize_into_region(config, &mut atlas, atlas_width, atlas_width - 2, 0).is_err());
+    assert!(font.rasterize_into_region(config, &mut atlas, atlas_width, 0, atlas_height - 2).is_err());
+
+    // An empty glyph with a padded bitmap still clears its whole region.
+    let settings = FontSettings {
+        raster_padding: 3,
+        ..FontSettings::default()
+    };
+    let padded = Font::from_bytes(FONTS[0], settings).unwrap();
+    let space = GlyphRasterConfig {
+        glyph_index: padded.lookup_glyph_index(' '),
+        px: 24.0,
+        font_hash: padded.file_hash(),
+    };
+    let mut atlas = vec![0xFF; atlas_width * atlas_height];
+    let metrics = padded.rasterize_into_region(space, &mut atlas, atlas_width, 5, 3).unwrap();
+    assert!(metrics.is_empty() && metrics.width > 0 && metrics.height > 0);
+    for (y, row) in atlas.chunks_exact(atlas_width).enumerate() {
+        for (x, &coverage) in row.iter().enumerate() {
+            let inside = (5..5 + metrics.width).contains(&x) && (3..3 + metrics.height).contains(&y);
+            let expected = if inside {
+                0
+            } else {
+                0xFF
+            };
+            assert_eq!(coverage, expected);
+        }
+    }
+}
+
+#[test]
+fn point_distances() {
+    let p = Point::new(3.0, 4.0);
+    assert_eq!(p.distance(Point::new(0.0, 0.0)), 5.0);
+    assert_eq!(p.distance_squared(Point::new(0.0, 0.0)), 25.0);
+    // Beside the segment, the distance is perpendicular to it.
+    assert_eq!(p.distance_to_segment(Point::new(0.0, 0.0), Point::new(10.0, 0.0)), 4.0);
+    // Past the segment's ends, the distance is to the closest end.
+    assert_eq!(p.distance_to_segment(Point::new(-10.0, 0.0), Point::new(0.0, 0.0)), 5.0);
+    assert_eq!(p.distance_to_segment(Point::new(6.0, 8.0), Point::new(6.0, 8.0)), 5.0);
+}
+
+#[test]
+fn rasterize_px() {
+    let font = roboto();
+    assert_eq!(font.rasterize_px('g', 35), font.rasterize('g', 35.0));
+}
+
+#[test]
+fn metrics_unscaled() {
+    let close = |a: f32, b: f32| (a - b).abs() < 0.001;
+    for font in &[roboto(), Font::from_bytes(FONTS[5], FontSettings::default()).unwrap()] {
+        let scale = 37.0 / font.units_per_em();
+        let index = font.lookup_glyph_index('H');
+        let unscaled = font.metrics_unscaled(index);
+        let scaled = font.metrics_indexed(index, 37.0);
+        assert!(close(scaled.advance_width, unscaled.advance_width as f32 * scale));
+        assert!(close(scaled.bounds.xmin, unscaled.xmin as f32 * scale));
+        assert!(close(scaled.bounds.ymin, unscaled.ymin as f32 * scale));
+        assert!(close(scaled.bounds.width, unscaled.width as f32 * scale));
+        assert!(close(scaled.bounds.height, unscaled.height as f32 * scale));
+    }
+    let font = roboto();
+    let unscaled = font.metrics_unscaled(font.lookup_glyph_index('H'));
+    assert_eq!((unscaled.xmin, unscaled.advance_width - unscaled.width), (169, 169 + 172));
+}
+
+#[test]
+fn glyph_polygons() {
+    let settings = FontSettings {
+        load_outlines: true,
+        ..FontSettings::default()
+    };
+    let font = Font::from_bytes(FONTS[0], settings).unwrap();
+    let signed_area = |ring: &[Point]| {
+        let mut area = 0.0;
+        for (i, a) in ring.iter().enumerate() {
+            let b = ring[(i + 1) % ring.len()];
+            area += a.x * b.y - b.x * a.y;
+        }
+        area / 2.0
+    };
+    // H is a single straight contour.
+    let h = font.glyph_polygons(font.lookup_glyph_index('H'), 2048.0, 1.0);
+    assert_eq!(h.len(), 1);
+    assert_eq!(h[0].len(), 12);
+    assert!(h[0].contains(&Point::new(169.0, 0.0)));
+
+    // The hole in o winds opposite to its outside.
+    let index = font.lookup_glyph_index('o');
+    let coarse = font.glyph_polygons(index, 40.0, 1.0);
+    let fine = font.glyph_polygons(index, 40.0, 0.05);
+    assert_eq!(coarse.len(), 2);
+    assert!(signed_area(&coarse[0]) * signed_area(&coarse[1]) < 0.0);
+    assert!(fine[0].len() > coarse[0].len());
+    let metrics = font.metrics_indexed(index, 40.0);
+    for point in fine.iter().flatten() {
+        assert!(
+            point.x >= metrics.bounds.xmin - 0.01
+                && point.x <= metrics.bounds.xmin + metrics.bounds.width + 0.01
+        );
+    }
+
+    // Outlines aren't kept by default.
+    assert!(roboto().glyph_polygons(index, 40.0, 1.0).is_empty());
+}
+
+#[derive(Default)]
+struct CommandCounts {
+    moves: usize,
+    lines: usize,
+    quads: usize,
+    curves: usize,
+    closes: usize,
+    first: Option<Point>,
+}
+
+impl OutlineSink for CommandCounts {
+    fn move_to(&mut self, to: Point) {
+        self.moves += 1;
+        self.first = self.first.or(Some(to));
+    }
+
+    fn line_to(&mut self, _: Point) {
+        self.lines += 1;
+    }
+
+    fn quad_to(&mut self, _: Point, _: Point) {
+        self.quads += 1;
+    }
+
+    fn curve_to(&mut self, _: Point, _: Point, _: Point) {
+        self.curves += 1;
+    }
+
+    fn close(&mut self) {
+        self.closes += 1;
+    }
+}
+
+#[test]
+fn outline_commands() {
+    let settings = FontSettings {
+        load_outlines: true,
+        ..FontSettings::default()
+    };
+    let font = Font::from_bytes(FONTS[0], settings).unwrap();
+    let mut h = CommandCounts::default();
+    font.outline_commands(font.lookup_glyph_index('H'), &mut h);
+    assert_eq!((h.moves, h.closes, h.quads, h.curves), (1, 1, 0, 0));
+    assert!(h.lines >= 11);
+    assert!(font.glyph_polygons(font.lookup_glyph_index('H'), 2048.0, 1.0)[0].contains(&h.first.unwrap()));
+
+    // TrueType outlines are quadratic, while CFF outlines are cubic.
+    let mut o = CommandCounts::default();
+    font.outline_commands(font.lookup_glyph_index('o'), &mut o);
+    assert_eq!((o.moves, o.closes, o.curves), (2, 2, 0));
+    assert!(o.quads > 0);
+    let cff = Font::from_bytes(FONTS[5], settings).unwrap();
+    let mut o = CommandCounts::default();
+    cff.outline_commands(cff.lookup_glyph_index('o'), &mut o);
+    assert_eq!((o.moves, o.closes, o.quads), (2, 2, 0));
+    assert!(o.curves > 0);
+
+    // Outlines aren't kept by default.
+    let mut counts = CommandCounts::default();
+    roboto().outline_commands(font.lookup_glyph_index('o'), &mut counts);
+    assert_eq!(counts.moves, 0);
+}
+
+#[test]
+fn metrics_is_empty() {
+    let font = roboto();
+    assert!(font.metrics(' ', 20.0).is_empty());
+    assert!(Metrics::default().is_empty());
+    let metrics = font.metrics('a', 20.0);
+    assert!(!metrics.is_empty());
+    let (_, bitmap) = font.rasterize(' ', 20.0);
+    assert!(bitmap.is_empty());
+}
+
+#[test]
+fn font_bounding_box() {
+    let font = roboto();
+    let (xmin, ymin, xmax, ymax) = font.bounding_box(20.0);
+    assert!(xmin < 0.0 && ymin < 0.0 && xmax > 0.0 && ymax > 0.0);
+    assert_eq!(font.bounding_box(40.0), (xmin * 2.0, ymin * 2.0, xmax * 2.0, ymax * 2.0));
+    for character in "AgjQ@\u{c5}".chars() {
+        let bounds = font.metrics(character, 20.0).bounds;
+        assert!(bounds.xmin >= xmin - 0.01 && bounds.xmin + bounds.width <= xmax + 0.01);
+        assert!(bounds.ymin >= ymin - 0.01 && bounds.ymin + bounds.height <= ymax + 0.01);
+    }
+}
+
+#[test]
+fn kern_run() {
+    for font in FONTS.iter().map(|data| Font::from_bytes(*data, FontSettings::default()).unwrap()) {
+        let indices: Vec<u16> = "AVATAR Wo\u{301}".chars().map(|c| font.lookup_glyph_index(c)).collect();
+        let kerning = font.kern_run(&indices, 40.0);
+        assert_eq!(kerning.len(), indices.len());
+        assert_eq!(kerning[0], 0.0);
+        for (pair, &kern) in indices.windows(2).zip(&kerning[1..]) {
+            if font.metrics_indexed(pair[1], 40.0).advance_width != 0.0 {
+                assert_eq!(kern, font.horizontal_kern_indexed(pair[0], pair[1], 40.0).unwrap_or(0.0));
+            } else {
+                assert_eq!(kern, 0.0);
+            }
+        }
+        assert!(font.kern_run(&[], 40.0).is_empty());
+    }
+}
+
+#[test]
+fn ligature_for() {
+    let font = roboto();
+    let ffi = font.ligature_for(&['f', 'f', 'i']).unwrap();
+    let fi = font.ligature_for(&['f', 'i']).unwrap();
+    assert_ne!(ffi, fi);
+    assert_ne!(fi, font.lookup_glyph_index('f'));
+    assert_eq!(font.ligature_for(&['f']), None);
+    assert_eq!(font.ligature_for(&['f', 'f', 'i', 'i']), None);
+    assert_eq!(font.ligature_for(&['a', 'b']), None);
+    assert_eq!(font.ligature_for(&[]), None);
+
+    let settings = FontSettings {
+        load_substitutions: false,
+        ..FontSettings::default()
+    };
+    assert_eq!(Font::from_bytes(FONTS[0], settings).unwrap().ligature_for(&['f', 'i']), None);
+}
+
+#[test]
+fn from_bytes_subset() {
+    let full = roboto();
+    let font = Font::from_bytes_subset(FONTS[0], FontSettings::default(), &['f', 'i', '\u{c5}']).unwrap();
+    assert_ne!(font.file_hash(), full.file_hash());
+    assert_eq!(font.chars().len(), 3);
+    assert!(font.has_glyph('f') && !font.has_glyph('a'));
+    assert_eq!(font.lookup_glyph_index('a'), 0);
+    assert_eq!(font.rasterize('i', 20.0), full.rasterize('i', 20.0));
+    // Composite glyphs are resolved without listing their components.
+    assert_eq!(font.rasterize('\u{c5}', 20.0), full.rasterize('\u{c5}', 20.0));
+    let unloaded = full.lookup_glyph_index('a');
+    assert!(font.rasterize_indexed(unloaded, 20.0).1.is_empty());
+    // Only the loaded glyphs are stored: .notdef, the three characters and their ligatures.
+    assert_eq!(font.glyph_count(), full.glyph_count());
+    assert_eq!(full.loaded_glyph_count(), full.glyph_count() as usize);
+    assert!(font.loaded_glyph_count() < 10);
+
+    // Ligatures of the subset's glyphs are kept, and their glyphs loaded.
+    let fi = font.ligature_for(&['f', 'i']).unwrap();
+    assert_eq!(font.rasterize_indexed(fi, 20.0), full.rasterize_indexed(fi, 20.0));
+    assert_eq!(font.ligature_for(&['f', 'f', 'i']).is_some(), full.ligature_for(&['f', 'f', 'i']).is_some());
+    let font = Font::from_bytes_subset(FONTS[0], FontSettings::default(), &['f']).unwrap();
+    assert_eq!(font.ligature_for(&['f', 'i']), None);
+}
+
+#[test]
+fn rasterize_huge_px() {
+    let font = roboto();
+    let metrics = font.metrics('A', 100000.0);
+    assert_eq!((metrics.width, metrics.height), (0, 0));
+    assert!(metrics.is_empty());
+    // The advance and bounds are still scaled as usual.
+    let small = font.metrics('A', 100.0);
+    assert!((metrics.advance_width - small.advance_width * 1000.0).abs() < 1.0);
+    assert!((metrics.bounds.width - small.bounds.width * 1000.0).abs() < 1.0);
+
+    let (raster_metrics, bitmap) = font.rasterize('A', 100000.0);
+    assert_eq!(raster_metrics, metrics);
+    assert!(bitmap.is_empty());
+    assert!(font.rasterize_subpixel('A', 100000.0).1.is_empty());
+    assert!(font.rasterize_exact('A', 100000.0).1.is_empty());
+    assert!(font.rasterize_sdf('A', 100000.0, 4.0, 4.0).1.is_empty());
+    assert!(font.rasterizer('A', 100000.0).coverage().is_empty());
+
+    let settings = FontSettings {
+        raster_padding: 2,
+        ..FontSettings::default()
+    };
+    let padded = Font::from_bytes(FONTS[0], settings).unwrap();
+    assert!(padded.rasterize('A', 100000.0).1.is_empty());
+    // Large sizes that still fit are rasterized.
+    let (metrics, bitmap) = font.rasterize('A', 2000.0);
+    assert!(metrics.width > 1000);
+    assert_eq!(bitmap.len(), metrics.width * metrics.height);
+}
+
+#[test]
+fn rasterize_with_shadow() {
+    let font = roboto();
+    let (metrics, glyph) = font.rasterize('A', 20.0);
+    let (w, h) = (metrics.width, metrics.height);
+
+    let (shadowed, bitmap) = font.rasterize_with_shadow('A', 20.0, 2, 1, 128);
+    assert_eq!((shadowed.width, shadowed.height), (w + 2, h + 1));
+    assert_eq!((shadowed.xmin, shadowed.ymin), (metrics.xmin, metrics.ymin - 1));
+    assert_eq!(shadowed.advance_width, metrics.advance_width);
+    assert_eq!(bitmap.len(), shadowed.width * shadowed.height);
+    // Each pixel keeps the higher of the glyph and the shadow moved 2 right and 1 down.
+    for y in 0..h {
+        for x in 0..w {
+            let coverage = glyph[y * w + x];
+            assert!(bitmap[y * (w + 2) + x] >= coverage);
+            assert!(bitmap[(y + 1) * (w + 2) + x + 2] >= (coverage as u32 * 128 / 255) as u8);
+        }
+    }
+    let ink = |bitmap: &[u8]| bitmap.iter().map(|&c| c as u32).sum::<u32>();
+    assert!(ink(&bitmap) > ink(&glyph));
+
+    // Shadows up and to the left move the bitmap's origin instead.
+    let (shadowed, _) = font.rasterize_with_shadow('A', 20.0, -2, -1, 128);
+    assert_eq!((shadowed.xmin, shadowed.ymin), (metrics.xmin - 2, metrics.ymin));
+    assert_eq!(font.rasterize_with_shadow(' ', 20.0, 1, 1, 255).1, Vec::<u8>::new());
+}
+
+#[test]
+fn ink_coverage() {
+    let font = roboto();
+    let (_, bitmap) = font.rasterize('g', 20.0);
+    let ink = font.ink_coverage('g', 20.0);
+    assert_eq!(ink, bitmap.iter().map(|&coverage| coverage as u64).sum::<u64>());
+    assert_eq!(font.ink_coverage_indexed(font.lookup_glyph_index('g'), 20.0), ink);
+    assert_eq!(font.ink_coverage(' ', 20.0), 0);
+    // Ink grows with the area of the glyph, about four times over for twice the size.
+    let ratio = font.ink_coverage('g', 40.0) as f32 / ink as f32;
+    assert!(ratio > 3.5 && ratio < 4.5, "{}", ratio);
+}
+
+#[test]
+fn sdf_horizontal_edges() {
+    let settings = FontSettings {
+        load_outlines: true,
+        ..FontSettings::default()
+    };
+    let outlined = Font::from_bytes(FONTS[0], settings).unwrap();
+    let (metrics, sdf) = outlined.rasterize_sdf('l', 32.0, 4.0, 4.0);
+    let (plain_metrics, plain) = roboto().rasterize_sdf('l', 32.0, 4.0, 4.0);
+    assert_eq!(metrics, plain_metrics);
+    // Just above the middle of the flat top of the stem, the top edge is the nearest one.
+    let index = metrics.width * 3 + metrics.width / 2;
+    assert!(sdf[index] > 64 && sdf[index] < 128, "{}", sdf[index]);
+    // Without the outline, only the far off sides of the stem are measured.
+    assert!(plain[index] < sdf[index]);
+    let rasterizer = outlined.rasterizer('l', 32.0);
+    assert!(rasterizer.edges().len() > roboto().rasterizer('l', 32.0).edges().len());
+}
+
+#[test]
+fn rasterize_config_oriented() {
+    let font = roboto();
+    let config = GlyphRasterConfig {
+        glyph_index: font.lookup_glyph_index('g'),
+        px: 24.0,
+        font_hash: font.file_hash(),
+    };
+    let (metrics, bitmap) = font.rasterize_config(config);
+    let (down_metrics, down) = font.rasterize_config_oriented(config, CoordinateSystem::PositiveYDown);
+    assert_eq!(down_metrics, metrics);
+    assert_eq!(down, bitmap);
+    let (up_metrics, up) = font.rasterize_config_oriented(config, CoordinateSystem::PositiveYUp);
+    assert_eq!(up_metrics, metrics);
+    assert_eq!(up.len(), bitmap.len());
+    // Each row of the flipped bitmap is the row at the mirrored height.
+    let w = metrics.width;
+    for y in 0..metrics.height {
+        assert_eq!(up[y * w..][..w], bitmap[(metrics.height - 1 - y) * w..][..w]);
+    }
+    // The descender of 'g' is at the start of the bottom-up rows.
+    assert!(up[..w].iter().any(|&c| c > 0));
+    assert_ne!(up, bitmap);
+    // An empty glyph stays empty.
+    let space = GlyphRasterConfig {
+        glyph_index: font.lookup_glyph_index(' '),
+        ..config
+    };
+    assert!(font.rasterize_config_oriented(space, CoordinateSystem::PositiveYUp).1.is_empty());
+}
+
+#[test]
+fn break_word() {
+    let font = roboto();
+    let word = "abcdéfghijklmn";
+    let breaks = font.break_word(word, 20.0, 40.0);
+    assert!(!breaks.is_empty());
+    // Each piece fits, and wouldn't fit with the next character added.
+    let advance = |c: char| font.metrics(c, 20.0).advance_width.ceil();
+    let mut starts = vec![0];
+    starts.extend(breaks.iter().copied());
+    for (index, &start) in starts.iter().enumerate() {
+        assert!(word.is_char_boundary(start));
+        let end = starts.get(index + 1).copied().unwrap_or(word.len());
+        let width: f32 = word[start..end].chars().map(advance).sum();
+        assert!(width <= 40.0, "{} is {} wide", &word[start..end], width);
+        if let Some(next) = word[end..].chars().next() {
+            assert!(width + advance(next) > 40.0);
+        }
+    }
+    // A character wider than the width still gets a piece of its own.
+    assert_eq!(font.break_word("ab", 20.0, 1.0), vec![1]);
+    assert!(font.break_word("", 20.0, 40.0).is_empty());
+    assert!(font.break_word("abc", 20.0, 1000.0).is_empty());
+}
+
+#[test]
+fn glyph_is_present() {
+    let font = roboto();
+    assert!(font.glyph_is_present('a'));
+    // Spaces map to a real, blank glyph.
+    assert!(font.has_glyph(' '));
+    assert!(font.glyph_is_present(' '));
+    // Characters that are never drawn don't need a glyph.
+    for c in ['\u{7}', '\u{AD}', '\u{2060}', '\u{FE0F}'] {
+        if !font.has_glyph(c) {
+            assert!(font.glyph_is_present(c), "{:?}", c);
+        }
+    }
+    assert!(!font.has_glyph('\u{7}'));
+    // Drawn characters the font doesn't have aren't present.
+    for c in ['\u{4E2D}', '\u{985}', '\u{E000}'] {
+        assert_eq!(font.lookup_glyph_index(c), 0);
+        assert!(!font.glyph_is_present(c), "{:?}", c);
+    }
+}
+
+/// Builds a font without outlines, whose glyphs are PNG images in a single `sbix` strike at 100
+/// pixels per em. Glyph 1 is 'A', 2 is 'B', and 3 is the space, with the given hmtx advances. Each
+/// image is 80 pixels wide, and the images aren't valid PNGs past their size.
+fn sbix_font(advances: [u16; 4]) -> Vec<u8> {
+    fn be16(out: &mut Vec<u8>, values: &[u16]) {
+        values.iter().for_each(|value| out.extend_from_slice(&value.to_be_bytes()));
+    }
+    fn be32(out: &mut Vec<u8>, values: &[u32]) {
+        values.iter().for_each(|value| out.extend_from_slice(&value.to_be_bytes()));
+    }
+    let mut cmap = Vec::new();
+    be16(&mut cmap, &[0, 1, 3, 10]);
+    be32(&mut cmap, &[12]);
+    be16(&mut cmap, &[12, 0]);
+    be32(&mut cmap, &[40, 0, 2, 0x20, 0x20, 3, 0x41, 0x42, 1]);
+    let mut head = Vec::new();
+    be32(&mut head, &[0x10000, 0x10000, 0, 0x5F0F3CF5]);
+    be16(&mut head, &[0, 1000]);
+    be32(&mut head, &[0, 0, 0, 0]);
+    be16(&mut head, &[0, 0, 1000, 1000, 0, 8, 2, 0, 0]);
+    let mut hhea = Vec::new();
+    be32(&mut hhea, &[0x10000]);
+    be16(&mut hhea, &[800, (-200i16) as u16, 0, 1000, 0, 0, 1000, 1, 0, 0, 0, 0, 0, 0, 0, 4]);
+    let mut hmtx = Vec::new();
+    advances.iter().for_each(|&advance| be16(&mut hmtx, &[advance, 0]));
+    let mut maxp = Vec::new();
+    be32(&mut maxp, &[0x5000]);
+    be16(&mut maxp, &[4]);
+    let mut sbix = Vec::new();
+    be16(&mut sbix, &[1, 1]);
+    be32(&mut sbix, &[1, 12]);
+    be16(&mut sbix, &[100, 72]);
+    be32(&mut sbix, &[24, 24, 56, 88, 120]);
+    for _ in 1..4 {
+        be16(&mut sbix, &[0, 0]);
+        sbix.extend_from_slice(b"png \x89PNG\r\n\x1a\n\0\0\0\x0dIHDR");
+        be32(&mut sbix, &[80, 90]);
+    }
+    let tables: [(&[u8; 4], Vec<u8>); 6] = [
+        (b"cmap", cmap),
+        (b"head", head),
+        (b"hhea", hhea),
+        (b"hmtx", hmtx),
+        (b"maxp", maxp),
+        (b"sbix", sbix),
+    ];
+    let mut font = Vec::new();
+    be32(&mut font, &[0x10000]);
+    be16(&mut font, &[tables.len() as u16, 64, 2, 32]);
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, data) in &tables {
+        font.extend_from_slice(&tag[..]);
+        be32(&mut font, &[0, offset as u32, data.len() as u32]);
+        offset += (data.len() + 3) & !3;
+    }
+    for (_, data) in &tables {
+        font.extend_from_slice(data);
+        font.resize((font.len() + 3) & !3, 0);
+    }
+    font
+}
+
+#[test]
+fn sbix_advances() {
+    // Glyphs with an hmtx advance keep it, including the space, which has an image too.
+    let font = Font::from_bytes(sbix_font([0, 600, 500, 250]), FontSettings::default()).unwrap();
+    assert_eq!(font.lookup_glyph_index('A'), 1);
+    assert_eq!(font.metrics('A', 100.0).advance_width, 60.0);
+    assert_eq!(font.metrics('B', 50.0).advance_width, 25.0);
+    assert_eq!(font.metrics(' ', 100.0).advance_width, 25.0);
+    // Without one, the advance is the width of the image in the strike, scaled to the px.
+    let font = Font::from_bytes(sbix_font([0, 0, 500, 250]), FontSettings::default()).unwrap();
+    assert_eq!(font.metrics('A', 100.0).advance_width, 80.0);
+    assert_eq!(font.metrics('A', 50.0).advance_width, 40.0);
+    assert_eq!(font.metrics('B', 100.0).advance_width, 50.0);
+}
+
+#[test]
+fn font_line_height() {
+    let font = roboto();
+    let metrics = font.horizontal_line_metrics(20.0).unwrap();
+    assert_eq!(font.line_height(20.0), metrics.ascent - metrics.descent + metrics.line_gap);
+    assert_eq!(font.line_height(20.0), metrics.new_line_size);
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("a\nb", ()));
+    layout.finalize();
+    let lines = layout.lines().unwrap();
+    // The layout snaps the line height to whole pixels.
+    assert_eq!(lines[0].max_new_line_size, font.line_height(20.0).ceil());
+    assert_eq!(lines[1].baseline_y - lines[0].baseline_y, lines[0].max_new_line_size);
+}
+
+#[test]
+fn debug_missing() {
+    let full = roboto();
+    let settings = FontSettings {
+        debug_missing: true,
+        ..FontSettings::default()
+    };
+    let font = Font::from_bytes(FONTS[0], settings).unwrap();
+    assert_ne!(font.file_hash(), full.file_hash());
+    // Missing characters are drawn with the crossed box instead of the font's notdef.
+    assert_eq!(font.lookup_glyph_index('\u{E000}'), 0);
+    let (metrics, bitmap) = font.rasterize('\u{E000}', 20.0);
+    let (notdef_metrics, notdef) = full.rasterize('\u{E000}', 20.0);
+    assert!(!bitmap.is_empty());
+    assert_ne!(bitmap, notdef);
+    assert_eq!(metrics.advance_width, notdef_metrics.advance_width);
+    // Characters the font has are unchanged.
+    assert_eq!(font.rasterize('a', 20.0), full.rasterize('a', 20.0));
+
+    // Subsets draw the characters they didn't load with the crossed box too.
+    let subset = Font::from_bytes_subset(FONTS[0], settings, &['a']).unwrap();
+    let plain_subset = Font::from_bytes_subset(FONTS[0], FontSettings::default(), &['a']).unwrap();
+    assert_ne!(subset.file_hash(), plain_subset.file_hash());
+    assert_eq!(subset.rasterize('b', 20.0), (metrics, bitmap));
+    assert_eq!(subset.rasterize('a', 20.0), full.rasterize('a', 20.0));
+}
+
+#[test]
+fn outline_kind_detection() {
+    let truetype = Font::from_bytes(FONTS[0], FontSettings::default()).unwrap();
+    assert_eq!(truetype.outline_kind(), OutlineKind::TrueType);
+    let cff = Font::from_bytes(FONTS[5], FontSettings::default()).unwrap();
+    assert_eq!(cff.outline_kind(), OutlineKind::Cff);
+}
+
+#[test]
+fn cff_outlines_rasterize() {
+    // Exo 2 and Great Vibes are OpenType fonts with CFF outlines.
+    for data in [FONTS[5], FONTS[6]] {
+        let font = Font::from_bytes(data, FontSettings::default()).unwrap();
+        assert_eq!(font.outline_kind(), OutlineKind::Cff);
+        for character in ['a', 'o', 'S'] {
+            let (metrics, bitmap) = font.rasterize(character, 32.0);
+            assert!(metrics.width > 0 && metrics.height > 0);
+            assert!(bitmap.iter().any(|&coverage| coverage == 255));
+        }
+    }
+}
+
+#[test]
+fn px_for_cap_height() {
+    let font = roboto();
+    let px = font.px_for_cap_height(16.0);
+    assert!((font.cap_height(px).unwrap() - 16.0).abs() < 0.001);
+    let metrics = font.metrics('H', px);
+    assert!((metrics.bounds.height - 16.0).abs() < 0.5);
+    let px = font.px_for_x_height(10.0);
+    assert!((font.metrics('x', px).bounds.height - 10.0).abs() < 0.5);
+}
+
+#[test]
+fn rasterizer_matches_rasterize() {
+    let font = roboto();
+    let rasterizer = font.rasterizer('g', 32.0);
+    let (metrics, bitmap) = font.rasterize('g', 32.0);
+    assert_eq!(rasterizer.metrics(), metrics);
+    assert_eq!(rasterizer.coverage(), bitmap);
+    let winding = rasterizer.winding();
+    assert_eq!(winding.len(), bitmap.len());
+    for (&winding, &coverage) in winding.iter().zip(bitmap.iter()) {
+        assert_eq!((winding.abs() * 255.9).max(0.0).min(255.0) as u8, coverage);
+    }
+    // Partially covered pixels are crossed by an edge, so they're close to one. Flat edges are only
+    // measured with the outline loaded.
+    let settings = FontSettings {
+        load_outlines: true,
+        ..FontSettings::default()
+    };
+    let rasterizer = Font::from_bytes(FONTS[0], settings).unwrap().rasterizer('g', 32.0);
+    let distances = rasterizer.edge_distances();
+    for (&distance, &coverage) in distances.iter().zip(bitmap.iter()) {
+        if coverage > 0 && coverage < 255 {
+            assert!(distance < 1.0, "{} away at coverage {}", distance, coverage);
+        }
+    }
+
+    // Stem darkening applies to the coverage too.
+    let settings = FontSettings {
+        stem_darkening: true,
+        ..FontSettings::default()
+    };
+    let darkened = Font::from_bytes(FONTS[0], settings).unwrap();
+    let (_, bitmap) = darkened.rasterize('g', 12.0);
+    assert_ne!(bitmap, font.rasterize('g', 12.0).1);
+    assert_eq!(darkened.rasterizer('g', 12.0).coverage(), bitmap);
+}
+
+#[test]
+fn features_list() {
+    // There's no Arabic font among the test fonts, so Roboto's Latin features are checked.
+    let font = roboto();
+    let features = font.features();
+    let tags: Vec<&[u8; 4]> = features.iter().map(|feature| &feature.tag).collect();
+    for tag in [b"liga", b"kern", b"smcp", b"c2sc", b"frac"] {
+        assert!(tags.contains(&tag));
+    }
+    let mut sorted = tags.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(tags, sorted);
+    let required: Vec<&[u8; 4]> =
+        features.iter().filter(|feature| feature.required).map(|feature| &feature.tag).collect();
+    assert_eq!(required, [b"ccmp", b"locl"]);
+    // Fonts without GSUB or GPOS list nothing.
+    let font = Font::from_bytes(FONTS[6], FontSettings::default()).unwrap();
+    assert!(font.features().is_empty());
+}
@@ -0,0 +1,1805 @@
+use fontdue::layout::{
+    Anchor, Block, CoordinateSystem, DecorationKind, Direction, EllipsisPosition, FeatureSet,
+    GlyphRasterConfig, HorizontalAlign, Layout, LayoutSettings, ParagraphSettings, Renderable, RoundMode,
+    Span, UserDataMerge, VerticalAlign, WrapStyle,
+};
+use fontdue::{Font, FontSettings};
+
+use crate::modules::FONTS;
+
+fn roboto() -> Font {
+    Font::from_bytes(FONTS[0], FontSettings::default()).unwrap()
+}
+
+#[test]
+fn layout_mixed_size_baseline() {
+    let font = roboto();
+    for coordinate_system in [CoordinateSystem::PositiveYUp, CoordinateSystem::PositiveYDown] {
+        // The user data is the whole pixel rise expected for the span.
+        let mut layout = Layout::new(&font, 35.0, coordinate_system);
+        layout.append(Span::text("Hxgy ", 0));
+        layout.append(Span::text("Hxgy ", 0).with_px(12.0));
+        layout.append(Span::text("Hxgy", 3).with_px(12.0).with_rise(3.7));
+        layout.finalize();
+
+        let lines = layout.lines().unwrap();
+        assert_eq!(lines.len(), 1);
+        let baseline = lines[0].baseline_y;
+        for glyph in layout.glyphs() {
+            let config = glyph.key.unwrap();
+            let metrics = font.metrics_indexed(config.glyph_index, config.px);
+            // Distance from the baseline to the bottom of the glyph's bitmap, positive up.
+            let bottom = match coordinate_system {
+                CoordinateSystem::PositiveYUp => glyph.y - baseline,
+                CoordinateSystem::PositiveYDown => baseline - (glyph.y + glyph.height as f32),
+            };
+            assert_eq!(
+                bottom,
+                (metrics.ymin + glyph.user_data) as f32,
+                "glyph [{}] at {}px is off the baseline",
+                glyph.parent,
+                config.px
+            );
+        }
+    }
+}
+
+#[test]
+fn layout_word_split_across_spans() {
+    let font = roboto();
+    let px = 20.0;
+    let width = |text: &str| text.chars().map(|c| font.metrics(c, px).advance_width.ceil()).sum::<f32>();
+    // Wide enough for "Hello wor", but not for "Hello world".
+    let max_width = width("Hello wor") + 1.0;
+    let splits: [(&str, &str, f32); 3] =
+        [("Hello wor", "ld", px), ("Hello w", "orld", px), ("Hello ", "world", 30.0)];
+    for (first, second, second_px) in splits {
+        let mut layout = Layout::new(&font, px, CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings {
+            max_width: Some(max_width),
+            ..LayoutSettings::default()
+        });
+        layout.append(Span::text(first, ()));
+        layout.append(Span::text(second, ()).with_px(second_px));
+        layout.finalize();
+
+        let lines = layout.lines().unwrap();
+        assert_eq!(lines.len(), 2, "{:?} + {:?}", first, second);
+        let glyphs = layout.glyphs();
+        let second_line: String =
+            glyphs[lines[1].glyph_start..=lines[1].glyph_end].iter().map(|g| g.parent).collect();
+        assert_eq!(second_line, "world", "{:?} + {:?}", first, second);
+    }
+}
+
+#[test]
+fn layout_invalid_px() {
+    let font = roboto();
+    for px in [f32::NAN, f32::INFINITY, -12.0, 0.0] {
+        let mut layout = Layout::new(&font, px, CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings::default());
+        layout.append(Span::text("Hello", ()));
+        layout.append(Span::text("world", ()).with_px(px));
+        layout.finalize();
+        for glyph in layout.glyphs() {
+            assert!(glyph.x.is_finite() && glyph.y.is_finite());
+            assert_eq!(glyph.key.unwrap().px, 0.0);
+        }
+        assert!(layout.height().is_finite());
+        let (metrics, bitmap) = font.rasterize('a', px);
+        assert!(bitmap.is_empty());
+        assert_eq!(metrics.width * metrics.height, 0);
+    }
+}
+
+#[test]
+fn layout_decorations_follow_alignment() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings {
+        max_width: Some(100.0),
+        horizontal_align: HorizontalAlign::Center,
+        ..LayoutSettings::default()
+    });
+    layout.append(Span::text("Plain ", ()));
+    layout.append(Span::text("underlined text", ()).with_underline(true));
+    layout.finalize();
+    let lines = layout.lines().unwrap();
+    assert_eq!(lines.len(), 3);
+    let decorations = layout.decorations();
+    // The underline is split at the wrap, with one rect per line it's on.
+    assert_eq!(decorations.len(), 2);
+    for (rect, line) in decorations.iter().zip(&lines[1..]) {
+        assert_eq!(rect.kind, DecorationKind::Underline);
+        assert!(rect.y > line.baseline_y);
+        assert!(rect.height >= 1.0);
+        let glyphs = &layout.glyphs()[line.glyph_start..=line.glyph_end];
+        let last = glyphs.last().unwrap();
+        assert!(rect.x + rect.width >= last.x + last.width as f32);
+    }
+    // The last line is entirely underlined, so its rect starts where its first glyph does.
+    let first = &layout.glyphs()[lines[2].glyph_start];
+    assert!((decorations[1].x - first.x).abs() <= 2.0);
+}
+
+#[test]
+fn layout_show_invisibles() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings {
+        show_invisibles: true,
+        ..LayoutSettings::default()
+    });
+    layout.append(Span::text("ab \ncd", ()));
+    layout.finalize();
+    let parents: Vec<char> = layout.glyphs().iter().map(|glyph| glyph.parent).collect();
+    assert_eq!(parents, ['a', 'b', ' ', '\n', 'c', 'd']);
+    for glyph in &layout.glyphs()[2..4] {
+        assert!(glyph.char_data.is_whitespace());
+        assert!(glyph.char_data.rasterize());
+        assert!(glyph.width > 0 && glyph.height > 0);
+    }
+    let pilcrow = font.lookup_glyph_index('¶');
+    assert_eq!(layout.glyphs()[3].key.unwrap().glyph_index, pilcrow);
+}
+
+#[test]
+fn layout_relayout_matches_fresh_layout() {
+    let font = roboto();
+    let narrow = LayoutSettings {
+        max_width: Some(60.0),
+        record_spans: true,
+        ..LayoutSettings::default()
+    };
+    let wide = LayoutSettings {
+        max_width: Some(300.0),
+        horizontal_align: HorizontalAlign::Right,
+        ..LayoutSettings::default()
+    };
+    let spans = [Span::text("Some text that ", 1u8), Span::text("wraps", 2u8).with_px(30.0)];
+
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&narrow);
+    for span in &spans {
+        layout.append(span.clone());
+    }
+    layout.finalize();
+    assert!(layout.lines().unwrap().len() > 1);
+    layout.relayout(&wide);
+    layout.finalize();
+
+    let mut fresh = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    fresh.reset(&wide);
+    for span in &spans {
+        fresh.append(span.clone());
+    }
+    fresh.finalize();
+
+    assert_eq!(layout.glyphs().len(), fresh.glyphs().len());
+    for (a, b) in layout.glyphs().iter().zip(fresh.glyphs()) {
+        assert_eq!((a.parent, a.x, a.y, a.user_data), (b.parent, b.x, b.y, b.user_data));
+    }
+
+    // The wide settings don't record spans, so there's nothing left to lay out again.
+    layout.relayout(&narrow);
+    layout.finalize();
+    assert!(layout.glyphs().is_empty());
+    // Spans appended without recording aren't laid out again either.
+    fresh.relayout(&narrow);
+    fresh.finalize();
+    assert!(fresh.glyphs().is_empty());
+}
+
+#[test]
+fn layout_rtl_mirrors_brackets() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("(abc)", ()).with_direction(Direction::RightToLeft));
+    layout.append(Span::text("(abc)", ()));
+    layout.finalize();
+    let glyphs = layout.glyphs();
+    let open = font.lookup_glyph_index('(');
+    let close = font.lookup_glyph_index(')');
+    assert_eq!(glyphs[0].parent, '(');
+    assert_eq!(glyphs[0].key.unwrap().glyph_index, close);
+    assert_eq!(glyphs[4].key.unwrap().glyph_index, open);
+    assert_eq!(glyphs[1].key.unwrap().glyph_index, font.lookup_glyph_index('a'));
+    assert_eq!(glyphs[5].key.unwrap().glyph_index, open);
+    assert_eq!(glyphs[9].key.unwrap().glyph_index, close);
+}
+
+#[test]
+fn layout_cursor_matches_next_glyph() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings {
+        max_width: Some(80.0),
+        ..LayoutSettings::default()
+    });
+    let text = "Typewriter text that wraps";
+    let mut cursors = Vec::new();
+    for (i, _) in text.char_indices() {
+        cursors.push(layout.cursor());
+        layout.append(Span::text(&text[i..i + 1], ()));
+    }
+    layout.finalize();
+    let lines = layout.lines().unwrap();
+    assert!(lines.len() > 1);
+    let last_line = lines.last().unwrap();
+    // The cursor before the last character sits on the last line's baseline, at its pen position.
+    let (x, y) = *cursors.last().unwrap();
+    let glyph = layout.glyphs().last().unwrap();
+    assert_eq!(y, last_line.baseline_y);
+    let metrics = font.metrics(glyph.parent, 20.0);
+    assert_eq!(glyph.x, x + metrics.bounds.xmin.floor());
+}
+
+#[test]
+fn layout_collapse_whitespace() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings {
+        collapse_whitespace: true,
+        ..LayoutSettings::default()
+    });
+    layout.append(Span::text("a  \t b ", ()));
+    layout.append(Span::text(" c \n  d", ()));
+    layout.finalize();
+    let parents: String = layout.glyphs().iter().map(|glyph| glyph.parent).collect();
+    assert_eq!(parents, "a b c d");
+    assert_eq!(layout.omitted(), [2..5, 7..8, 10..13]);
+    // The hard break inside the collapsed run still starts a new line.
+    assert_eq!(layout.lines().unwrap().len(), 2);
+}
+
+#[test]
+fn layout_small_caps() {
+    // Roboto substitutes its own small capitals through smcp.
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("Ab", ()).with_small_caps(true));
+    layout.finalize();
+    let glyphs = layout.glyphs();
+    let capital = glyphs[0].key.unwrap();
+    let small = glyphs[1].key.unwrap();
+    assert_eq!(capital.glyph_index, font.lookup_glyph_index('A'));
+    assert_ne!(small.glyph_index, font.lookup_glyph_index('b'));
+    assert_ne!(small.glyph_index, font.lookup_glyph_index('B'));
+    assert_eq!(small.px, 20.0);
+
+    // Comfortaa has no small capitals, so lowercase letters become smaller capitals.
+    let font = Font::from_bytes(FONTS[2], FontSettings::default()).unwrap();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("Ab", ()).with_small_caps(true));
+    layout.finalize();
+    let glyphs = layout.glyphs();
+    let capital = glyphs[0].key.unwrap();
+    let small = glyphs[1].key.unwrap();
+    assert_eq!(capital.px, 20.0);
+    assert_eq!(small.glyph_index, font.lookup_glyph_index('B'));
+    assert_eq!(small.px, 15.0);
+}
+
+#[test]
+fn layout_rasterize_run() {
+    let font = roboto();
+    for coordinate_system in [CoordinateSystem::PositiveYUp, CoordinateSystem::PositiveYDown] {
+        let mut layout = Layout::new(&font, 24.0, coordinate_system);
+        layout.append(Span::text("the", ()));
+        layout.finalize();
+        let (metrics, bitmap) = layout.rasterize_run(0..3);
+        assert_eq!(bitmap.len(), metrics.width * metrics.height);
+        let glyphs = layout.glyphs();
+        assert_eq!(metrics.advance_width, glyphs.iter().map(|glyph| glyph.advance).sum::<f32>());
+        for glyph in glyphs {
+            let (glyph_metrics, coverage) = font.rasterize_config(glyph.key.unwrap());
+            let col = (glyph.x as i32 - metrics.xmin) as usize;
+            let row = match coordinate_system {
+                CoordinateSystem::PositiveYDown => glyph.y as i32 - metrics.ymin,
+                CoordinateSystem::PositiveYUp => {
+                    metrics.ymin + metrics.height as i32 - (glyph.y as i32 + glyph.height as i32)
+                }
+            } as usize;
+            for (y, source) in coverage.chunks(glyph_metrics.width).enumerate() {
+                for (x, &value) in source.iter().enumerate() {
+                    assert!(bitmap[(row + y) * metrics.width + col + x] >= value);
+                }
+            }
+        }
+
+        // A run of one glyph is that glyph's bitmap.
+        let (single, bitmap) = layout.rasterize_run(1..2);
+        let (glyph_metrics, coverage) = font.rasterize_config(layout.glyphs()[1].key.unwrap());
+        assert_eq!((single.width, single.height), (glyph_metrics.width, glyph_metrics.height));
+        assert_eq!(bitmap, coverage);
+
+        // Ranges are clamped to the glyphs that exist.
+        assert_eq!(layout.rasterize_run(0..10000), layout.rasterize_run(0..3));
+        assert!(layout.rasterize_run(5..10).1.is_empty());
+    }
+}
+
+#[test]
+fn layout_mark_to_base() {
+    // Comfortaa and Exo 2 both position combining marks through GPOS.
+    for index in [2, 5] {
+        let font = Font::from_bytes(FONTS[index], FontSettings::default()).unwrap();
+        let mut layout = Layout::new(&font, 40.0, CoordinateSystem::PositiveYDown);
+        layout.append(Span::text("a\u{302}", ()));
+        layout.finalize();
+        let baseline = layout.lines().unwrap()[0].baseline_y as i32;
+        let base = &layout.glyphs()[0];
+        let origin = base.x as i32 - font.metrics('a', 40.0).xmin;
+
+        // The base with its mark covers the same pixels as the precomposed glyph.
+        let (composed, _) = layout.rasterize_run(0..2);
+        let precomposed = font.metrics('\u{e2}', 40.0);
+        assert_eq!(composed.xmin - origin, precomposed.xmin);
+        assert_eq!(baseline - composed.ymin, precomposed.ymin + precomposed.height as i32);
+        assert_eq!((composed.width, composed.height), (precomposed.width, precomposed.height));
+    }
+}
+
+#[test]
+fn layout_feature_set() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("fit", ()));
+    layout.finalize();
+    let parents: String = layout.glyphs().iter().map(|glyph| glyph.parent).collect();
+    assert_eq!(parents, "ft");
+    assert_ne!(layout.glyphs()[0].key.unwrap().glyph_index, font.lookup_glyph_index('f'));
+
+    let mut features = FeatureSet::default();
+    assert_eq!(features.tags(), [b"kern", b"liga", b"mark"]);
+    features.remove(FeatureSet::from_tag(b"liga").unwrap());
+    assert!(!features.contains(FeatureSet::LIGA));
+    layout.reset(&LayoutSettings {
+        features,
+        ..LayoutSettings::default()
+    });
+    layout.append(Span::text("fit", ()));
+    layout.finalize();
+    let parents: String = layout.glyphs().iter().map(|glyph| glyph.parent).collect();
+    assert_eq!(parents, "fit");
+    assert_eq!(FeatureSet::from_tag(b"zzzz"), None);
+}
+
+#[test]
+fn layout_fractions() {
+    let settings = LayoutSettings {
+        features: FeatureSet::default() | FeatureSet::FRAC,
+        ..LayoutSettings::default()
+    };
+
+    // Roboto has numerator and denominator forms, and a fraction slash.
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&settings);
+    layout.append(Span::text("1/2 cup", ()));
+    layout.finalize();
+    let glyphs = layout.glyphs();
+    let indices: Vec<u16> = glyphs.iter().map(|glyph| glyph.key.unwrap().glyph_index).collect();
+    assert_ne!(indices[0], font.lookup_glyph_index('1'));
+    assert_eq!(indices[1], font.lookup_glyph_index('\u{2044}'));
+    assert_ne!(indices[2], font.lookup_glyph_index('2'));
+    assert_eq!(indices[4], font.lookup_glyph_index('c'));
+    assert!(glyphs[..3].iter().all(|glyph| glyph.key.unwrap().px == 20.0));
+
+    // Roboto Mono doesn't, so the digits are shrunk and the numerator is raised.
+    let font = Font::from_bytes(FONTS[1], FontSettings::default()).unwrap();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYUp);
+    layout.reset(&settings);
+    layout.append(Span::text("1/2 cup 12/", ()));
+    layout.finalize();
+    let glyphs = layout.glyphs();
+    let px: Vec<f32> = glyphs.iter().map(|glyph| glyph.key.unwrap().px).collect();
+    assert_eq!(px[..3], [12.0, 20.0, 12.0]);
+    // Digits without a denominator aren't a fraction.
+    assert!(px[8..].iter().all(|&px| px == 20.0));
+    let bottom = |glyph: &fontdue::layout::GlyphPosition| {
+        let config = glyph.key.unwrap();
+        glyph.y - font.metrics_indexed(config.glyph_index, config.px).ymin as f32
+    };
+    assert_eq!(bottom(&glyphs[0]) - bottom(&glyphs[2]), 8.0);
+}
+
+#[test]
+fn layout_settings_builder() {
+    let built = LayoutSettings::builder().max_width(200.0).horizontal_align(HorizontalAlign::Center).build();
+    let literal = LayoutSettings {
+        max_width: Some(200.0),
+        horizontal_align: HorizontalAlign::Center,
+        ..LayoutSettings::default()
+    };
+    assert!(built == literal);
+    assert!(LayoutSettings::builder().build() == LayoutSettings::default());
+}
+
+#[test]
+fn layout_span_color() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("ab", ()).with_color([255, 0, 0, 255]));
+    layout.append(Span::text("c", ()));
+    layout.finalize();
+    let colors: Vec<Option<[u8; 4]>> = layout.glyphs().iter().map(|glyph| glyph.color).collect();
+    assert_eq!(colors, [Some([255, 0, 0, 255]), Some([255, 0, 0, 255]), None]);
+}
+
+#[test]
+fn layout_is_empty_and_overflows() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().max_width(100.0).max_height(30.0).build());
+    assert!(layout.is_empty());
+    layout.append(Span::text("", ()));
+    assert!(layout.is_empty());
+    assert!(!layout.overflows());
+    layout.append(Span::text("Short", ()));
+    assert!(!layout.is_empty());
+    assert!(!layout.overflows());
+    // A second line exceeds the max_height.
+    layout.append(Span::text(" and wrapping", ()));
+    assert!(layout.overflows());
+
+    // A glyph wider than the max_width overflows it.
+    layout.reset(&LayoutSettings::builder().max_width(5.0).build());
+    layout.append(Span::text("W", ()));
+    assert!(layout.overflows());
+    layout.clear();
+    assert!(layout.is_empty());
+}
+
+#[test]
+fn layout_append_with_advance_override() {
+    let font = roboto();
+    let mut plain = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    plain.append(Span::text("AVA", ()));
+    plain.finalize();
+    // Tighten only the advance after the first A.
+    let a = font.lookup_glyph_index('A');
+    let mut seen = 0;
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append_with(Span::text("AVA", ()), |glyph_index, advance| {
+        seen += 1;
+        if glyph_index == a && seen == 1 {
+            advance - 3.0
+        } else {
+            advance
+        }
+    });
+    layout.finalize();
+    let (plain, glyphs) = (plain.glyphs(), layout.glyphs());
+    assert_eq!(glyphs[0].x, plain[0].x);
+    assert_eq!(glyphs[0].advance, plain[0].advance - 3.0);
+    assert_eq!(glyphs[1].x, plain[1].x - 3.0);
+    assert_eq!(glyphs[2].x, plain[2].x - 3.0);
+}
+
+#[test]
+fn layout_lines_started_by_hard_break() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().max_width(100.0).build());
+    layout.append(Span::text("A line long enough to wrap\nNext", ()));
+    layout.finalize();
+    let hard: Vec<bool> = layout.lines().unwrap().iter().map(|line| line.started_by_hard_break).collect();
+    assert!(hard.len() > 2);
+    assert!(!hard[0]);
+    assert!(hard[1..hard.len() - 1].iter().all(|&hard| !hard));
+    assert!(hard[hard.len() - 1]);
+}
+
+#[test]
+fn layout_glyph_for_char_index() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().collapse_whitespace(true).build());
+    layout.append(Span::text("fi a  b", ()));
+    layout.append(Span::text("\u{e9}c", ()));
+    assert_eq!(layout.glyph_for_char_index(0), None);
+    layout.finalize();
+    let glyphs: Vec<Option<usize>> = (0..10).map(|i| layout.glyph_for_char_index(i)).collect();
+    // The "fi" ligature is one glyph, and the second space is collapsed.
+    assert_eq!(glyphs, [Some(0), Some(0), Some(1), Some(2), Some(3), None, Some(4), Some(5), Some(6), None]);
+    assert_eq!(layout.glyphs()[5].parent, '\u{e9}');
+}
+
+#[test]
+fn layout_rounding_modes() {
+    let font = roboto();
+    let layout_with = |rounding| {
+        let mut layout = Layout::new(&font, 13.0, CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings::builder().rounding(rounding).build());
+        layout.append(Span::text("Hamburgefonstiv", ()));
+        layout.finalize();
+        layout.glyphs().iter().map(|glyph| (glyph.x, glyph.advance)).collect::<Vec<_>>()
+    };
+    let floor = layout_with(RoundMode::Floor);
+    let round = layout_with(RoundMode::Round);
+    let none = layout_with(RoundMode::None);
+    let advances: f32 = none.iter().map(|&(_, advance)| advance).sum();
+    // Without snapping, advances add up to the font's advances.
+    let expected: f32 = "Hamburgefonstiv".chars().map(|c| font.metrics(c, 13.0).advance_width).sum();
+    assert!((advances - expected).abs() < 0.01);
+    assert!(none.iter().any(|&(x, _)| x.fract() != 0.0));
+    for glyphs in [&floor, &round] {
+        assert!(glyphs.iter().all(|&(x, advance)| x.fract() == 0.0 && advance.fract() == 0.0));
+    }
+    // Rounding up every advance drifts further right than rounding to the nearest pixel.
+    let last_x = |glyphs: &Vec<(f32, f32)>| glyphs.last().unwrap().0;
+    assert!(last_x(&floor) > last_x(&round));
+    assert!((last_x(&round) - last_x(&none)).abs() < 4.0);
+}
+
+#[test]
+fn layout_append_layout() {
+    let font = roboto();
+    let mut header = Layout::new(&font, 30.0, CoordinateSystem::PositiveYDown);
+    header.append(Span::text("Header", ()));
+    header.finalize();
+    let mut body = Layout::new(&font, 15.0, CoordinateSystem::PositiveYDown);
+    body.reset(&LayoutSettings::builder().max_width(60.0).build());
+    body.append(Span::text("Body text that wraps", ()).with_underline(true));
+    body.finalize();
+
+    let offset = header.height();
+    let header_len = header.glyphs().len();
+    header.append_layout(&body, offset);
+    let (merged, body_glyphs) = (header.glyphs(), body.glyphs());
+    assert_eq!(merged.len(), header_len + body_glyphs.len());
+    for (merged, original) in merged[header_len..].iter().zip(body_glyphs) {
+        assert_eq!((merged.x, merged.y), (original.x, original.y + offset));
+    }
+    // The appended lines order and anchor the appended glyphs, but aren't the header's own lines.
+    let body_lines = body.lines().unwrap();
+    assert_eq!(header.lines().unwrap().len(), 1);
+    let first_line: Vec<usize> = body.visual_order(0).iter().map(|index| header_len + index).collect();
+    assert_eq!(header.visual_order(1), first_line);
+    assert_eq!(header.visual_order(body_lines.len()).last().map(|&index| merged[index].parent), Some('s'));
+    let anchored = header.glyphs_anchored(Anchor::Baseline);
+    assert_eq!(anchored[header_len].y, body_lines[0].baseline_y + offset);
+    assert_eq!(header.decorations().len(), body.decorations().len());
+    assert_eq!(header.decorations()[0].y, body.decorations()[0].y + offset);
+}
+
+#[test]
+fn layout_can_break_before() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().max_width(100.0).build());
+    layout.append(Span::text("one", ()));
+    assert!(!layout.can_break_before('t'));
+    assert!(!layout.can_break_before(' '));
+    layout.append(Span::text(" ", ()));
+    assert!(layout.can_break_before('t'));
+    // Asking doesn't advance the layout's own linebreaker.
+    assert!(layout.can_break_before('t'));
+    // Hard breaks follow the newline.
+    layout.append(Span::text("two\n", ()));
+    assert!(layout.can_break_before('t'));
+
+    // Soft breaks are masked out without a max_width.
+    layout.reset(&LayoutSettings::default());
+    layout.append(Span::text("one ", ()));
+    assert!(!layout.can_break_before('t'));
+}
+
+#[test]
+fn layout_glyph_counts() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("Hello world", ()));
+    assert_eq!(layout.appended_glyph_count(), 11);
+    // Nothing is laid out until the layout is finalized.
+    assert_eq!(layout.glyph_count(), 0);
+    layout.finalize();
+    assert_eq!(layout.glyph_count(), layout.glyphs().len());
+    assert!(layout.glyph_count() <= layout.appended_glyph_count());
+    layout.clear();
+    assert_eq!(layout.appended_glyph_count(), 0);
+    assert_eq!(layout.glyph_count(), 0);
+}
+
+fn hyphenate_anywhere(word: &str) -> Vec<usize> {
+    (1..word.len()).collect()
+}
+
+#[test]
+fn layout_hyphenator() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    let settings = LayoutSettings::builder().max_width(120.0);
+    layout.reset(&settings.build());
+    layout.append(Span::text("a hyphenation", ()));
+    layout.finalize();
+    // Without a hyphenator the word wraps whole.
+    let lines = layout.lines().unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(layout.glyphs()[lines[1].glyph_start].parent, 'h');
+    assert!(layout.glyphs().iter().all(|glyph| glyph.parent != '-'));
+
+    layout.reset(&settings.hyphenator(hyphenate_anywhere).build());
+    layout.append(Span::text("a hyphenation", ()));
+    layout.finalize();
+    let lines = layout.lines().unwrap();
+    assert_eq!(lines.len(), 2);
+    let glyphs = layout.glyphs();
+    // The first line is filled up to a hyphen, and the word continues on the second.
+    assert_eq!(glyphs[lines[0].glyph_end].parent, '-');
+    assert_ne!(glyphs[lines[1].glyph_start].parent, 'h');
+    let hyphen = &glyphs[lines[0].glyph_end];
+    assert!(hyphen.x + hyphen.advance <= 120.0);
+    let text: String = glyphs.iter().map(|glyph| glyph.parent).filter(|&c| c != '-').collect();
+    assert_eq!(text, "a hyphenation");
+
+    // Words that fit aren't hyphenated.
+    layout.reset(&settings.hyphenator(hyphenate_anywhere).build());
+    layout.append(Span::text("a hyphen", ()));
+    layout.finalize();
+    assert_eq!(layout.lines().unwrap().len(), 1);
+    assert!(layout.glyphs().iter().all(|glyph| glyph.parent != '-'));
+}
+
+#[test]
+fn layout_renderable() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("a b", 0u8));
+    layout.append(Span::block(Block::new(10, 12), 7u8));
+    layout.finalize();
+    let renderables: Vec<_> = layout.renderable().collect();
+    // The space has no bitmap, so only the letters and the block remain.
+    assert_eq!(renderables.len(), 3);
+    let glyphs = layout.glyphs();
+    match renderables[1] {
+        Renderable::Glyph {
+            config,
+            x,
+            y,
+            width,
+            ..
+        } => {
+            assert_eq!(Some(config), glyphs[2].key);
+            assert_eq!((x, y, width), (glyphs[2].x, glyphs[2].y, glyphs[2].width));
+        }
+        Renderable::Block {
+            ..
+        } => panic!("expected a glyph"),
+    }
+    match renderables[2] {
+        Renderable::Block {
+            width,
+            height,
+            user_data,
+            ..
+        } => assert_eq!((width, height, user_data), (10, 12, 7)),
+        Renderable::Glyph {
+            ..
+        } => panic!("expected a block"),
+    }
+}
+
+#[test]
+fn layout_min_line_height() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    let line_advance = |layout: &mut Layout, px: f32| {
+        layout.append(Span::text("a\nb", ()).with_px(px));
+        layout.finalize();
+        let lines = layout.lines().unwrap();
+        lines[1].baseline_y - lines[0].baseline_y
+    };
+    let small = line_advance(&mut layout, 8.0);
+    layout.clear();
+    let large = line_advance(&mut layout, 60.0);
+    assert!(small < 30.0 && large > 30.0);
+
+    // Small lines are raised to the minimum, while large lines exceed it.
+    layout.reset(&LayoutSettings::builder().min_line_height(30.0).build());
+    assert_eq!(line_advance(&mut layout, 8.0), 30.0);
+    assert_eq!(layout.height(), 60.0);
+    layout.clear();
+    assert_eq!(line_advance(&mut layout, 60.0), large);
+}
+
+#[test]
+fn layout_metric_overrides() {
+    let roboto = roboto();
+    let comfortaa = Font::from_bytes(FONTS[2], FontSettings::default()).unwrap();
+    let baselines = |font: &Font, settings: &LayoutSettings| {
+        let mut layout = Layout::new(font, 20.0, CoordinateSystem::PositiveYDown);
+        layout.reset(settings);
+        layout.append(Span::text("a\nb\nc", ()));
+        layout.finalize();
+        let lines = layout.lines().unwrap();
+        (lines.iter().map(|line| line.baseline_y).collect::<Vec<f32>>(), layout.height())
+    };
+    let default = LayoutSettings::default();
+    assert_ne!(baselines(&roboto, &default), baselines(&comfortaa, &default));
+
+    // With every metric overridden, both fonts produce the same line boxes.
+    let settings =
+        LayoutSettings::builder().ascent_override(16.0).descent_override(-5.0).line_gap_override(3.0).build();
+    let expected = (vec![16.0, 40.0, 64.0], 72.0);
+    assert_eq!(baselines(&roboto, &settings), expected);
+    assert_eq!(baselines(&comfortaa, &settings), expected);
+}
+
+#[test]
+fn layout_visual_order() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("abc\nde", ()));
+    layout.finalize();
+    // Left to right text is already in visual order.
+    assert_eq!(layout.visual_order(0), [0, 1, 2, 3]);
+    assert_eq!(layout.visual_order(1), [4, 5]);
+    assert!(layout.visual_order(2).is_empty());
+
+    // Glyphs advancing to the left are listed right to left.
+    layout.clear();
+    layout.append_with(Span::text("abc", ()), |_, _| -20.0);
+    layout.finalize();
+    assert_eq!(layout.visual_order(0), [2, 1, 0]);
+}
+
+#[test]
+fn layout_append_truncated() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    let mut truncate = |text: &str, position: EllipsisPosition| {
+        layout.reset(&LayoutSettings::builder().max_width(120.0).build());
+        layout.append_truncated(Span::text(text, ()), position);
+        layout.finalize();
+        let glyphs = layout.glyphs();
+        let right = glyphs.iter().map(|glyph| glyph.x + glyph.width as f32).fold(0.0, f32::max);
+        (glyphs.iter().map(|glyph| glyph.parent).collect::<String>(), right)
+    };
+    // Text that fits is unchanged.
+    assert_eq!(truncate("short.txt", EllipsisPosition::Middle).0, "short.txt");
+
+    let text = "a_very_long_file_name.txt";
+    let (start, right) = truncate(text, EllipsisPosition::Start);
+    assert!(start.starts_with('…') && text.ends_with(&start[3..]) && right <= 120.0);
+    let (end, right) = truncate(text, EllipsisPosition::End);
+    assert!(end.ends_with('…') && text.starts_with(&end[..end.len() - 3]) && right <= 120.0);
+    let (middle, right) = truncate(text, EllipsisPosition::Middle);
+    let (head, tail) = middle.split_at(middle.find('…').unwrap());
+    assert!(text.starts_with(head) && text.ends_with(&tail[3..]) && right <= 120.0);
+    assert!(head.len().abs_diff(tail.len() - 3) <= 1);
+}
+
+#[test]
+fn layout_glyph_overrides() {
+    let font = roboto();
+    let icons = Font::from_bytes(FONTS[7], FontSettings::default()).unwrap();
+    let icon = icons.lookup_glyph_index('a');
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    let mut overrides = std::collections::HashMap::new();
+    overrides.insert('\u{E000}', (&icons, icon));
+    layout.set_glyph_overrides(overrides);
+    layout.append(Span::text("a\u{E000}a", ()));
+    layout.finalize();
+    let glyphs = layout.glyphs();
+    // The overridden character is drawn with the icon font's glyph and metrics.
+    let key = glyphs[1].key.unwrap();
+    assert_eq!((key.glyph_index, key.font_hash), (icon, icons.file_hash()));
+    assert_eq!(glyphs[1].width, icons.metrics_indexed(icon, 20.0).width);
+    assert_eq!(glyphs[1].advance, icons.metrics_indexed(icon, 20.0).advance_width.ceil());
+    assert_eq!(glyphs[0].key.unwrap().font_hash, font.file_hash());
+    assert_eq!(glyphs[2].key.unwrap().font_hash, font.file_hash());
+}
+
+#[test]
+fn layout_line_width() {
+    let font = roboto();
+    let advance = |text: &str| text.chars().map(|c| font.metrics(c, 20.0).advance_width.ceil()).sum::<f32>();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    // Without a max width, the width is still measured.
+    layout.append(Span::text("abc \nde", ()));
+    layout.finalize();
+    let lines = layout.lines().unwrap();
+    assert_eq!(lines[0].width, advance("abc"));
+    assert_eq!(lines[1].width, advance("de"));
+
+    layout.reset(&LayoutSettings::builder().max_width(60.0).build());
+    layout.append(Span::text("one two three", ()));
+    layout.finalize();
+    for line in layout.lines().unwrap() {
+        assert_eq!(line.width + line.padding, 60.0);
+    }
+}
+
+#[test]
+fn layout_trailing_newline_line() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("ab\n", ()));
+    assert_eq!(layout.line_count(), 1);
+    let line_height = layout.height();
+
+    layout.reset(&LayoutSettings::builder().trailing_newline_line(true).build());
+    layout.append(Span::text("ab\n", ()));
+    layout.finalize();
+    assert_eq!(layout.line_count(), 2);
+    assert_eq!(layout.height(), line_height * 2.0);
+    let lines = layout.lines().unwrap();
+    assert!(lines[1].started_by_hard_break);
+    assert_eq!(lines[1].glyph_start, 3);
+    assert!(layout.visual_order(1).is_empty());
+
+    // Text appended afterwards continues on the empty line.
+    layout.append(Span::text("c", ()));
+    layout.finalize();
+    assert_eq!(layout.line_count(), 2);
+    assert_eq!(layout.lines().unwrap()[1].glyph_start, 3);
+    assert_eq!(layout.visual_order(1), [3]);
+}
+
+#[test]
+fn layout_break_pairs() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    // Each text is wrapped 1px narrower than it is, so it breaks at its last opportunity.
+    let cases = [
+        ("a) (b", ["a) ", "(b"]),
+        ("1,000.00 x", ["1,000.00 ", "x"]),
+        ("$ 10", ["$ ", "10"]),
+        ("e.g. x", ["e.g. ", "x"]),
+        ("100% x", ["100% ", "x"]),
+        ("a \"b\" c", ["a \"b\" ", "c"]),
+        ("a-b", ["a-", "b"]),
+        ("a/b", ["a/", "b"]),
+        ("a—b", ["a—", "b"]),
+        ("中文字", ["中文", "字"]),
+    ];
+    for (text, expected) in cases.iter() {
+        layout.reset(&LayoutSettings::default());
+        layout.append(Span::text(text, ()));
+        layout.finalize();
+        let width = layout.lines().unwrap()[0].width;
+        layout.reset(&LayoutSettings::builder().max_width(width - 1.0).build());
+        layout.append(Span::text(text, ()));
+        layout.finalize();
+        let glyphs = layout.glyphs();
+        let lines = layout.lines().unwrap();
+        let parts: Vec<String> = lines
+            .iter()
+            .map(|line| glyphs[line.glyph_start..=line.glyph_end].iter().map(|glyph| glyph.parent).collect())
+            .collect();
+        assert_eq!(parts, expected, "{:?}", text);
+        // The first line ends after the glyph before the break.
+        if !glyphs[lines[0].glyph_end].char_data.is_whitespace() {
+            let advance: f32 = glyphs[..=lines[0].glyph_end].iter().map(|glyph| glyph.advance).sum();
+            assert_eq!(lines[0].width, advance);
+        }
+    }
+}
+
+#[test]
+fn layout_finalize_streaming() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    let settings =
+        LayoutSettings::builder().max_width(80.0).horizontal_align(HorizontalAlign::Center).build();
+    layout.reset(&settings);
+    layout.append(Span::text("the quick brown fox\njumps over the lazy dog", ()));
+    layout.finalize();
+    let expected: Vec<(char, f32, f32)> = layout.glyphs().iter().map(|g| (g.parent, g.x, g.y)).collect();
+    let baselines: Vec<f32> = layout.lines().unwrap().iter().map(|line| line.baseline_y).collect();
+
+    let mut streamed = Vec::new();
+    let mut streamed_baselines = Vec::new();
+    layout.finalize_streaming(|line, glyphs| {
+        streamed_baselines.push(line.baseline_y);
+        streamed.extend(glyphs.iter().map(|g| (g.parent, g.x, g.y)));
+    });
+    assert_eq!(streamed, expected);
+    assert_eq!(streamed_baselines, baselines);
+    // No glyphs are kept in the finalized output, though the lines are still positioned.
+    assert!(layout.glyphs().is_empty());
+    assert_eq!(layout.lines().unwrap().iter().map(|line| line.baseline_y).collect::<Vec<_>>(), baselines);
+}
+
+#[test]
+fn layout_reveal() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("ab c", ()));
+    layout.append(Span::block(Block::new(10, 10), ()));
+    layout.finalize();
+    let parents =
+        |glyphs: &[fontdue::layout::GlyphPosition]| glyphs.iter().map(|g| g.parent).collect::<String>();
+    assert_eq!(parents(layout.glyphs_range(1..3)), "b ");
+    assert_eq!(parents(layout.glyphs_range(3..10)), "cx");
+    assert!(layout.glyphs_range(8..10).is_empty());
+
+    // Whitespace is revealed along with the next drawn glyph, and blocks are counted.
+    assert!(layout.reveal(0).is_empty());
+    assert_eq!(parents(layout.reveal(2)), "ab");
+    assert_eq!(parents(layout.reveal(3)), "ab c");
+    assert_eq!(layout.reveal(4).len(), 5);
+    assert_eq!(layout.reveal(100).len(), 5);
+}
+
+#[test]
+fn layout_baseline_grid() {
+    let font = roboto();
+    let baselines = |coordinate_system: CoordinateSystem, px: f32| {
+        let mut layout = Layout::new(&font, px, coordinate_system);
+        layout.reset(&LayoutSettings::builder().y(100.0).baseline_grid(30.0).build());
+        layout.append(Span::text("a\nb\nc", ()));
+        layout.finalize();
+        layout.lines().unwrap().iter().map(|line| line.baseline_y).collect::<Vec<f32>>()
+    };
+    assert_eq!(baselines(CoordinateSystem::PositiveYDown, 20.0), [130.0, 160.0, 190.0]);
+    assert_eq!(baselines(CoordinateSystem::PositiveYUp, 20.0), [70.0, 40.0, 10.0]);
+    // Lines taller than the grid span several steps.
+    assert_eq!(baselines(CoordinateSystem::PositiveYDown, 50.0), [160.0, 220.0, 280.0]);
+}
+
+#[test]
+fn layout_script_presets() {
+    let font = roboto();
+    let superscript = font.superscript_metrics(40.0).unwrap();
+    let subscript = font.subscript_metrics(40.0).unwrap();
+    assert!(superscript.px < 40.0 && superscript.rise > 0.0);
+    assert!(subscript.px < 40.0 && subscript.rise < 0.0);
+
+    let positions = |spans: &[Span]| {
+        let mut layout = Layout::new(&font, 40.0, CoordinateSystem::PositiveYDown);
+        for span in spans {
+            layout.append(span.clone());
+        }
+        layout.finalize();
+        layout.glyphs().iter().map(|g| (g.x, g.y, g.key.unwrap().px)).collect::<Vec<_>>()
+    };
+    let presets = positions(&[
+        Span::text("x", ()),
+        Span::text("2", ()).superscript(),
+        Span::text("i", ()).subscript().with_rise(2.0),
+    ]);
+    let explicit = positions(&[
+        Span::text("x", ()),
+        Span::text("2", ()).with_px(superscript.px).with_rise(superscript.rise),
+        Span::text("i", ()).with_px(subscript.px).with_rise(subscript.rise + 2.0),
+    ]);
+    assert_eq!(presets, explicit);
+    assert_eq!(presets[1].2, superscript.px);
+}
+
+#[test]
+fn layout_append_leader() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().max_width(300.0).build());
+    layout.append(Span::text("Chapter 1 ", ()));
+    layout.append_leader('.', Span::text("15", ()));
+    layout.finalize();
+    assert_eq!(layout.line_count(), 1);
+    let glyphs = layout.glyphs();
+    let leaders = glyphs.iter().filter(|g| g.parent == '.').count();
+    assert!(leaders > 10);
+    assert_eq!(glyphs.len(), "Chapter 1 ".len() + leaders + 2);
+    assert_eq!(layout.lines().unwrap()[0].width, 300.0);
+
+    // Without a max width there's nothing to fill.
+    layout.reset(&LayoutSettings::default());
+    layout.append(Span::text("Chapter 1 ", ()));
+    layout.append_leader('.', Span::text("15", ()));
+    layout.finalize();
+    assert_eq!(layout.glyphs().len(), "Chapter 1 15".len());
+}
+
+#[test]
+fn layout_max_columns() {
+    let mono = Font::from_bytes(FONTS[3], FontSettings::default()).unwrap();
+    assert_eq!(mono.monospace_advance(20.0), Some(10.0));
+    assert!(Font::from_bytes(FONTS[1], FontSettings::default()).unwrap().monospace_advance(20.0).is_some());
+    assert_eq!(roboto().monospace_advance(20.0), None);
+
+    let mut layout = Layout::new(&mono, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().max_columns(10).wrap_style(WrapStyle::Letter).build());
+    layout.append(Span::text("abcdefghijklmnopqrstuvwxy", ()));
+    layout.finalize();
+    let lines: Vec<usize> =
+        layout.lines().unwrap().iter().map(|line| line.glyph_end + 1 - line.glyph_start).collect();
+    assert_eq!(lines, [10, 10, 5]);
+
+    // Fractional advances summed one at a time still fill every column.
+    for px in [17.0, 17.3, 13.7, 21.1] {
+        let mut layout = Layout::new(&mono, px, CoordinateSystem::PositiveYDown);
+        let settings = LayoutSettings::builder()
+            .max_columns(10)
+            .wrap_style(WrapStyle::Letter)
+            .rounding(RoundMode::None)
+            .build();
+        layout.reset(&settings);
+        layout.append(Span::text("abcdefghijklmnopqrstuvwxyz0123", ()));
+        layout.finalize();
+        let lines: Vec<usize> =
+            layout.lines().unwrap().iter().map(|line| line.glyph_end + 1 - line.glyph_start).collect();
+        assert_eq!(lines, [10, 10, 10], "{}px", px);
+    }
+
+    // Proportional fonts fall back to the max width.
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().max_columns(10).build());
+    layout.append(Span::text("abcdefghijklmnopqrstuvwxy", ()));
+    assert_eq!(layout.line_count(), 1);
+    layout.reset(
+        &LayoutSettings::builder().max_columns(10).max_width(100.0).wrap_style(WrapStyle::Letter).build(),
+    );
+    layout.append(Span::text("abcdefghijklmnopqrstuvwxy", ()));
+    assert!(layout.line_count() > 1);
+}
+
+#[test]
+fn layout_glyph_alpha() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("abcde", ()));
+    layout.finalize();
+    let hash = layout.content_hash();
+    assert!(layout.glyphs().iter().all(|glyph| glyph.alpha == 1.0));
+
+    layout.fade_range(0..5, 0.0, 1.0);
+    let alphas: Vec<f32> = layout.glyphs().iter().map(|glyph| glyph.alpha).collect();
+    assert_eq!(alphas, [0.0, 0.25, 0.5, 0.75, 1.0]);
+    assert_ne!(layout.content_hash(), hash);
+    layout.set_glyph_alpha(1, 2.0);
+    layout.set_glyph_alpha(10, 0.5);
+    layout.fade_range(3..100, 0.2, 0.2);
+    let alphas: Vec<f32> = layout.glyphs().iter().map(|glyph| glyph.alpha).collect();
+    assert_eq!(alphas, [0.0, 1.0, 0.5, 0.2, 0.2]);
+    match layout.renderable().next() {
+        Some(Renderable::Glyph {
+            alpha,
+            ..
+        }) => assert_eq!(alpha, 0.0),
+        _ => panic!("expected a glyph"),
+    }
+
+    layout.finalize();
+    assert_eq!(layout.content_hash(), hash);
+}
+
+#[test]
+fn layout_vertical_overflow() {
+    let font = roboto();
+    for coordinate_system in [CoordinateSystem::PositiveYDown, CoordinateSystem::PositiveYUp] {
+        let mut layout = Layout::new(&font, 20.0, coordinate_system);
+        layout.append(Span::text("a\nb\nc", ()));
+        assert_eq!(layout.vertical_overflow(), 0.0);
+        let height = layout.height();
+
+        layout
+            .reset(&LayoutSettings::builder().max_height(30.0).vertical_align(VerticalAlign::Bottom).build());
+        layout.append(Span::text("a\nb\nc", ()));
+        assert_eq!(layout.vertical_overflow(), height - 30.0);
+        assert!(layout.overflows_vertically());
+
+        layout.reset(&LayoutSettings::builder().max_height(height).build());
+        layout.append(Span::text("a\nb\nc", ()));
+        assert!(!layout.overflows_vertically());
+    }
+}
+
+#[test]
+fn layout_no_wrap() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    for wrap_style in [WrapStyle::Word, WrapStyle::Letter] {
+        let settings = LayoutSettings::builder().max_width(50.0).wrap_style(wrap_style).wrap(false).build();
+        layout.reset(&settings);
+        layout.append(Span::text("hello world foo", ()));
+        layout.append(Span::block(Block::new(40, 10).with_breakable(true), ()));
+        assert_eq!(layout.line_count(), 1);
+        assert!(layout.overflows());
+        layout.append(Span::text("\nbar", ()));
+        assert_eq!(layout.line_count(), 2);
+    }
+
+    // The max width still aligns the text.
+    layout.reset(
+        &LayoutSettings::builder()
+            .max_width(300.0)
+            .horizontal_align(HorizontalAlign::Right)
+            .wrap(false)
+            .build(),
+    );
+    layout.append(Span::text("hello", ()));
+    layout.finalize();
+    let line = layout.lines().unwrap()[0];
+    assert_eq!(line.width + line.padding, 300.0);
+    assert!(layout.glyphs()[0].x >= line.padding);
+}
+
+#[test]
+fn glyph_rect() {
+    let font = roboto();
+    for coordinate_system in [CoordinateSystem::PositiveYDown, CoordinateSystem::PositiveYUp] {
+        // Whether a is above b on screen.
+        let above = |a: f32, b: f32| match coordinate_system {
+            CoordinateSystem::PositiveYDown => a < b,
+            CoordinateSystem::PositiveYUp => a > b,
+        };
+        let mut layout = Layout::new(&font, 20.0, coordinate_system);
+        layout.append(Span::text("Ag", ()));
+        layout.finalize();
+        for glyph in layout.glyphs() {
+            let (left, top, right, bottom) = glyph.rect(coordinate_system);
+            assert!(above(top, bottom));
+            assert_eq!((left, right - left), (glyph.x, glyph.width as f32));
+            assert_eq!((top - bottom).abs(), glyph.height as f32);
+            assert_eq!(top.min(bottom), glyph.y);
+        }
+        // A reaches higher than g, and the descender of g reaches below A.
+        let (a, g) = (layout.glyphs()[0].rect(coordinate_system), layout.glyphs()[1].rect(coordinate_system));
+        assert!(above(a.1, g.1));
+        assert!(above(a.3, g.3));
+    }
+}
+
+#[test]
+fn layout_append_ranges() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().max_width(60.0).build());
+    let first = layout.append(Span::text("one two ", 1));
+    let empty = layout.append(Span::text("", 2));
+    let block = layout.append(Span::block(Block::new(10, 10), 3));
+    let last = layout.append(Span::text("three four", 4));
+    assert_eq!(first, 0..8);
+    assert_eq!(empty, 8..8);
+    assert_eq!(block, 8..9);
+    assert_eq!(last, 9..19);
+    layout.finalize();
+    assert!(layout.line_count() > 1);
+    let text: String = layout.glyphs()[last].iter().map(|glyph| glyph.parent).collect();
+    assert_eq!(text, "three four");
+    assert!(layout.glyphs()[first].iter().all(|glyph| glyph.user_data == 1));
+}
+
+#[test]
+fn layout_justify_weights() {
+    let font = roboto();
+    let text = "aa bb\u{a0}cc\u{3000}dd ffffffffffffffff";
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text(&text[..text.find(" f").unwrap()], ()));
+    layout.finalize();
+    let max_width = layout.lines().unwrap()[0].width + 40.0;
+    let x_of = |align| {
+        let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings::builder().max_width(max_width).horizontal_align(align).build());
+        layout.append(Span::text(text, ()));
+        layout.finalize();
+        assert_eq!(layout.line_count(), 2);
+        layout.glyphs().iter().map(|glyph| glyph.x).collect::<Vec<f32>>()
+    };
+    let (left, justified) = (x_of(HorizontalAlign::Left), x_of(HorizontalAlign::Justify));
+    let shift = |index: usize| justified[index] - left[index];
+    let space = shift(3) - shift(0);
+    assert!(space > 5.0);
+    // The no-break space doesn't stretch, and the ideographic space stretches twice as much.
+    assert!((shift(6) - shift(3)).abs() <= 1.0);
+    assert!((shift(9) - shift(6) - space * 2.0).abs() <= 2.0);
+}
+
+#[test]
+fn layout_append_paragraph() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().max_width(300.0).record_spans(true).build());
+    let heading = ParagraphSettings {
+        horizontal_align: Some(HorizontalAlign::Center),
+        ..ParagraphSettings::default()
+    };
+    let body = ParagraphSettings {
+        indent: 30.0,
+        space_before: 10.0,
+        ..ParagraphSettings::default()
+    };
+    let heading_range = layout.append_paragraph(&[Span::text("Title\n", ())], heading);
+    let body_range = layout.append_paragraph(&[Span::text("Body ", ()), Span::text("text", ())], body);
+    assert_eq!(heading_range, 0..6);
+    assert_eq!(body_range, 6..15);
+    layout.finalize();
+
+    // The hard break ending the heading doesn't leave an empty line before the body.
+    let lines = layout.lines().unwrap().clone();
+    assert_eq!(lines.len(), 2);
+    let xs: Vec<f32> = layout.glyphs().iter().map(|g| g.x).collect();
+    assert_eq!(xs[0], (lines[0].padding / 2.0).floor() + font.metrics('T', 20.0).bounds.xmin.floor());
+    assert_eq!(xs[6], 30.0 + font.metrics('B', 20.0).bounds.xmin.floor());
+    assert_eq!(lines[1].baseline_y - lines[0].baseline_y, lines[0].max_new_line_size + 10.0);
+    assert_eq!(layout.height(), lines[0].max_new_line_size * 2.0 + 10.0);
+
+    // Paragraphs are laid out the same way again on relayout.
+    layout.relayout(&LayoutSettings::builder().max_width(300.0).build());
+    layout.finalize();
+    assert_eq!(layout.lines().unwrap()[1].baseline_y, lines[1].baseline_y);
+    let relaid: Vec<f32> = layout.glyphs().iter().map(|g| g.x).collect();
+    assert_eq!(relaid, xs);
+
+    // Clearing restores the layout's alignment.
+    layout.clear();
+    layout.append(Span::text("Title", ()));
+    layout.finalize();
+    assert_eq!(layout.glyphs()[0].x, font.metrics('T', 20.0).bounds.xmin.floor());
+
+    // Text appended after a paragraph uses the layout's alignment and line height again, also on
+    // relayout.
+    let settings = LayoutSettings::builder().max_width(300.0).record_spans(true).build();
+    layout.reset(&settings);
+    let tall = ParagraphSettings {
+        horizontal_align: Some(HorizontalAlign::Center),
+        line_height: Some(2.0),
+        ..ParagraphSettings::default()
+    };
+    layout.append_paragraph(&[Span::text("Title\n", ())], tall);
+    layout.append(Span::text("Body", ()));
+    for relayout in [false, true] {
+        if relayout {
+            layout.relayout(&settings);
+        }
+        layout.finalize();
+        let lines = layout.lines().unwrap();
+        assert_eq!(lines.len(), 2);
+        let title_x = (lines[0].padding / 2.0).floor() + font.metrics('T', 20.0).bounds.xmin.floor();
+        assert_eq!(layout.glyphs()[0].x, title_x);
+        assert_eq!(layout.glyphs()[6].x, font.metrics('B', 20.0).bounds.xmin.floor());
+        assert_eq!((lines[0].line_height, lines[1].line_height), (Some(2.0), None));
+    }
+}
+
+#[test]
+fn layout_push_char() {
+    // Great Vibes has a legacy kerning table, with pairs like "Pa".
+    let font = Font::from_bytes(FONTS[6], FontSettings::default()).unwrap();
+    let text = "Papa Poe Pea Pony Pay";
+    let settings =
+        LayoutSettings::builder().max_width(120.0).features(FeatureSet::default()).record_spans(true).build();
+    let positions = |layout: &Layout| -> Vec<(f32, f32, Option<GlyphRasterConfig>)> {
+        layout.glyphs().iter().map(|g| (g.x, g.y, g.key)).collect()
+    };
+
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&settings);
+    layout.append(Span::text(text, ()));
+    layout.finalize();
+    let expected = positions(&layout);
+    assert!(layout.line_count() > 1);
+
+    layout.reset(&settings);
+    for c in text.chars() {
+        layout.push_char(c, &font, 20.0, ());
+    }
+    layout.finalize();
+    assert_eq!(positions(&layout), expected);
+    layout.relayout(&settings);
+    layout.finalize();
+    assert_eq!(positions(&layout), expected);
+
+    // Separate spans aren't kerned against each other, unlike pushed characters.
+    layout.reset(&settings);
+    for (i, c) in text.char_indices() {
+        layout.append(Span::text(&text[i..i + c.len_utf8()], ()));
+    }
+    layout.finalize();
+    assert_ne!(positions(&layout), expected);
+
+    // Pushed characters are joined into ligatures like characters of a span.
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("fi", ()));
+    layout.finalize();
+    let expected = positions(&layout);
+    assert_eq!(expected.len(), 1);
+    layout.clear();
+    layout.push_char('f', &font, 20.0, ());
+    layout.push_char('i', &font, 20.0, ());
+    layout.finalize();
+    assert_eq!(positions(&layout), expected);
+
+    // A ligature merges the user data of its pushed characters like that of separate spans.
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.set_user_data_merge(Some(|kept: u8, next: u8| kept | next));
+    layout.push_char('f', &font, 20.0, 1);
+    layout.push_char('i', &font, 20.0, 2);
+    layout.finalize();
+    assert_eq!(layout.glyphs().iter().map(|g| g.user_data).collect::<Vec<_>>(), [3]);
+}
+
+#[test]
+fn layout_push_char_matches_append() {
+    // After every push, the pushed characters are laid out like the same text appended as a single
+    // span, while the ligatures, fractions, and hyphenated words they end with are still open.
+    let font = roboto();
+    let text = "fi 1/2  office 12/345\n\n  hyphenation 7/ final\u{A0}ly \n";
+    let base = LayoutSettings {
+        max_width: Some(100.0),
+        features: FeatureSet::default() | FeatureSet::FRAC,
+        ..LayoutSettings::default()
+    };
+    let variants = [
+        base,
+        LayoutSettings {
+            collapse_whitespace: true,
+            trailing_newline_line: true,
+            ..base
+        },
+        LayoutSettings {
+            hyphenator: Some(hyphenate_anywhere),
+            horizontal_align: HorizontalAlign::Justify,
+            ..base
+        },
+        LayoutSettings {
+            show_invisibles: true,
+            max_width: None,
+            ..base
+        },
+    ];
+    let state = |layout: &Layout<u8>| {
+        let glyphs: Vec<_> = layout.glyphs().iter().map(|g| (g.x, g.y, g.key, g.user_data)).collect();
+        let lines: Vec<_> = layout
+            .lines()
+            .unwrap()
+            .iter()
+            .map(|l| (l.baseline_y, l.width, l.glyph_start, l.glyph_end))
+            .collect();
+        (glyphs, lines, layout.omitted().to_vec(), layout.scripts())
+    };
+    for settings in &variants {
+        let mut appended = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+        let mut pushed = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+        pushed.reset(settings);
+        for (i, c) in text.char_indices() {
+            let prefix = &text[..i + c.len_utf8()];
+            appended.reset(settings);
+            appended.append(Span::text(prefix, 1));
+            appended.finalize();
+            pushed.push_char(c, &font, 20.0, 1);
+            pushed.finalize();
+            assert_eq!(state(&pushed), state(&appended), "{:?}", prefix);
+        }
+    }
+
+    // Long runs only lay out the characters at their end again.
+    let text = text.repeat(100);
+    let mut appended = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    appended.reset(&variants[2]);
+    appended.append(Span::text(&text, 1));
+    appended.finalize();
+    let mut pushed = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    pushed.reset(&variants[2]);
+    for c in text.chars() {
+        pushed.push_char(c, &font, 20.0, 1);
+    }
+    pushed.finalize();
+    assert_eq!(state(&pushed), state(&appended));
+}
+
+#[test]
+fn layout_to_vertices() {
+    let font = roboto();
+    let x_index = font.lookup_glyph_index('x');
+    let atlas = |config: &GlyphRasterConfig| {
+        if config.glyph_index == x_index {
+            None
+        } else {
+            Some((0.25, 0.5, 0.75, 1.0))
+        }
+    };
+    for coordinate_system in [CoordinateSystem::PositiveYDown, CoordinateSystem::PositiveYUp] {
+        let mut layout = Layout::new(&font, 20.0, coordinate_system);
+        layout.append(Span::text("Hi x", ()));
+        layout.append(Span::block(Block::new(10, 10), ()));
+        layout.finalize();
+        // The space has no bitmap, x isn't in the atlas, and blocks are skipped.
+        let vertices = layout.to_vertices(&atlas);
+        assert_eq!(vertices.len(), 2 * 24);
+
+        let (x0, top, x1, bottom) = layout.glyphs()[0].rect(coordinate_system);
+        assert_eq!(vertices[0..4], [x0, top, 0.25, 0.5]);
+        assert_eq!(vertices[4..8], [x1, top, 0.75, 0.5]);
+        assert_eq!(vertices[8..12], [x0, bottom, 0.25, 1.0]);
+        assert_eq!(vertices[20..24], [x1, bottom, 0.75, 1.0]);
+    }
+}
+
+#[test]
+fn layout_first_baseline() {
+    let font = roboto();
+    for coordinate_system in [CoordinateSystem::PositiveYDown, CoordinateSystem::PositiveYUp] {
+        let mut layout = Layout::new(&font, 20.0, coordinate_system);
+        let settings = LayoutSettings::builder()
+            .y(100.0)
+            .max_height(200.0)
+            .vertical_align(VerticalAlign::Middle)
+            .build();
+        layout.reset(&settings);
+        // The empty layout uses the base font's metrics for the line text will appear on.
+        let empty = layout.first_baseline();
+        layout.append(Span::text("Hello", ()));
+        assert_eq!(layout.first_baseline(), empty);
+        layout.finalize();
+        assert_eq!(layout.lines().unwrap()[0].baseline_y, empty);
+    }
+}
+
+#[test]
+fn layout_unique_configs() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.append(Span::text("abca", ()));
+    layout.append(Span::block(Block::new(10, 10), ()));
+    layout.append(Span::text("b", ()).with_px(30.0));
+    layout.finalize();
+    let configs = layout.unique_configs();
+    let glyphs = layout.glyphs();
+    // The repeated 'a' at the same size is listed once, and the block is skipped.
+    assert_eq!(
+        configs,
+        [glyphs[0].key.unwrap(), glyphs[1].key.unwrap(), glyphs[2].key.unwrap(), glyphs[5].key.unwrap()]
+    );
+    assert_eq!(glyphs[3].key, glyphs[0].key);
+    assert!(glyphs[4].key.is_none());
+    // The 'b' at another size is a different raster.
+    assert_ne!(glyphs[5].key, glyphs[1].key);
+    assert!(Layout::<()>::new(&font, 20.0, CoordinateSystem::PositiveYDown).unique_configs().is_empty());
+}
+
+#[test]
+fn layout_glyph_advance() {
+    let font = roboto();
+    for rounding in [RoundMode::Floor, RoundMode::None] {
+        let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings::builder().rounding(rounding).build());
+        layout.append(Span::text("AVATar ", ()));
+        layout.append(Span::block(Block::new(12, 10), ()).with_kerning(3.0));
+        layout.append(Span::text("x", ()));
+        layout.finalize();
+        let glyphs = layout.glyphs();
+        // Each glyph's pen position is the previous one's plus its advance.
+        let pen = |index: usize| {
+            let glyph = &glyphs[index];
+            if glyph.key.is_none() {
+                return glyph.x;
+            }
+            match rounding {
+                RoundMode::None => glyph.x - font.metrics(glyph.parent, 20.0).xmin as f32,
+                _ => glyph.x - font.metrics(glyph.parent, 20.0).bounds.xmin.floor(),
+            }
+        };
+        for index in 0..8 {
+            assert!((pen(index) + glyphs[index].advance - pen(index + 1)).abs() < 0.001, "{}", index);
+        }
+        if rounding == RoundMode::Floor {
+            assert!(glyphs.iter().all(|glyph| glyph.advance == glyph.advance.ceil()));
+        }
+        // A block advances by its width plus kerning.
+        assert_eq!(glyphs[7].advance, 15.0);
+    }
+}
+
+#[test]
+fn layout_line_gap_override() {
+    let font = roboto();
+    let metrics = font.horizontal_line_metrics(20.0).unwrap();
+    let mut baselines = Vec::new();
+    for line_gap in [None, Some(0.0), Some(7.0)] {
+        let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+        let mut builder = LayoutSettings::builder();
+        if let Some(line_gap) = line_gap {
+            builder = builder.line_gap_override(line_gap);
+        }
+        layout.reset(&builder.build());
+        layout.append(Span::text("a\nb", ()));
+        layout.finalize();
+        let lines = layout.lines().unwrap();
+        assert_eq!(lines[0].max_line_gap, line_gap.unwrap_or(metrics.line_gap).ceil());
+        let expected = (metrics.ascent - metrics.descent + line_gap.unwrap_or(metrics.line_gap)).ceil();
+        assert_eq!(lines[1].baseline_y - lines[0].baseline_y, expected);
+        baselines.push(lines[1].baseline_y);
+    }
+    // The override replaces the font's gap rather than adding to it.
+    assert_eq!(baselines[0], baselines[1] + metrics.line_gap.ceil());
+    assert_eq!(baselines[2] - baselines[1], 7.0);
+}
+
+#[test]
+fn layout_lines_before_finalize() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    assert!(layout.lines().is_none());
+    // Lines are available while appending, and positioned by finalizing.
+    layout.append(Span::text("a\nb", ()));
+    assert_eq!(layout.lines().unwrap().len(), 2);
+    layout.finalize();
+    let baseline = layout.lines().unwrap()[1].baseline_y;
+    layout.append(Span::text("\nc", ()));
+    assert_eq!(layout.lines().unwrap().len(), 3);
+    layout.finalize();
+    assert_eq!(layout.lines().unwrap()[1].baseline_y, baseline);
+}
+
+#[test]
+fn layout_padded_font() {
+    let plain = roboto();
+    let padded = Font::from_bytes(
+        FONTS[0],
+        FontSettings {
+            raster_padding: 3,
+            ..FontSettings::default()
+        },
+    )
+    .unwrap();
+    for rounding in [RoundMode::Floor, RoundMode::None] {
+        for coordinate_system in [CoordinateSystem::PositiveYDown, CoordinateSystem::PositiveYUp] {
+            let positions = |font: &Font| {
+                let mut layout = Layout::new(font, 17.0, coordinate_system);
+                layout.reset(&LayoutSettings::builder().rounding(rounding).build());
+                layout.append(Span::text("Hello", ()));
+                layout.finalize();
+                layout.glyphs().iter().map(|glyph| (glyph.x, glyph.y, glyph.width)).collect::<Vec<_>>()
+            };
+            // The padded bitmap grows by 3 pixels on every side, so it starts 3 pixels earlier.
+            for (plain, padded) in positions(&plain).into_iter().zip(positions(&padded)) {
+                assert_eq!(padded.0, plain.0 - 3.0);
+                assert_eq!(padded.2, plain.2 + 6);
+                // y is the bitmap's top edge going down and its bottom edge going up; both move out.
+                assert_eq!(padded.1, plain.1 - 3.0);
+            }
+        }
+    }
+}
+
+#[test]
+fn layout_content_hash() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    let mut hash_of = |text: &str, settings: &LayoutSettings| {
+        layout.reset(settings);
+        layout.append(Span::text(text, ()));
+        layout.finalize();
+        layout.content_hash()
+    };
+    let settings = LayoutSettings::default();
+    let hash = hash_of("Hello", &settings);
+    assert_eq!(hash_of("Hello", &settings), hash);
+    assert_ne!(hash_of("Hellp", &settings), hash);
+    assert_ne!(hash_of("Hello", &LayoutSettings::builder().x(3.0).build()), hash);
+}
+
+#[test]
+fn layout_breakable_block() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().max_width(100.0).build());
+    layout.append(Span::text("ab ", 0u8));
+    layout.append(Span::block(Block::new(230, 10).with_breakable(true), 1u8));
+    layout.finalize();
+    let pieces: Vec<(f32, usize)> = layout
+        .glyphs()
+        .iter()
+        .filter(|glyph| glyph.user_data == 1)
+        .map(|glyph| (glyph.x, glyph.width))
+        .collect();
+    // The block fills the rest of the first line and every line after it until it's placed.
+    assert_eq!(pieces.len(), 3);
+    assert_eq!(pieces.iter().map(|&(_, width)| width).sum::<usize>(), 230);
+    assert_eq!(pieces[0].0 + pieces[0].1 as f32, 100.0);
+    assert_eq!(pieces[1], (0.0, 100));
+    assert_eq!(pieces[2].0, 0.0);
+    assert_eq!(layout.lines().unwrap().len(), 3);
+
+    // Blocks that aren't breakable wrap whole.
+    layout.clear();
+    layout.append(Span::text("ab ", 0u8));
+    layout.append(Span::block(Block::new(80, 10), 1u8));
+    layout.finalize();
+    let lines = layout.lines().unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(layout.glyphs()[lines[1].glyph_start].width, 80);
+}
+
+#[test]
+fn layout_span_inheritance() {
+    let roboto = roboto();
+    let mono = Font::from_bytes(FONTS[1], FontSettings::default()).unwrap();
+    let mut layout = Layout::new(&roboto, 20.0, CoordinateSystem::PositiveYDown);
+    fn configs<'f>(layout: &mut Layout<'f>, span: Span<'f, '_>) -> Vec<(usize, f32, u16)> {
+        layout.clear();
+        layout.append(span);
+        layout.finalize();
+        layout
+            .glyphs()
+            .iter()
+            .map(|glyph| glyph.key.unwrap())
+            .map(|k| (k.font_hash, k.px, k.glyph_index))
+            .collect()
+    }
+    // Styled spans set the font and size at once.
+    let styled = configs(&mut layout, Span::styled("a", &mono, 30.0, ()));
+    let chained = configs(&mut layout, Span::text("a", ()).with_font(&mono).with_px(30.0));
+    assert_eq!(styled, chained);
+    assert_eq!(styled[0].0, mono.file_hash());
+    // Unset attributes fall back to the layout's base, not the previous span.
+    layout.clear();
+    layout.append(Span::styled("a", &mono, 30.0, ()));
+    layout.append(Span::text("a", ()));
+    layout.finalize();
+    let base = layout.glyphs()[1].key.unwrap();
+    assert_eq!((base.font_hash, base.px), (roboto.file_hash(), 20.0));
+
+    // Lines without a line height multiplier advance by the font's new line size.
+    let new_line_size = roboto.horizontal_line_metrics(20.0).unwrap().new_line_size.ceil();
+    let advance = |layout: &mut Layout, line_height: Option<f32>| {
+        layout.clear();
+        let span = Span::text("a\nb", ());
+        layout.append(match line_height {
+            Some(line_height) => span.with_line_height(line_height),
+            None => span,
+        });
+        layout.finalize();
+        let lines = layout.lines().unwrap();
+        lines[1].baseline_y - lines[0].baseline_y
+    };
+    assert_eq!(advance(&mut layout, None), new_line_size);
+    assert_eq!(advance(&mut layout, Some(1.0)), new_line_size);
+    assert_eq!(advance(&mut layout, Some(2.0)), new_line_size * 2.0);
+}
+
+#[test]
+fn layout_glyphs_anchored() {
+    let font = roboto();
+    for system in [CoordinateSystem::PositiveYDown, CoordinateSystem::PositiveYUp] {
+        let mut layout = Layout::new(&font, 20.0, system);
+        layout.reset(&LayoutSettings::builder().max_width(60.0).build());
+        layout.append(Span::text("gh gh gh", ()));
+        layout.finalize();
+        let glyphs = layout.glyphs();
+        let lines = layout.lines().unwrap();
+        assert!(lines.len() > 1);
+        let top_left = layout.glyphs_anchored(Anchor::TopLeft);
+        let baseline = layout.glyphs_anchored(Anchor::Baseline);
+        let center = layout.glyphs_anchored(Anchor::Center);
+        let down = system == CoordinateSystem::PositiveYDown;
+        for line in lines {
+            let g = line.glyph_start;
+            let (x, y, h) = (glyphs[g].x, glyphs[g].y, glyphs[g].height as f32);
+            // The top of the bitmap is its lowest y in PositiveYDown, and its highest in PositiveYUp.
+            assert_eq!(
+                (top_left[g].x, top_left[g].y),
+                (
+                    x,
+                    if down {
+                        y
+                    } else {
+                        y + h
+                    }
+                )
+            );
+            assert_eq!((baseline[g].x, baseline[g].y), (x, line.baseline_y));
+            assert_eq!(center[g].y, y + h / 2.0);
+            // The descender of the g hangs below the baseline.
+            if down {
+                assert!(y + h > line.baseline_y);
+            } else {
+                assert!(y < line.baseline_y);
+            }
+        }
+    }
+}
+
+#[test]
+fn layout_append_columns() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    layout.reset(&LayoutSettings::builder().record_spans(true).build());
+    let cells = [
+        Span::text("Item", 0),
+        Span::text("Qty", 1),
+        Span::text("Price", 2),
+        Span::text("Apple", 0),
+        Span::text("2", 1),
+        Span::text("1.50", 2),
+    ];
+    let columns = [0.0, 100.0, 150.0];
+    layout.append_columns(&cells, &columns);
+    layout.finalize();
+    let check = |layout: &Layout<i32>| {
+        let glyphs = layout.glyphs();
+        let lines = layout.lines().unwrap();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            let row = &glyphs[line.glyph_start..=line.glyph_end];
+            for (column, &x) in columns.iter().enumerate() {
+                let first = row.iter().find(|glyph| glyph.user_data == column as i32).unwrap();
+                assert!(first.x >= x && first.x < x + 3.0);
+            }
+        }
+    };
+    check(&layout);
+    // The column positions are kept when laying out again.
+    layout.relayout(&LayoutSettings::default());
+    layout.finalize();
+    check(&layout);
+}
+
+#[test]
+fn layout_empty_is_zero_height() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    assert_eq!(layout.height(), 0.0);
+    assert_eq!(layout.line_count(), 0);
+    layout.append(Span::text("", ()));
+    layout.append(Span::block(Block::new(0, 10), ()));
+    assert_eq!(layout.height(), 0.0);
+    assert_eq!(layout.line_count(), 0);
+    layout.append(Span::text("a\nb", ()));
+    assert_eq!(layout.line_count(), 2);
+    assert!(layout.height() > 0.0);
+    layout.clear();
+    assert_eq!(layout.height(), 0.0);
+    assert_eq!(layout.line_count(), 0);
+}
+
+#[test]
+fn layout_fit() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    let spans = [Span::text("Headline text", ())];
+    let settings = LayoutSettings::builder().max_width(200.0).max_height(50.0).build();
+    let px = layout.fit(&spans, &settings, 8.0, 100.0);
+    assert!(px > 8.0 && px < 100.0);
+    assert_eq!(layout.base_px, px);
+    assert!(!layout.glyphs().is_empty());
+    assert!(!layout.overflows());
+    // Half a pixel larger no longer fits.
+    layout.base_px = px + 0.5;
+    assert!(!layout.would_fit(&spans, &settings));
+
+    // The maximum is used when everything fits, and the minimum when nothing does.
+    assert_eq!(layout.fit(&spans, &settings, 8.0, 10.0), 10.0);
+    let tiny = LayoutSettings::builder().max_width(10.0).max_height(5.0).build();
+    assert_eq!(layout.fit(&spans, &tiny, 8.0, 100.0), 8.0);
+    assert!(layout.overflows());
+}
+
+#[test]
+fn layout_user_data_merge() {
+    let font = roboto();
+    let mut layout = Layout::new(&font, 20.0, CoordinateSystem::PositiveYDown);
+    let mut space_data = |merge| {
+        layout.reset(&LayoutSettings::builder().collapse_whitespace(true).user_data_merge(merge).build());
+        layout.append(Span::text("a ", 1));
+        layout.append(Span::text("  b", 2));
+        layout.finalize();
+        let glyphs = layout.glyphs();
+        assert_eq!(glyphs.len(), 3);
+        (glyphs[1].user_data, glyphs[2].user_data)
+    };
+    // The space collapsed from both spans keeps the data of one of them.
+    assert_eq!(space_data(UserDataMerge::First), (1, 2));
+    assert_eq!(space_data(UserDataMerge::Last), (2, 2));
+
+    // A merge function replaces the setting, and is kept across resets until it's unset.
+    layout.set_user_data_merge(Some(|kept: u8, next: u8| kept | next));
+    let merged = |layout: &mut Layout<u8>, merge| {
+        layout.reset(&LayoutSettings::builder().collapse_whitespace(true).user_data_merge(merge).build());
+        layout.append(Span::text("a ", 1));
+        layout.append(Span::text("  b", 2));
+        layout.append(Span::text(" c", 4));
+        layout.finalize();
+        layout.glyphs().iter().map(|glyph| glyph.user_data).collect::<Vec<u8>>()
+    };
+    assert_eq!(merged(&mut layout, UserDataMerge::First), [1, 3, 2, 4, 4]);
+    assert_eq!(merged(&mut layout, UserDataMerge::Last), [1, 3, 2, 4, 4]);
+    layout.set_user_data_merge(None);
+    assert_eq!(merged(&mut layout, UserDataMerge::Last), [1, 2, 2, 4, 4]);
+}
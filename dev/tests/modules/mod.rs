@@ -1,4 +1,6 @@
 pub mod baseline_tests;
+pub mod font_tests;
+pub mod layout_tests;
 pub mod letter_render_tests;
 
 pub static FONT_NAMES: [&str; 8] = [
@@ -1,8 +1,11 @@
-use crate::layout::GlyphRasterConfig;
-use crate::math::{Geometry, Line};
-use crate::platform::{as_i32, ceil, floor, fract, is_negative};
-use crate::raster::Raster;
-use crate::table::{load_gsub, TableKern};
+use crate::layout::{CoordinateSystem, GlyphRasterConfig};
+use crate::math::{flatten_contours, Geometry, Line, OutlineCommand, OutlineRecorder, OutlineSink, Point};
+use crate::platform::{as_i32, ceil, floor, fract, is_negative, sqrt};
+use crate::raster::{Raster, Rasterizer};
+use crate::table::{
+    load_features, load_gsub, load_ligatures, load_mark_attachments, load_single_substitutions,
+    MarkAttachment, TableKern,
+};
 use crate::unicode;
 use crate::FontResult;
 use alloc::string::String;
@@ -65,9 +68,12 @@ pub struct Metrics {
     /// Whole pixel offset of the bottom-most edge of the bitmap. This may be negative to reflect
     /// the glyph is positioned below the baseline.
     pub ymin: i32,
-    /// The width of the bitmap in whole pixels.
+    /// The width of the bitmap in whole pixels. Glyphs whose bitmap would have more than 2^26
+    /// pixels, like at extremely large sizes, get an empty bitmap with a width and height of 0
+    /// instead, while their advances and bounds are kept.
     pub width: usize,
-    /// The height of the bitmap in whole pixels.
+    /// The height of the bitmap in whole pixels. This is 0 for glyphs too large to rasterize,
+    /// like the width.
     pub height: usize,
     /// Advance width of the glyph in subpixels. Used in horizontal fonts.
     pub advance_width: f32,
@@ -92,6 +98,34 @@ impl Default for Metrics {
     }
 }
 
+impl Metrics {
+    /// Returns true if the glyph has no visible ink, meaning its bitmap would be empty. This is
+    /// the case for glyphs without contours such as spaces, or glyphs whose outline has no area.
+    /// Empty glyphs don't need to be rasterized.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0 || self.bounds.width <= 0.0 || self.bounds.height <= 0.0
+    }
+}
+
+/// Metrics of a glyph in font units, as they're stored in the font without scaling. Scaled
+/// metrics are these multiplied by px / units_per_em.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct MetricsU {
+    /// The left-most edge of the glyph's bounding box.
+    pub xmin: i16,
+    /// The bottom-most edge of the glyph's bounding box.
+    pub ymin: i16,
+    /// The width of the glyph's bounding box.
+    pub width: u16,
+    /// The height of the glyph's bounding box.
+    pub height: u16,
+    /// Advance width of the glyph from `hmtx`. Used in horizontal fonts.
+    pub advance_width: u16,
+    /// Advance height of the glyph from `vmtx`. Used in vertical fonts.
+    pub advance_height: u16,
+}
+
 /// Metrics associated with line positioning.
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct LineMetrics {
@@ -134,6 +168,127 @@ impl LineMetrics {
     }
 }
 
+/// An OpenType feature listed by a font's `GSUB` or `GPOS` table.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct FontFeature {
+    /// The feature's tag, like `liga`.
+    pub tag: [u8; 4],
+    /// If the feature is needed to render the font legibly. This is true for features a language
+    /// system of the font declares as required, and for features shaping engines always apply,
+    /// like Arabic positional forms.
+    pub required: bool,
+}
+
+/// Features shaping engines apply regardless of the font or user preference.
+const ALWAYS_APPLIED_FEATURES: [&[u8; 4]; 7] =
+    [b"ccmp", b"locl", b"rlig", b"init", b"medi", b"fina", b"isol"];
+
+/// The kind of glyph outlines a font provides, in order of precedence.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OutlineKind {
+    /// Quadratic outlines from the `glyf` table.
+    TrueType,
+    /// Cubic outlines from the `CFF ` or `CFF2` table. These are rasterized like TrueType
+    /// outlines.
+    Cff,
+    /// Color bitmaps from the `CBDT` or `sbix` table, without outlines. Glyphs rasterize as empty.
+    ColorBitmap,
+    /// SVG documents from the `SVG ` table, without outlines. Glyphs rasterize as empty.
+    SvgOnly,
+    /// No supported glyph data. Glyphs rasterize as empty.
+    Missing,
+}
+
+impl OutlineKind {
+    /// Determines the outline kind from the tables present in the font.
+    fn detect(face: &Face) -> OutlineKind {
+        let has = |tag: &[u8; 4]| face.raw_face().table(Tag::from_bytes(tag)).is_some();
+        if has(b"glyf") {
+            OutlineKind::TrueType
+        } else if has(b"CFF ") || has(b"CFF2") {
+            OutlineKind::Cff
+        } else if has(b"CBDT") || has(b"sbix") {
+            OutlineKind::ColorBitmap
+        } else if has(b"SVG ") {
+            OutlineKind::SvgOnly
+        } else {
+            OutlineKind::Missing
+        }
+    }
+}
+
+/// The size and offset the font's designer intended for superscript or subscript text.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ScriptMetrics {
+    /// The size to set the text at. The units of the scale are pixels per Em unit.
+    pub px: f32,
+    /// How far the baseline of the text is raised. Negative values lower it.
+    pub rise: f32,
+}
+
+impl ScriptMetrics {
+    /// Creates script metrics from the font's values, which are missing if the size is empty.
+    /// Subscript offsets in the font are positive downwards, so they're negated.
+    fn new(metrics: Option<ttf_parser::ScriptMetrics>, lowered: bool) -> Option<ScriptMetrics> {
+        let metrics = metrics?;
+        if metrics.y_size <= 0 {
+            return None;
+        }
+        let offset = metrics.y_offset as f32;
+        Some(ScriptMetrics {
+            px: metrics.y_size as f32,
+            rise: if lowered {
+                -offset
+            } else {
+                offset
+            },
+        })
+    }
+
+    /// Scales the script metrics by the given factor.
+    #[inline(always)]
+    fn scale(&self, scale: f32) -> ScriptMetrics {
+        ScriptMetrics {
+            px: self.px * scale,
+            rise: self.rise * scale,
+        }
+    }
+}
+
+/// Metrics for a line drawn across text, such as an underline or a strikeout.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DecorationMetrics {
+    /// The offset of the top of the line from the baseline. Negative values are below the
+    /// baseline.
+    pub position: f32,
+    /// The thickness of the line.
+    pub thickness: f32,
+}
+
+impl DecorationMetrics {
+    /// Creates decoration metrics from the font's values, which are missing if the line has no
+    /// thickness.
+    fn new(metrics: Option<ttf_parser::LineMetrics>) -> Option<DecorationMetrics> {
+        let metrics = metrics?;
+        if metrics.thickness <= 0 {
+            return None;
+        }
+        Some(DecorationMetrics {
+            position: metrics.position as f32,
+            thickness: metrics.thickness as f32,
+        })
+    }
+
+    /// Scales the decoration metrics by the given factor.
+    #[inline(always)]
+    fn scale(&self, scale: f32) -> DecorationMetrics {
+        DecorationMetrics {
+            position: self.position * scale,
+            thickness: self.thickness * scale,
+        }
+    }
+}
+
 /// Stores compiled geometry and metric information.
 #[derive(Clone)]
 pub(crate) struct Glyph {
@@ -142,6 +297,13 @@ pub(crate) struct Glyph {
     advance_width: f32,
     advance_height: f32,
     pub bounds: OutlineBounds,
+    pub contours: u16,
+    /// The left and right side bearings in font units.
+    side_bearings: (f32, f32),
+    /// The advances and bounding box in font units.
+    unscaled: MetricsU,
+    /// The unflattened outline, if outlines are loaded.
+    outline: Vec<OutlineCommand>,
 }
 
 impl Default for Glyph {
@@ -152,6 +314,10 @@ impl Default for Glyph {
             advance_width: 0.0,
             advance_height: 0.0,
             bounds: OutlineBounds::default(),
+            contours: 0,
+            side_bearings: (0.0, 0.0),
+            unscaled: MetricsU::default(),
+            outline: Vec::new(),
         }
     }
 }
@@ -172,6 +338,27 @@ pub struct FontSettings {
     /// i.e. `Font::raserize_indexed`, as singular characters do not have enough context to be
     /// substituted.
     pub load_substitutions: bool,
+    /// The default is false. If enabled, the font's `.notdef` glyph is replaced with a box crossed
+    /// by an X, so missing characters stand out from boxes the font draws on purpose. Intended for
+    /// development only. Fonts loaded with this enabled have a different `file_hash`.
+    pub debug_missing: bool,
+    /// The default is false. If enabled, the outline of each loaded glyph is also kept as the
+    /// curves it's drawn with, before they're flattened for rasterization. This is needed by
+    /// `Font::glyph_polygons` and `Font::outline_commands`, and lets distance fields measure
+    /// horizontal edges. Uses more memory.
+    pub load_outlines: bool,
+    /// The default is 0. The number of pixels of transparent padding added to every side of every
+    /// glyph's bitmap, for atlases that sample beyond a glyph's edges such as SDF atlases. The
+    /// bitmap offsets and dimensions in `Metrics` include the padding, while the advances and
+    /// outline bounds are unchanged. Fonts loaded with padding have a different `file_hash`, so
+    /// `GlyphRasterConfig` keys stay consistent with it.
+    pub raster_padding: usize,
+    /// The default is false. If enabled, partial coverage is boosted at small sizes so thin stems
+    /// don't wash out, similar to FreeType's stem darkening. The boost is strongest below 9px and
+    /// fades out by 36px. It applies to the coverage bitmaps, while the unquantized coverage from
+    /// `Font::rasterize_exact` and the distance fields are unaffected. Fonts loaded with this
+    /// enabled have a different `file_hash`.
+    pub stem_darkening: bool,
 }
 
 impl Default for FontSettings {
@@ -180,20 +367,63 @@ impl Default for FontSettings {
             collection_index: 0,
             scale: 40.0,
             load_substitutions: true,
+            debug_missing: false,
+            load_outlines: false,
+            raster_padding: 0,
+            stem_darkening: false,
         }
     }
 }
 
-/// Represents a font. Fonts are immutable after creation and owns its own copy of the font data.
+/// Represents a font. Fonts are immutable after creation and own everything they need.
+///
+/// The font data is fully parsed when the font is constructed and isn't retained afterwards, so
+/// there is no lifetime tied to the source bytes. The parsed representation keeps:
+/// * The `glyf`, `CFF ` or `CFF2` outline of every glyph reachable from the `cmap` table, plus
+/// those reachable through `GSUB` when `FontSettings::load_substitutions` is enabled, compiled
+/// into line segments along with their `hmtx`/`vmtx` advances.
+/// * The character to glyph index map from `cmap`.
+/// * The line metrics from `hhea`/`OS/2` and `vhea`, and the global bounding box from `head`.
+/// * The underline and strikeout metrics from `post` and `OS/2`, and the superscript and subscript
+/// metrics from `OS/2`.
+/// * The kerning pairs from the `kern` table.
+/// * The small capital, ligature, and fraction substitutions from `GSUB`, and the mark anchors
+/// from `GPOS`.
+/// * The feature tags listed by `GSUB` and `GPOS`.
+/// * The full font name from the `name` table.
+/// * The unflattened glyph outlines, if `FontSettings::load_outlines` is enabled.
+///
+/// Every other table is discarded, so memory use scales with the number of loaded glyphs and
+/// the complexity of their outlines rather than the size of the file.
 #[derive(Clone)]
 pub struct Font {
     name: Option<String>,
     units_per_em: f32,
+    /// The loaded glyphs, by glyph index. Subsets only store the glyphs they load, after an empty
+    /// glyph that every other index resolves to, at the slots in `glyph_slots`.
     glyphs: Vec<Glyph>,
+    glyph_slots: Option<HashMap<u16, u16>>,
+    glyph_count: u16,
     char_to_glyph: HashMap<char, NonZeroU16>,
     horizontal_line_metrics: Option<LineMetrics>,
     horizontal_kern: Option<HashMap<u32, i16>>,
     vertical_line_metrics: Option<LineMetrics>,
+    underline_metrics: Option<DecorationMetrics>,
+    strikeout_metrics: Option<DecorationMetrics>,
+    superscript_metrics: Option<ScriptMetrics>,
+    subscript_metrics: Option<ScriptMetrics>,
+    outline_kind: OutlineKind,
+    features: Vec<FontFeature>,
+    cap_height: Option<f32>,
+    x_height: Option<f32>,
+    bounding_box: [f32; 4],
+    monospace_advance: Option<f32>,
+    small_caps: HashMap<u16, u16>,
+    capitals_to_small_caps: HashMap<u16, u16>,
+    ligatures: HashMap<u16, Vec<(Vec<u16>, u16)>>,
+    numerators: HashMap<u16, u16>,
+    denominators: HashMap<u16, u16>,
+    mark_attachments: Vec<MarkAttachment>,
     settings: FontSettings,
     hash: usize,
 }
@@ -215,6 +445,128 @@ impl core::fmt::Debug for Font {
     }
 }
 
+/// Replaces a px size that is negative or not finite with 0, so a bad size renders nothing
+/// instead of poisoning positions with NaN.
+#[inline(always)]
+pub(crate) fn sanitize_px(px: f32) -> f32 {
+    if px.is_finite() && px > 0.0 {
+        px
+    } else {
+        0.0
+    }
+}
+
+/// Builds a box crossed by an X to stand in for the missing glyph, keeping its advances.
+fn debug_missing_glyph(missing: &Glyph, scale: f32, units_per_em: f32) -> Glyph {
+    use ttf_parser::OutlineBuilder;
+
+    let stroke = units_per_em * 0.06;
+    let x0 = units_per_em * 0.05;
+    let x1 = (missing.advance_width - x0).max(x0 + units_per_em * 0.4);
+    let (y0, y1) = (0.0, units_per_em * 0.7);
+    // Every contour is counter-clockwise except the hole in the frame, so overlaps stay filled.
+    let contours = [
+        [(x0, y0), (x1, y0), (x1, y1), (x0, y1)],
+        [
+            (x0 + stroke, y0 + stroke),
+            (x0 + stroke, y1 - stroke),
+            (x1 - stroke, y1 - stroke),
+            (x1 - stroke, y0 + stroke),
+        ],
+        [(x0, y0), (x0 + stroke, y0), (x1, y1), (x1 - stroke, y1)],
+        [(x1 - stroke, y0), (x1, y0), (x0 + stroke, y1), (x0, y1)],
+    ];
+    let mut geometry = Geometry::new(scale, units_per_em);
+    for contour in &contours {
+        geometry.move_to(contour[0].0, contour[0].1);
+        for &(x, y) in &contour[1..] {
+            geometry.line_to(x, y);
+        }
+        geometry.close();
+    }
+    let mut glyph = Glyph {
+        advance_width: missing.advance_width,
+        advance_height: missing.advance_height,
+        unscaled: missing.unscaled,
+        outline: missing.outline.clone(),
+        ..Glyph::default()
+    };
+    geometry.finalize(&mut glyph);
+    glyph
+}
+
+/// Maps a distance into the 0..=1 range of the given spread. A spread of 0 saturates immediately.
+fn spread_ratio(distance: f32, spread: f32) -> f32 {
+    if spread > 0.0 {
+        (distance / spread).min(1.0)
+    } else {
+        1.0
+    }
+}
+
+/// How far flattened curves may stray from the outline when measuring distances to it, in px.
+const EDGE_TOLERANCE: f32 = 1.0 / 16.0;
+
+/// The glyph's outline as line segments in pixels, relative to the top left corner of its bitmap
+/// with the Y axis pointing down. The segments come from the glyph's recorded outline if it was
+/// loaded with `FontSettings::load_outlines`, so every edge is included. Otherwise they come from
+/// the lines kept for rasterization, which leave out horizontal edges as those add no coverage.
+pub(crate) fn glyph_edges(glyph: &Glyph, scale: f32, offset_x: f32, offset_y: f32) -> Vec<(Point, Point)> {
+    if glyph.outline.is_empty() {
+        return glyph
+            .v_lines
+            .iter()
+            .chain(glyph.m_lines.iter())
+            .map(|line| {
+                let (start, end) = line.points();
+                let shift = |p: Point| Point::new(p.x * scale + offset_x, p.y * scale + offset_y);
+                (shift(start), shift(end))
+            })
+            .collect();
+    }
+    let left = glyph.bounds.xmin * scale - offset_x;
+    let top = (glyph.bounds.ymin + glyph.bounds.height) * scale + offset_y;
+    let mut edges = Vec::new();
+    for ring in flatten_contours(&glyph.outline, scale, EDGE_TOLERANCE) {
+        let shift = |p: Point| Point::new(p.x - left, top - p.y);
+        for (index, &point) in ring.iter().enumerate() {
+            let next = ring[(index + 1) % ring.len()];
+            if point != next {
+                edges.push((shift(point), shift(next)));
+            }
+        }
+    }
+    edges
+}
+
+/// The most pixels a glyph's bitmap may have. Larger glyphs aren't rasterized, as their bitmap
+/// would take gigabytes or overflow its size.
+const MAX_RASTER_AREA: usize = 1 << 26;
+
+/// The size below which stem darkening is applied at full strength, in px.
+const STEM_DARKENING_FULL_PX: f32 = 9.0;
+/// The size at and above which stem darkening is no longer applied, in px.
+const STEM_DARKENING_END_PX: f32 = 36.0;
+/// How much partial coverage is boosted at full strength. Half coverage becomes 0.625.
+const STEM_DARKENING_AMOUNT: f32 = 0.5;
+
+/// Boosts the partial coverage in the bitmap by the given strength. Empty and fully covered
+/// pixels are unchanged, so the glyph's shape stays the same while its stems get heavier.
+pub(crate) fn darken_stems(bitmap: &mut [u8], strength: f32) {
+    if strength <= 0.0 {
+        return;
+    }
+    let mut table = [0u8; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        let coverage = value as f32 / 255.0;
+        let darkened = coverage + strength * coverage * (1.0 - coverage);
+        *entry = as_i32(darkened * 255.0 + 0.5).min(255) as u8;
+    }
+    for value in bitmap.iter_mut() {
+        *value = table[*value as usize];
+    }
+}
+
 /// Converts a ttf-parser FaceParsingError into a string.
 fn convert_error(error: FaceParsingError) -> &'static str {
     use FaceParsingError::*;
@@ -238,11 +590,47 @@ fn convert_name(face: &Face) -> Option<String> {
 }
 
 impl Font {
-    /// Constructs a font from an array of bytes.
+    /// Constructs a font from an array of bytes. The bytes are only read during this call, so a
+    /// borrowed slice such as a memory mapped file can be unmapped once this returns.
     pub fn from_bytes<Data: Deref<Target = [u8]>>(data: Data, settings: FontSettings) -> FontResult<Font> {
-        let hash = crate::hash::hash(&data);
+        Font::load(&data, settings, None)
+    }
+
+    /// Constructs a font from an array of bytes like `Font::from_bytes`, but only loads the glyphs
+    /// of the given characters, to save memory when only a few of a large font's characters are
+    /// needed. Composite glyphs are resolved when they're loaded, so their components don't have
+    /// to be listed. With `FontSettings::load_substitutions`, only the substitutions and
+    /// ligatures formed entirely from loaded glyphs are kept. Every other character maps to the
+    /// default glyph, and fonts loaded this way have a different `file_hash` than the full font.
+    pub fn from_bytes_subset<Data: Deref<Target = [u8]>>(
+        data: Data,
+        settings: FontSettings,
+        chars: &[char],
+    ) -> FontResult<Font> {
+        Font::load(&data, settings, Some(chars))
+    }
+
+    fn load(data: &[u8], settings: FontSettings, subset: Option<&[char]>) -> FontResult<Font> {
+        let mut hash = crate::hash::hash(data);
+        if settings.debug_missing {
+            hash = crate::hash::mix(hash, 1);
+        }
+        if settings.raster_padding > 0 {
+            hash = crate::hash::mix(hash, settings.raster_padding + 1);
+        }
+        if settings.stem_darkening {
+            hash = crate::hash::mix(hash, 2);
+        }
+        let subset: Option<HashSet<char>> = subset.map(|chars| chars.iter().copied().collect());
+        if let Some(subset) = &subset {
+            let mut chars: Vec<char> = subset.iter().copied().collect();
+            chars.sort_unstable();
+            for c in chars {
+                hash = crate::hash::mix(hash, c as usize);
+            }
+        }
 
-        let face = match Face::parse(&data, settings.collection_index) {
+        let face = match Face::parse(data, settings.collection_index) {
             Ok(f) => f,
             Err(e) => return Err(convert_error(e)),
         };
@@ -272,16 +660,60 @@ impl Font {
                 })
             }
         }
+        if let Some(subset) = &subset {
+            char_to_glyph.retain(|c, _| subset.contains(c));
+            indices_to_load.clear();
+            indices_to_load.insert(0u16);
+            indices_to_load.extend(char_to_glyph.values().map(|index| index.get()));
+        }
 
         // If the gsub table exists and the user needs it, add all of its glyphs to the glyphs we should load.
+        let mut small_caps = HashMap::new();
+        let mut capitals_to_small_caps = HashMap::new();
+        let mut ligatures = HashMap::new();
+        let mut numerators = HashMap::new();
+        let mut denominators = HashMap::new();
         if settings.load_substitutions {
-            load_gsub(&face, &mut indices_to_load);
+            if subset.is_none() {
+                load_gsub(&face, &mut indices_to_load);
+            }
+            small_caps = load_single_substitutions(&face, Tag::from_bytes(b"smcp"));
+            capitals_to_small_caps = load_single_substitutions(&face, Tag::from_bytes(b"c2sc"));
+            ligatures = load_ligatures(&face, Tag::from_bytes(b"liga"));
+            numerators = load_single_substitutions(&face, Tag::from_bytes(b"numr"));
+            denominators = load_single_substitutions(&face, Tag::from_bytes(b"dnom"));
+        }
+        if subset.is_some() {
+            // Only substitutions from the subset's glyphs are kept, and their results are loaded.
+            let base = indices_to_load.clone();
+            for map in [&mut small_caps, &mut capitals_to_small_caps, &mut numerators, &mut denominators] {
+                map.retain(|from, _| base.contains(from));
+                indices_to_load.extend(map.values().copied());
+            }
+            ligatures.retain(|first, candidates: &mut Vec<(Vec<u16>, u16)>| {
+                candidates.retain(|(components, _)| components.iter().all(|c| base.contains(c)));
+                base.contains(first) && !candidates.is_empty()
+            });
+            indices_to_load.extend(ligatures.values().flatten().map(|&(_, ligature)| ligature));
         }
 
         let units_per_em = face.units_per_em() as f32;
 
-        // Parse and store all unique codepoints.
-        let mut glyphs: Vec<Glyph> = vec::from_elem(Glyph::default(), glyph_count as usize);
+        // Parse and store all unique codepoints. Subsets store their glyphs compactly, so a few
+        // characters of a font with thousands of glyphs don't allocate a glyph for each of them.
+        let glyph_slots: Option<HashMap<u16, u16>> = subset.as_ref().map(|_| {
+            let mut indices: Vec<u16> = indices_to_load.iter().copied().collect();
+            indices.sort_unstable();
+            indices.into_iter().zip(1..).collect()
+        });
+        let slot = |index: u16| -> usize {
+            match &glyph_slots {
+                Some(slots) => slots.get(&index).map_or(0, |&slot| slot as usize),
+                None => index as usize,
+            }
+        };
+        let stored = glyph_slots.as_ref().map_or(glyph_count as usize, |slots| slots.len() + 1);
+        let mut glyphs: Vec<Glyph> = vec::from_elem(Glyph::default(), stored);
 
         let generate_glyph = |index: u16| -> Result<Glyph, &'static str> {
             if index >= glyph_count {
@@ -292,20 +724,64 @@ impl Font {
             let glyph_id = GlyphId(index);
             if let Some(advance_width) = face.glyph_hor_advance(glyph_id) {
                 glyph.advance_width = advance_width as f32;
+                glyph.unscaled.advance_width = advance_width;
             }
             if let Some(advance_height) = face.glyph_ver_advance(glyph_id) {
                 glyph.advance_height = advance_height as f32;
+                glyph.unscaled.advance_height = advance_height;
             }
 
             let mut geometry = Geometry::new(settings.scale, units_per_em);
+            // Composite glyphs are resolved here, with each component transformed into place.
+            // Component references are followed to a limited depth, so self-referential composites
+            // in malformed fonts terminate.
             face.outline_glyph(glyph_id, &mut geometry);
             geometry.finalize(&mut glyph);
+            if settings.load_outlines {
+                let mut recorder = OutlineRecorder::default();
+                face.outline_glyph(glyph_id, &mut recorder);
+                glyph.outline = recorder.commands;
+            }
+            // Color bitmap glyphs have no outline, and some fonts leave their hmtx advance at 0.
+            // The advance is then taken from the image in the largest strike, which is in pixels at
+            // the strike's size, so it scales with the px like outline metrics.
+            if glyph.contours == 0 && glyph.advance_width == 0.0 {
+                if let Some(image) = face.glyph_raster_image(glyph_id, u16::MAX) {
+                    if image.pixels_per_em > 0 && image.width > 0 {
+                        let strike_advance = image.x.max(0) as f32 + image.width as f32;
+                        glyph.advance_width = strike_advance * units_per_em / image.pixels_per_em as f32;
+                    }
+                }
+            }
+            // The glyf header has the exact bounding box. CFF outlines don't, and ttf-parser would
+            // only walk the outline again to find it, so the flattened outline's bounds are used.
+            let bounding_box = match face.tables().glyf {
+                Some(_) => face.glyph_bounding_box(glyph_id).map(|rect| {
+                    glyph.unscaled.xmin = rect.x_min;
+                    glyph.unscaled.ymin = rect.y_min;
+                    glyph.unscaled.width = (rect.x_max as i32 - rect.x_min as i32) as u16;
+                    glyph.unscaled.height = (rect.y_max as i32 - rect.y_min as i32) as u16;
+                    (rect.x_min as f32, glyph.unscaled.width as f32)
+                }),
+                None if glyph.bounds.width > 0.0 => {
+                    let bounds = glyph.bounds;
+                    glyph.unscaled.xmin = floor(bounds.xmin) as i16;
+                    glyph.unscaled.ymin = floor(bounds.ymin) as i16;
+                    glyph.unscaled.width = (ceil(bounds.xmin + bounds.width) - floor(bounds.xmin)) as u16;
+                    glyph.unscaled.height = (ceil(bounds.ymin + bounds.height) - floor(bounds.ymin)) as u16;
+                    Some((bounds.xmin, bounds.width))
+                }
+                None => None,
+            };
+            let (xmin, width) = bounding_box.unwrap_or((0.0, 0.0));
+            let lsb = face.glyph_hor_side_bearing(glyph_id).map_or(xmin, f32::from);
+            glyph.side_bearings = (lsb, glyph.advance_width - lsb - width);
             Ok(glyph)
         };
 
         #[cfg(not(feature = "parallel"))]
         for index in indices_to_load {
-            glyphs[index as usize] = generate_glyph(index)?;
+            glyphs[slot(index)] = generate_glyph(index)?;
         }
 
         #[cfg(feature = "parallel")]
@@ -315,10 +791,15 @@ impl Font {
                 .map(|index| Ok((index, generate_glyph(index)?)))
                 .collect::<Result<_, _>>()?;
             for (index, glyph) in generated {
-                glyphs[index as usize] = glyph;
+                glyphs[slot(index)] = glyph;
             }
         }
 
+        if settings.debug_missing {
+            let missing = &mut glyphs[slot(0)];
+            *missing = debug_missing_glyph(missing, settings.scale, units_per_em);
+        }
+
         // New line metrics.
         let horizontal_line_metrics =
             Some(LineMetrics::new(face.ascender(), face.descender(), face.line_gap()));
@@ -331,15 +812,84 @@ impl Font {
         } else {
             None
         };
+        let underline_metrics = DecorationMetrics::new(face.underline_metrics());
+        let strikeout_metrics = DecorationMetrics::new(face.strikeout_metrics());
+        let superscript_metrics = ScriptMetrics::new(face.superscript_metrics(), false);
+        let subscript_metrics = ScriptMetrics::new(face.subscript_metrics(), true);
+        let outline_kind = OutlineKind::detect(&face);
+        let mark_attachments = load_mark_attachments(&face);
+        let features = load_features(&face)
+            .into_iter()
+            .map(|(tag, required)| FontFeature {
+                tag,
+                required: required || ALWAYS_APPLIED_FEATURES.contains(&&tag),
+            })
+            .collect();
+        // Fonts that don't define these in OS/2 fall back to measuring a representative glyph.
+        let glyph_top = |character: char| -> Option<f32> {
+            let index = char_to_glyph.get(&character)?.get();
+            let bounds = glyphs[slot(index)].bounds;
+            if bounds.height > 0.0 {
+                Some(bounds.ymin + bounds.height)
+            } else {
+                None
+            }
+        };
+        let cap_height =
+            face.capital_height().filter(|&h| h > 0).map(|h| h as f32).or_else(|| glyph_top('H'));
+        let x_height = face.x_height().filter(|&h| h > 0).map(|h| h as f32).or_else(|| glyph_top('x'));
+        // A font is monospace if nearly every character it maps to a spacing glyph advances the
+        // same, or if it declares itself fixed pitch. Monospace fonts often have a few wider
+        // characters, like some symbols and accented letters.
+        let mut advances: Vec<f32> = char_to_glyph
+            .values()
+            .map(|index| glyphs[slot(index.get())].advance_width)
+            .filter(|&advance| advance > 0.0)
+            .collect();
+        advances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let (mut common, mut common_count, mut count) = (None, 0, 0);
+        for (i, &advance) in advances.iter().enumerate() {
+            count = if i > 0 && advances[i - 1] == advance {
+                count + 1
+            } else {
+                1
+            };
+            if count > common_count {
+                common = Some(advance);
+                common_count = count;
+            }
+        }
+        let monospace_advance =
+            common.filter(|_| common_count * 20 >= advances.len() * 19 || face.is_monospaced());
+        let rect = face.global_bounding_box();
+        let bounding_box = [rect.x_min as f32, rect.y_min as f32, rect.x_max as f32, rect.y_max as f32];
 
         Ok(Font {
             name,
             glyphs,
+            glyph_slots,
+            glyph_count,
             char_to_glyph,
             units_per_em,
             horizontal_line_metrics,
             horizontal_kern,
             vertical_line_metrics,
+            underline_metrics,
+            strikeout_metrics,
+            superscript_metrics,
+            subscript_metrics,
+            outline_kind,
+            features,
+            cap_height,
+            x_height,
+            bounding_box,
+            monospace_advance,
+            small_caps,
+            capitals_to_small_caps,
+            ligatures,
+            numerators,
+            denominators,
+            mark_attachments,
             settings,
             hash,
         })
@@ -352,6 +902,146 @@ impl Font {
         &self.char_to_glyph
     }
 
+    /// Flattens the outline of the glyph at the given index into a closed polygon per contour,
+    /// for uses like collision shapes or triangulating extruded text. This requires the font to be
+    /// loaded with `FontSettings::load_outlines`, otherwise it's empty.
+    /// # Arguments
+    ///
+    /// * `glyph_index` - The glyph index in the font to get the polygons of.
+    /// * `px` - The size to scale the polygons to. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// * `tolerance` - How far in pixels the polygons may stray from the curves they replace.
+    /// Smaller tolerances produce more points.
+    /// # Returns
+    ///
+    /// * `Vec<Vec<Point>>` - A ring of points per contour, in pixels from the glyph's origin with y
+    /// increasing upwards. The last point of each ring connects back to its first. Rings keep the
+    /// winding of the font's contours, so holes wind opposite to the shapes around them.
+    pub fn glyph_polygons(&self, glyph_index: u16, px: f32, tolerance: f32) -> Vec<Vec<Point>> {
+        flatten_contours(&self.glyph(glyph_index).outline, self.scale_factor(px), tolerance)
+    }
+
+    /// Visits the drawing commands of the glyph's outline at the given index, with its curves and
+    /// their control points as the font describes them rather than flattened into lines. This
+    /// requires the font to be loaded with `FontSettings::load_outlines`, otherwise nothing is
+    /// visited.
+    /// # Arguments
+    ///
+    /// * `glyph_index` - The glyph index in the font to visit the outline of.
+    /// * `sink` - Receives the commands in order, with points in font units.
+    pub fn outline_commands(&self, glyph_index: u16, sink: &mut impl OutlineSink) {
+        for command in &self.glyph(glyph_index).outline {
+            command.visit(sink);
+        }
+    }
+
+    /// Returns the kind of glyph outlines the font provides. Fonts without outlines rasterize
+    /// every glyph as empty, which this can be used to diagnose.
+    pub fn outline_kind(&self) -> OutlineKind {
+        self.outline_kind
+    }
+
+    /// Returns the number of contours in the outline of the glyph at the given index. Composite
+    /// glyphs are resolved, so this counts the contours of all their components. Glyphs without an
+    /// outline, like spaces, have no contours, as do glyphs that weren't loaded and indices out of
+    /// bounds. This is counted when the font is loaded, so it's cheap to query.
+    pub fn contour_count(&self, glyph_index: u16) -> usize {
+        if glyph_index < self.glyph_count {
+            self.glyph(glyph_index).contours as usize
+        } else {
+            0
+        }
+    }
+
+    /// The padding in pixels on every side of the font's glyph bitmaps, from
+    /// `FontSettings::raster_padding`.
+    pub(crate) fn raster_padding(&self) -> usize {
+        self.settings.raster_padding
+    }
+
+    /// True if the font substitutes small capitals through its `smcp` feature. Always false when
+    /// the font was loaded without `FontSettings::load_substitutions`.
+    pub(crate) fn has_small_caps(&self) -> bool {
+        !self.small_caps.is_empty()
+    }
+
+    /// Returns the small capital glyph standing in for a lowercase character, taken from the
+    /// `smcp` feature, or from the `c2sc` feature applied to its capital. Returns none if the
+    /// font doesn't substitute the character.
+    pub(crate) fn small_cap_index(&self, character: char) -> Option<u16> {
+        if !character.is_lowercase() {
+            return None;
+        }
+        let lowercase = self.lookup_glyph_index(character);
+        if let Some(&index) = self.small_caps.get(&lowercase) {
+            return Some(index);
+        }
+        let mut upper = character.to_uppercase();
+        match (upper.next(), upper.next()) {
+            (Some(capital), None) => {
+                self.capitals_to_small_caps.get(&self.lookup_glyph_index(capital)).copied()
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the ligatures from the font's `liga` feature that start with the given glyph, as
+    /// the glyphs that must follow it and the ligature glyph they form, in the order the font
+    /// prefers them. Always empty when the font was loaded without
+    /// `FontSettings::load_substitutions`.
+    pub(crate) fn ligatures(&self, first: u16) -> &[(Vec<u16>, u16)] {
+        self.ligatures.get(&first).map_or(&[], |candidates| candidates.as_slice())
+    }
+
+    /// Returns the glyph index of the ligature the font's `liga` feature forms from exactly the
+    /// given sequence of characters, or none if the sequence doesn't ligate. This is always none
+    /// when the font was loaded without `FontSettings::load_substitutions`.
+    pub fn ligature_for(&self, chars: &[char]) -> Option<u16> {
+        let (&first, rest) = chars.split_first()?;
+        let components: Vec<u16> = rest.iter().map(|&c| self.lookup_glyph_index(c)).collect();
+        if components.is_empty() || components.contains(&0) {
+            return None;
+        }
+        self.ligatures(self.lookup_glyph_index(first))
+            .iter()
+            .find(|(candidate, _)| *candidate == components)
+            .map(|&(_, ligature)| ligature)
+    }
+
+    /// True if the font substitutes fraction numerators and denominators through its `numr` and
+    /// `dnom` features. Always false when the font was loaded without
+    /// `FontSettings::load_substitutions`.
+    pub(crate) fn has_fractions(&self) -> bool {
+        !self.numerators.is_empty() && !self.denominators.is_empty()
+    }
+
+    /// Returns the numerator or denominator form of a glyph from the font's `numr` or `dnom`
+    /// feature, or none if the font doesn't substitute the glyph.
+    pub(crate) fn fraction_index(&self, glyph_index: u16, numerator: bool) -> Option<u16> {
+        let forms = if numerator {
+            &self.numerators
+        } else {
+            &self.denominators
+        };
+        forms.get(&glyph_index).copied()
+    }
+
+    /// Returns the offset from a base glyph's origin to a mark glyph's origin that places the mark
+    /// on the base's anchor, as defined by the font's `GPOS` `mark` feature. The offset is in
+    /// pixels with the Y axis pointing up. Returns none if the font doesn't position the pair.
+    pub(crate) fn mark_offset(&self, base: u16, mark: u16, px: f32) -> Option<(f32, f32)> {
+        let (x, y) = self.mark_attachments.iter().find_map(|attachment| attachment.offset(base, mark))?;
+        let scale = self.scale_factor(px);
+        Some((x * scale, y * scale))
+    }
+
+    /// Returns the OpenType features the font's `GSUB` and `GPOS` tables list, sorted by tag.
+    /// Features being listed doesn't mean layout applies them, see `layout::FeatureSet` for the
+    /// features that are supported.
+    pub fn features(&self) -> &[FontFeature] {
+        &self.features
+    }
+
     /// Returns a precomputed hash for the font file.
     pub fn file_hash(&self) -> usize {
         self.hash
@@ -369,6 +1059,20 @@ impl Font {
         Some(metrics.scale(self.scale_factor(px)))
     }
 
+    /// The recommended distance between the baselines of consecutive lines of horizontal text,
+    /// which is the `new_line_size` of the horizontal line metrics: ascent - descent + line_gap.
+    /// In a layout, a line advances by the largest of these among the fonts and sizes on it, as
+    /// its `LinePosition::max_new_line_size`, snapped to whole pixels by the layout's rounding
+    /// mode unless settings or spans override the metrics. This is 0 if the font has no horizontal
+    /// line metrics.
+    /// # Arguments
+    ///
+    /// * `px` - The size to scale the line height by. The units of the scale are pixels per Em
+    /// unit.
+    pub fn line_height(&self, px: f32) -> f32 {
+        self.horizontal_line_metrics(px).map_or(0.0, |metrics| metrics.new_line_size)
+    }
+
     /// New line metrics for fonts that append characters to lines vertically, and append new
     /// lines horizontally (left or right of the current line). Only populated for fonts with the
     /// appropriate metrics, none if it's missing.
@@ -381,6 +1085,153 @@ impl Font {
         Some(metrics.scale(self.scale_factor(px)))
     }
 
+    /// Underline position and thickness from the font's `post` table. Only populated for fonts
+    /// with the appropriate metrics, none if it's missing.
+    /// # Arguments
+    ///
+    /// * `px` - The size to scale the metrics by. The units of the scale are pixels per Em unit.
+    pub fn underline_metrics(&self, px: f32) -> Option<DecorationMetrics> {
+        let metrics = self.underline_metrics?;
+        Some(metrics.scale(self.scale_factor(px)))
+    }
+
+    /// Strikeout position and thickness from the font's `OS/2` table. Only populated for fonts
+    /// with the appropriate metrics, none if it's missing.
+    /// # Arguments
+    ///
+    /// * `px` - The size to scale the metrics by. The units of the scale are pixels per Em unit.
+    pub fn strikeout_metrics(&self, px: f32) -> Option<DecorationMetrics> {
+        let metrics = self.strikeout_metrics?;
+        Some(metrics.scale(self.scale_factor(px)))
+    }
+
+    /// Superscript size and offset from the font's `OS/2` table. Only populated for fonts with the
+    /// appropriate metrics, none if it's missing.
+    /// # Arguments
+    ///
+    /// * `px` - The size of the surrounding text to scale the metrics by. The units of the scale
+    /// are pixels per Em unit.
+    pub fn superscript_metrics(&self, px: f32) -> Option<ScriptMetrics> {
+        let metrics = self.superscript_metrics?;
+        Some(metrics.scale(self.scale_factor(px)))
+    }
+
+    /// Subscript size and offset from the font's `OS/2` table. The rise is negative when the
+    /// subscript is lowered. Only populated for fonts with the appropriate metrics, none if it's
+    /// missing.
+    /// # Arguments
+    ///
+    /// * `px` - The size of the surrounding text to scale the metrics by. The units of the scale
+    /// are pixels per Em unit.
+    pub fn subscript_metrics(&self, px: f32) -> Option<ScriptMetrics> {
+        let metrics = self.subscript_metrics?;
+        Some(metrics.scale(self.scale_factor(px)))
+    }
+
+    /// Finds where a word has to be broken so that each piece fits in the given width. Advances are
+    /// accumulated the same way layout does, so the pieces match what layout produces when
+    /// wrapping by letter. A piece always holds at least one character, even if that character is
+    /// wider than the width on its own.
+    /// # Arguments
+    ///
+    /// * `word` - The word to break.
+    /// * `px` - The size to measure the word at. The units of the scale are pixels per Em unit.
+    /// * `max_width` - The width each piece should fit in. Dimensions are in pixels.
+    /// # Returns
+    ///
+    /// * `Vec<usize>` - The byte offsets in the word where each piece after the first starts.
+    pub fn break_word(&self, word: &str, px: f32, max_width: f32) -> Vec<usize> {
+        let mut breaks = Vec::new();
+        let mut piece_start = 0;
+        let mut width = 0.0;
+        for (offset, character) in word.char_indices() {
+            let advance = ceil(self.metrics(character, px).advance_width);
+            if offset > piece_start && width + advance > max_width {
+                breaks.push(offset);
+                piece_start = offset;
+                width = 0.0;
+            }
+            width += advance;
+        }
+        breaks
+    }
+
+    /// The height of capital letters above the baseline, from the font's `OS/2` table or measured
+    /// from the 'H' glyph. None if neither is available.
+    /// # Arguments
+    ///
+    /// * `px` - The size to scale the height by. The units of the scale are pixels per Em unit.
+    pub fn cap_height(&self, px: f32) -> Option<f32> {
+        Some(self.cap_height? * self.scale_factor(px))
+    }
+
+    /// The height of lowercase letters above the baseline, from the font's `OS/2` table or
+    /// measured from the 'x' glyph. None if neither is available.
+    /// # Arguments
+    ///
+    /// * `px` - The size to scale the height by. The units of the scale are pixels per Em unit.
+    pub fn x_height(&self, px: f32) -> Option<f32> {
+        Some(self.x_height? * self.scale_factor(px))
+    }
+
+    /// The advance of a character in the font, if it's monospace. A font is monospace if at least
+    /// 95% of its characters with an advance advance the same, or if its `post` table declares it
+    /// fixed pitch. Monospace fonts can still have a few wider characters, so this is the advance
+    /// shared by the most characters.
+    /// # Arguments
+    ///
+    /// * `px` - The size to scale the advance by. The units of the scale are pixels per Em unit.
+    pub fn monospace_advance(&self, px: f32) -> Option<f32> {
+        Some(self.monospace_advance? * self.scale_factor(px))
+    }
+
+    /// The bounding box that contains every glyph in the font, as declared by the `head` table.
+    /// This is the largest extent any glyph can have, which is useful for sizing fixed cells in a
+    /// glyph cache.
+    /// # Arguments
+    ///
+    /// * `px` - The size to scale the bounding box by. The units of the scale are pixels per Em
+    /// unit.
+    /// # Returns
+    ///
+    /// * `(f32, f32, f32, f32)` - The left, bottom, right, and top edges of the bounding box,
+    /// relative to the glyph origin with positive Y up.
+    pub fn bounding_box(&self, px: f32) -> (f32, f32, f32, f32) {
+        let scale = self.scale_factor(px);
+        let [xmin, ymin, xmax, ymax] = self.bounding_box;
+        (xmin * scale, ymin * scale, xmax * scale, ymax * scale)
+    }
+
+    /// Calculates the px size that makes capital letters the given height. If the font has no cap
+    /// height, the given height is returned as is.
+    /// # Arguments
+    ///
+    /// * `cap_px` - The height capital letters should be. Dimensions are in pixels.
+    /// # Returns
+    ///
+    /// * `f32` - The size to render at. The units of the scale are pixels per Em unit.
+    pub fn px_for_cap_height(&self, cap_px: f32) -> f32 {
+        match self.cap_height {
+            Some(cap_height) => cap_px * self.units_per_em / cap_height,
+            None => cap_px,
+        }
+    }
+
+    /// Calculates the px size that makes lowercase letters the given height. If the font has no
+    /// x-height, the given height is returned as is.
+    /// # Arguments
+    ///
+    /// * `x_px` - The height lowercase letters should be. Dimensions are in pixels.
+    /// # Returns
+    ///
+    /// * `f32` - The size to render at. The units of the scale are pixels per Em unit.
+    pub fn px_for_x_height(&self, x_px: f32) -> f32 {
+        match self.x_height {
+            Some(x_height) => x_px * self.units_per_em / x_height,
+            None => x_px,
+        }
+    }
+
     /// Gets the font's units per em.
     #[inline(always)]
     pub fn units_per_em(&self) -> f32 {
@@ -388,10 +1239,10 @@ impl Font {
     }
 
     /// Calculates the glyph's outline scale factor for a given px size. The units of the scale are
-    /// pixels per Em unit.
+    /// pixels per Em unit. A px size that is negative or not finite is treated as 0.
     #[inline(always)]
     pub fn scale_factor(&self, px: f32) -> f32 {
-        px / self.units_per_em
+        sanitize_px(px) / self.units_per_em
     }
 
     /// Retrieves the horizontal scaled kerning value for two adjacent characters.
@@ -430,13 +1281,42 @@ impl Font {
         Some((*value as f32) * scale)
     }
 
+    /// Retrieves the horizontal scaled kerning of every glyph in a run against the glyph before it,
+    /// so the kerning of text that doesn't change can be computed once and reused. Like in layout,
+    /// glyphs without an advance, such as marks, aren't kerned and are skipped over when finding
+    /// the glyph before.
+    /// # Arguments
+    ///
+    /// * `glyph_indices` - The glyph indices of the run, in order.
+    /// * `px` - The size to scale the kerning values for. The units of the scale are pixels per Em
+    /// unit.
+    /// # Returns
+    ///
+    /// * `Vec<f32>` - The kerning to add before each glyph, with the same length as the run. The
+    /// first glyph's is always 0, as are those of pairs without kerning.
+    pub fn kern_run(&self, glyph_indices: &[u16], px: f32) -> Vec<f32> {
+        let mut previous: Option<u16> = None;
+        glyph_indices
+            .iter()
+            .map(|&index| {
+                if self.glyph(index).advance_width == 0.0 {
+                    return 0.0;
+                }
+                let kern =
+                    previous.and_then(|left| self.horizontal_kern_indexed(left, index, px)).unwrap_or(0.0);
+                previous = Some(index);
+                kern
+            })
+            .collect()
+    }
+
     /// Retrieves the layout metrics for the given character. If the character isn't present in the
     /// font, then the layout for the font's default character is returned instead.
     /// # Arguments
     ///
     /// * `index` - The character in the font to to generate the layout metrics for.
-    /// * `px` - The size to generate the layout metrics for the character at. Cannot be negative.
-    /// The units of the scale are pixels per Em unit.
+    /// * `px` - The size to generate the layout metrics for the character at. The units of the
+    /// scale are pixels per Em unit. Sizes that are negative or not finite are treated as 0.
     /// # Returns
     ///
     /// * `Metrics` - Sizing and positioning metadata for the glyph.
@@ -450,18 +1330,79 @@ impl Font {
     /// # Arguments
     ///
     /// * `index` - The glyph index in the font to to generate the layout metrics for.
-    /// * `px` - The size to generate the layout metrics for the glyph at. Cannot be negative. The
-    /// units of the scale are pixels per Em unit.
+    /// * `px` - The size to generate the layout metrics for the glyph at. The units of the scale
+    /// are pixels per Em unit. Sizes that are negative or not finite are treated as 0.
     /// # Returns
     ///
     /// * `Metrics` - Sizing and positioning metadata for the glyph.
     pub fn metrics_indexed(&self, index: u16, px: f32) -> Metrics {
-        let glyph = &self.glyphs[index as usize];
+        let glyph = &self.glyph(index);
         let scale = self.scale_factor(px);
         let (metrics, _, _) = self.metrics_raw(scale, glyph, 0.0);
         metrics
     }
 
+    /// Retrieves the left and right side bearings of the given character. If the character isn't
+    /// present in the font, then the side bearings of the font's default character are returned
+    /// instead.
+    /// # Arguments
+    ///
+    /// * `character` - The character in the font to get the side bearings of.
+    /// * `px` - The size to scale the side bearings to. The units of the scale are pixels per Em
+    /// unit. Sizes that are negative or not finite are treated as 0.
+    /// # Returns
+    ///
+    /// * `(f32, f32)` - The left and right side bearings in subpixels. The left side bearing is
+    /// the distance from the origin to the left edge of the glyph's bounding box, from the `hmtx`
+    /// table. The right side bearing is the distance from the right edge of the bounding box to
+    /// the advance. Either may be negative when the glyph overhangs.
+    #[inline]
+    pub fn side_bearings(&self, character: char, px: f32) -> (f32, f32) {
+        self.side_bearings_indexed(self.lookup_glyph_index(character), px)
+    }
+
+    /// Retrieves the left and right side bearings of the glyph at the given index. You normally
+    /// want to be using side_bearings(char, f32) instead, unless your glyphs are pre-indexed.
+    /// # Arguments
+    ///
+    /// * `index` - The glyph index in the font to get the side bearings of.
+    /// * `px` - The size to scale the side bearings to. The units of the scale are pixels per Em
+    /// unit. Sizes that are negative or not finite are treated as 0.
+    /// # Returns
+    ///
+    /// * `(f32, f32)` - The left and right side bearings in subpixels. The left side bearing is
+    /// the distance from the origin to the left edge of the glyph's bounding box, from the `hmtx`
+    /// table. The right side bearing is the distance from the right edge of the bounding box to
+    /// the advance. Either may be negative when the glyph overhangs.
+    pub fn side_bearings_indexed(&self, index: u16, px: f32) -> (f32, f32) {
+        let (lsb, rsb) = self.glyph(index).side_bearings;
+        let scale = self.scale_factor(px);
+        (lsb * scale, rsb * scale)
+    }
+
+    /// Retrieves the metrics of the glyph at the given index in font units, without scaling them
+    /// to a px size. The bounding box is from the `glyf` table's header. Fonts without one use the
+    /// outline's bounds, rounded outwards to whole units.
+    /// # Arguments
+    ///
+    /// * `glyph_index` - The glyph index in the font to get the metrics of.
+    /// # Returns
+    ///
+    /// * `MetricsU` - The advances and bounding box of the glyph in font units.
+    pub fn metrics_unscaled(&self, glyph_index: u16) -> MetricsU {
+        self.glyph(glyph_index).unscaled
+    }
+
+    /// How much stem darkening boosts partial coverage at the given size, or 0 for none.
+    fn stem_darkening_strength(&self, px: f32) -> f32 {
+        if !self.settings.stem_darkening {
+            return 0.0;
+        }
+        let ratio =
+            (STEM_DARKENING_END_PX - sanitize_px(px)) / (STEM_DARKENING_END_PX - STEM_DARKENING_FULL_PX);
+        ratio.max(0.0).min(1.0) * STEM_DARKENING_AMOUNT
+    }
+
     /// Internal function to generate the metrics, offset_x, and offset_y of the glyph.
     fn metrics_raw(&self, scale: f32, glyph: &Glyph, offset: f32) -> (Metrics, f32, f32) {
         let bounds = glyph.bounds.scale(scale);
@@ -473,16 +1414,25 @@ impl Font {
         if is_negative(offset_y) {
             offset_y += 1.0;
         }
+        let pad = self.settings.raster_padding;
+        // The size is checked as a float, before it can overflow the integers it's stored in.
+        let width = ceil(bounds.width + offset_x) + (pad * 2) as f32;
+        let height = ceil(bounds.height + offset_y) + (pad * 2) as f32;
+        let (width, height) = if width * height > MAX_RASTER_AREA as f32 {
+            (0, 0)
+        } else {
+            (as_i32(width) as usize, as_i32(height) as usize)
+        };
         let metrics = Metrics {
-            xmin: as_i32(floor(bounds.xmin)),
-            ymin: as_i32(floor(bounds.ymin)),
-            width: as_i32(ceil(bounds.width + offset_x)) as usize,
-            height: as_i32(ceil(bounds.height + offset_y)) as usize,
+            xmin: as_i32(floor(bounds.xmin)).saturating_sub(pad as i32),
+            ymin: as_i32(floor(bounds.ymin)).saturating_sub(pad as i32),
+            width,
+            height,
             advance_width: scale * glyph.advance_width,
             advance_height: scale * glyph.advance_height,
             bounds,
         };
-        (metrics, offset_x, offset_y)
+        (metrics, offset_x + pad as f32, offset_y + pad as f32)
     }
 
     /// Retrieves the layout rasterized bitmap for the given raster config. If the raster config's
@@ -502,14 +1452,188 @@ impl Font {
         self.rasterize_indexed(config.glyph_index, config.px)
     }
 
+    /// Rasterizes the glyph for the given raster config directly into a region of an atlas,
+    /// without allocating a separate bitmap for it. The region is the size of the glyph's bitmap,
+    /// and every pixel in it is overwritten. If the raster config's character isn't present in the
+    /// font, then the font's default character is rasterized instead.
+    /// # Arguments
+    ///
+    /// * `config` - The settings to render the character at.
+    /// * `atlas` - The atlas' coverage vector, starting at its top left corner.
+    /// * `atlas_width` - The width of the atlas in pixels, which is the length of its rows.
+    /// * `x` - The left side of the region in the atlas.
+    /// * `y` - The top side of the region in the atlas.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph, or an error if the
+    /// region doesn't fit in the atlas. The coverage is quantized the same way as rasterize_config,
+    /// though it's accumulated without SIMD.
+    pub fn rasterize_into_region(
+        &self,
+        config: GlyphRasterConfig,
+        atlas: &mut [u8],
+        atlas_width: usize,
+        x: usize,
+        y: usize,
+    ) -> FontResult<Metrics> {
+        if sanitize_px(config.px) == 0.0 {
+            return Ok(Metrics::default());
+        }
+        let glyph = &self.glyph(config.glyph_index);
+        let scale = self.scale_factor(config.px);
+        let (metrics, offset_x, offset_y) = self.metrics_raw(scale, glyph, 0.0);
+        let atlas_height = if atlas_width > 0 {
+            atlas.len() / atlas_width
+        } else {
+            0
+        };
+        if x + metrics.width > atlas_width || y + metrics.height > atlas_height {
+            return Err("The glyph's bitmap doesn't fit in the atlas at the given position.");
+        }
+        // Empty glyphs, which can still have a padded bitmap, clear their region.
+        if metrics.is_empty() {
+            for row in 0..metrics.height {
+                let start = (y + row) * atlas_width + x;
+                atlas[start..start + metrics.width].fill(0);
+            }
+            return Ok(metrics);
+        }
+        let mut canvas = Raster::new(metrics.width, metrics.height);
+        canvas.draw(&glyph, scale, scale, offset_x, offset_y);
+        canvas.write_bitmap(&mut atlas[y * atlas_width + x..], atlas_width);
+        let strength = self.stem_darkening_strength(config.px);
+        for row in 0..metrics.height {
+            let start = (y + row) * atlas_width + x;
+            darken_stems(&mut atlas[start..start + metrics.width], strength);
+        }
+        Ok(metrics)
+    }
+
+    /// Retrieves the layout rasterized bitmap for the given raster config, surrounded by transparent
+    /// padding. Padding glyphs keeps neighbors in an atlas from bleeding into each other when
+    /// sampled. If the raster config's character isn't present in the font, then the layout and
+    /// bitmap for the font's default character's raster is returned instead.
+    /// # Arguments
+    ///
+    /// * `config` - The settings to render the character at.
+    /// * `pad` - The number of pixels of padding to add to the top, right, bottom, and left sides
+    /// of the bitmap, in that order.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph. The bitmap offsets
+    /// and dimensions include the padding, while the advances and outline bounds are unchanged.
+    /// * `Vec<u8>` - Coverage vector for the glyph. Coverage is a linear scale where 0 represents
+    /// 0% coverage of that pixel by the glyph and 255 represents 100% coverage. The padding has no
+    /// coverage. The vec starts at the top left corner of the padded bitmap.
+    pub fn rasterize_config_padded(&self, config: GlyphRasterConfig, pad: [usize; 4]) -> (Metrics, Vec<u8>) {
+        let [top, right, bottom, left] = pad;
+        let (mut metrics, glyph) = self.rasterize_config(config);
+        let width = metrics.width + left + right;
+        let height = metrics.height + top + bottom;
+        let mut bitmap = vec![0; width * height];
+        if metrics.width > 0 {
+            for (y, row) in glyph.chunks_exact(metrics.width).enumerate() {
+                let start = (y + top) * width + left;
+                bitmap[start..start + metrics.width].copy_from_slice(row);
+            }
+        }
+        metrics.xmin -= left as i32;
+        metrics.ymin -= bottom as i32;
+        metrics.width = width;
+        metrics.height = height;
+        (metrics, bitmap)
+    }
+
+    /// Retrieves the layout rasterized bitmap for the given raster config, with its rows ordered to
+    /// match the given coordinate system. If the raster config's character isn't present in the
+    /// font, then the layout and bitmap for the font's default character's raster is returned
+    /// instead.
+    /// # Arguments
+    ///
+    /// * `config` - The settings to render the character at.
+    /// * `coordinate_system` - The coordinate system the bitmap rows should follow.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph.
+    /// * `Vec<u8>` - Coverage vector for the glyph. Coverage is a linear scale where 0 represents
+    /// 0% coverage of that pixel by the glyph and 255 represents 100% coverage. The vec starts at
+    /// the top left corner of the glyph for `PositiveYDown`, and at the bottom left corner of the
+    /// glyph for `PositiveYUp`.
+    pub fn rasterize_config_oriented(
+        &self,
+        config: GlyphRasterConfig,
+        coordinate_system: CoordinateSystem,
+    ) -> (Metrics, Vec<u8>) {
+        let (metrics, mut bitmap) = self.rasterize_config(config);
+        if coordinate_system == CoordinateSystem::PositiveYUp && metrics.width > 0 {
+            bitmap = bitmap.chunks_exact(metrics.width).rev().flatten().copied().collect();
+        }
+        (metrics, bitmap)
+    }
+
+    /// Retrieves the layout metrics and rasterized bitmap for the given character, mirrored
+    /// horizontally or vertically, such as for arrows in a right to left interface. If the
+    /// character isn't present in the font, then the layout and bitmap for the font's default
+    /// character is returned instead.
+    /// # Arguments
+    ///
+    /// * `character` - The character to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// * `horizontal` - If true, the glyph is mirrored left to right across the middle of its
+    /// advance, so its side bearings swap and it's placed like an unmirrored glyph. The bitmap's
+    /// offset is rounded to the nearest pixel. If false, the glyph is mirrored top to bottom across
+    /// the middle of its bitmap, which stays in place.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the mirrored glyph.
+    /// * `Vec<u8>` - Coverage vector for the glyph. Coverage is a linear scale where 0 represents
+    /// 0% coverage of that pixel by the glyph and 255 represents 100% coverage. The vec starts at
+    /// the top left corner of the glyph.
+    #[inline]
+    pub fn rasterize_mirrored(&self, character: char, px: f32, horizontal: bool) -> (Metrics, Vec<u8>) {
+        self.rasterize_indexed_mirrored(self.lookup_glyph_index(character), px, horizontal)
+    }
+
+    /// Retrieves the layout metrics and rasterized bitmap at the given index, mirrored like
+    /// `Font::rasterize_mirrored`. You normally want to be using rasterize_mirrored(char, f32,
+    /// bool) instead, unless your glyphs are pre-indexed.
+    /// # Arguments
+    ///
+    /// * `index` - The glyph index in the font to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// * `horizontal` - If true, the glyph is mirrored left to right, otherwise top to bottom.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the mirrored glyph.
+    /// * `Vec<u8>` - Coverage vector for the glyph. Coverage is a linear scale where 0 represents
+    /// 0% coverage of that pixel by the glyph and 255 represents 100% coverage. The vec starts at
+    /// the top left corner of the glyph.
+    pub fn rasterize_indexed_mirrored(&self, index: u16, px: f32, horizontal: bool) -> (Metrics, Vec<u8>) {
+        let (mut metrics, bitmap) = self.rasterize_indexed(index, px);
+        if metrics.width == 0 {
+            return (metrics, bitmap);
+        }
+        let rows = bitmap.chunks_exact(metrics.width);
+        if !horizontal {
+            return (metrics, rows.rev().flatten().copied().collect());
+        }
+        let bitmap = rows.flat_map(|row| row.iter().rev()).copied().collect();
+        let advance = as_i32(floor(metrics.advance_width + 0.5));
+        metrics.xmin = advance - metrics.xmin - metrics.width as i32;
+        metrics.bounds.xmin = metrics.advance_width - metrics.bounds.xmin - metrics.bounds.width;
+        (metrics, bitmap)
+    }
+
     /// Retrieves the layout metrics and rasterized bitmap for the given character. If the
     /// character isn't present in the font, then the layout and bitmap for the font's default
     /// character is returned instead.
     /// # Arguments
     ///
     /// * `character` - The character to rasterize.
-    /// * `px` - The size to render the character at. Cannot be negative. The units of the scale
-    /// are pixels per Em unit.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
     /// # Returns
     ///
     /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph.
@@ -521,6 +1645,25 @@ impl Font {
         self.rasterize_indexed(self.lookup_glyph_index(character), px)
     }
 
+    /// Retrieves the layout metrics and rasterized bitmap for the given character at a whole
+    /// pixel size, like `Font::rasterize`. Whole sizes convert to f32 exactly, so caches keyed by
+    /// the size never miss on sizes that only differ by float rounding.
+    /// # Arguments
+    ///
+    /// * `character` - The character to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em
+    /// unit.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph.
+    /// * `Vec<u8>` - Coverage vector for the glyph. Coverage is a linear scale where 0 represents
+    /// 0% coverage of that pixel by the glyph and 255 represents 100% coverage. The vec starts at
+    /// the top left corner of the glyph.
+    #[inline]
+    pub fn rasterize_px(&self, character: char, px: u32) -> (Metrics, Vec<u8>) {
+        self.rasterize(character, px as f32)
+    }
+
     /// Retrieves the layout rasterized bitmap for the given raster config. If the raster config's
     /// character isn't present in the font, then the layout and bitmap for the font's default
     /// character's raster is returned instead.
@@ -550,8 +1693,8 @@ impl Font {
     /// # Arguments
     ///
     /// * `character` - The character to rasterize.
-    /// * `px` - The size to render the character at. Cannot be negative. The units of the scale
-    /// are pixels per Em unit.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
     /// # Returns
     ///
     /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph.
@@ -568,8 +1711,8 @@ impl Font {
     /// # Arguments
     ///
     /// * `index` - The glyph index in the font to rasterize.
-    /// * `px` - The size to render the character at. Cannot be negative. The units of the scale
-    /// are pixels per Em unit.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
     /// # Returns
     ///
     /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph.
@@ -577,15 +1720,17 @@ impl Font {
     /// 0% coverage of that pixel by the glyph and 255 represents 100% coverage. The vec starts at
     /// the top left corner of the glyph.
     pub fn rasterize_indexed(&self, index: u16, px: f32) -> (Metrics, Vec<u8>) {
-        if px <= 0.0 {
+        if sanitize_px(px) == 0.0 {
             return (Metrics::default(), Vec::new());
         }
-        let glyph = &self.glyphs[index as usize];
+        let glyph = &self.glyph(index);
         let scale = self.scale_factor(px);
         let (metrics, offset_x, offset_y) = self.metrics_raw(scale, glyph, 0.0);
         let mut canvas = Raster::new(metrics.width, metrics.height);
         canvas.draw(&glyph, scale, scale, offset_x, offset_y);
-        (metrics, canvas.get_bitmap())
+        let mut bitmap = canvas.get_bitmap();
+        darken_stems(&mut bitmap, self.stem_darkening_strength(px));
+        (metrics, bitmap)
     }
 
     /// Retrieves the layout metrics and rasterized bitmap at the given index. You normally want to
@@ -596,8 +1741,8 @@ impl Font {
     /// # Arguments
     ///
     /// * `index` - The glyph index in the font to rasterize.
-    /// * `px` - The size to render the character at. Cannot be negative. The units of the scale
-    /// are pixels per Em unit.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
     /// # Returns
     ///
     /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph.
@@ -605,15 +1750,404 @@ impl Font {
     /// represents 0% coverage of that subpixel by the glyph and 255 represents 100% coverage. The
     /// vec starts at the top left corner of the glyph.
     pub fn rasterize_indexed_subpixel(&self, index: u16, px: f32) -> (Metrics, Vec<u8>) {
-        if px <= 0.0 {
+        if sanitize_px(px) == 0.0 {
             return (Metrics::default(), Vec::new());
         }
-        let glyph = &self.glyphs[index as usize];
+        let glyph = &self.glyph(index);
         let scale = self.scale_factor(px);
         let (metrics, offset_x, offset_y) = self.metrics_raw(scale, glyph, 0.0);
         let mut canvas = Raster::new(metrics.width * 3, metrics.height);
-        canvas.draw(&glyph, scale * 3.0, scale, offset_x, offset_y);
-        (metrics, canvas.get_bitmap())
+        // The padding in offset_x is in whole pixels, which are three subpixels wide here.
+        let pad = self.settings.raster_padding as f32;
+        canvas.draw(&glyph, scale * 3.0, scale, offset_x + pad * 2.0, offset_y);
+        let mut bitmap = canvas.get_bitmap();
+        darken_stems(&mut bitmap, self.stem_darkening_strength(px));
+        (metrics, bitmap)
+    }
+
+    /// Retrieves the layout metrics and unquantized coverage for the given character. If the
+    /// character isn't present in the font, then the layout and coverage for the font's default
+    /// character is returned instead.
+    /// # Arguments
+    ///
+    /// * `character` - The character to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph.
+    /// * `Vec<f32>` - Coverage vector for the glyph, from 0.0 for 0% coverage to 1.0 for 100%
+    /// coverage. This is the coverage the bitmap from rasterize is quantized from, where each value
+    /// is scaled by 255.9 and truncated. The vec starts at the top left corner of the glyph.
+    #[inline]
+    pub fn rasterize_exact(&self, character: char, px: f32) -> (Metrics, Vec<f32>) {
+        self.rasterize_indexed_exact(self.lookup_glyph_index(character), px)
+    }
+
+    /// Retrieves the layout metrics and unquantized coverage at the given index. You normally want
+    /// to be using rasterize_exact(char, f32) instead, unless your glyphs are pre-indexed.
+    /// # Arguments
+    ///
+    /// * `index` - The glyph index in the font to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph.
+    /// * `Vec<f32>` - Coverage vector for the glyph, from 0.0 for 0% coverage to 1.0 for 100%
+    /// coverage. This is the coverage the bitmap from rasterize_indexed is quantized from, where
+    /// each value is scaled by 255.9 and truncated. The vec starts at the top left corner of the
+    /// glyph.
+    pub fn rasterize_indexed_exact(&self, index: u16, px: f32) -> (Metrics, Vec<f32>) {
+        if sanitize_px(px) == 0.0 {
+            return (Metrics::default(), Vec::new());
+        }
+        let glyph = &self.glyph(index);
+        let scale = self.scale_factor(px);
+        let (metrics, offset_x, offset_y) = self.metrics_raw(scale, glyph, 0.0);
+        let mut canvas = Raster::new(metrics.width, metrics.height);
+        canvas.draw(&glyph, scale, scale, offset_x, offset_y);
+        (metrics, canvas.get_coverage())
+    }
+
+    /// Scan converts the given character into a `Rasterizer`, which exposes the data its coverage
+    /// is built from for custom effects. If the character isn't present in the font, then the
+    /// font's default character is used instead.
+    /// # Arguments
+    ///
+    /// * `character` - The character to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    pub fn rasterizer(&self, character: char, px: f32) -> Rasterizer {
+        self.rasterizer_indexed(self.lookup_glyph_index(character), px)
+    }
+
+    /// Scan converts the glyph at the given index into a `Rasterizer`, which exposes the data its
+    /// coverage is built from for custom effects. You normally want to be using
+    /// rasterizer(char, f32) instead, unless your glyphs are pre-indexed.
+    /// # Arguments
+    ///
+    /// * `index` - The glyph index in the font to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    pub fn rasterizer_indexed(&self, index: u16, px: f32) -> Rasterizer {
+        if sanitize_px(px) == 0.0 {
+            return Rasterizer::new(&Glyph::default(), Metrics::default(), 0.0, 0.0, 0.0, 0.0);
+        }
+        let glyph = &self.glyph(index);
+        let scale = self.scale_factor(px);
+        let (metrics, offset_x, offset_y) = self.metrics_raw(scale, glyph, 0.0);
+        Rasterizer::new(glyph, metrics, scale, offset_x, offset_y, self.stem_darkening_strength(px))
+    }
+
+    /// Retrieves the layout metrics and signed distance field for the given character. If the
+    /// character isn't present in the font, then the layout and distance field for the font's
+    /// default character is returned instead. Distances to horizontal edges are only measured if
+    /// the font was loaded with `FontSettings::load_outlines`.
+    /// # Arguments
+    ///
+    /// * `character` - The character to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// * `inside_spread` - The distance in pixels inside the outline over which the field ramps
+    /// from 128 up to 255.
+    /// * `outside_spread` - The distance in pixels outside the outline over which the field ramps
+    /// from 128 down to 0.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph. The bitmap is padded
+    /// on every side by the larger of the two spreads, rounded up to a whole pixel.
+    /// * `Vec<u8>` - Distance field for the glyph. The outline sits at 128, with values above it
+    /// inside the glyph and values below it outside. The vec starts at the top left corner of the
+    /// glyph.
+    #[inline]
+    pub fn rasterize_sdf(
+        &self,
+        character: char,
+        px: f32,
+        inside_spread: f32,
+        outside_spread: f32,
+    ) -> (Metrics, Vec<u8>) {
+        self.rasterize_indexed_sdf(self.lookup_glyph_index(character), px, inside_spread, outside_spread)
+    }
+
+    /// Retrieves the layout metrics and signed distance field at the given index. You normally
+    /// want to be using rasterize_sdf(char, ...) instead, unless your glyphs are pre-indexed.
+    /// # Arguments
+    ///
+    /// * `index` - The glyph index in the font to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// * `inside_spread` - The distance in pixels inside the outline over which the field ramps
+    /// from 128 up to 255.
+    /// * `outside_spread` - The distance in pixels outside the outline over which the field ramps
+    /// from 128 down to 0.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph. The bitmap is padded
+    /// on every side by the larger of the two spreads, rounded up to a whole pixel.
+    /// * `Vec<u8>` - Distance field for the glyph. The outline sits at 128, with values above it
+    /// inside the glyph and values below it outside. The vec starts at the top left corner of the
+    /// glyph.
+    pub fn rasterize_indexed_sdf(
+        &self,
+        index: u16,
+        px: f32,
+        inside_spread: f32,
+        outside_spread: f32,
+    ) -> (Metrics, Vec<u8>) {
+        let inside_spread = inside_spread.max(0.0);
+        let outside_spread = outside_spread.max(0.0);
+        let pad = as_i32(ceil(inside_spread.max(outside_spread))) as usize;
+        let (metrics, distances) = self.signed_distances(index, px, pad);
+        let bitmap = distances
+            .iter()
+            .map(|&distance| {
+                let value = if distance > 0.0 {
+                    0.5 + 0.5 * spread_ratio(distance, inside_spread)
+                } else {
+                    0.5 - 0.5 * spread_ratio(-distance, outside_spread)
+                };
+                as_i32(value * 255.0 + 0.5).min(255) as u8
+            })
+            .collect();
+        (metrics, bitmap)
+    }
+
+    /// Retrieves the layout metrics and bevel height map for the given character, for lighting
+    /// embossed text as a bump map. If the character isn't present in the font, then the layout
+    /// and height map for the font's default character is returned instead. Distances to
+    /// horizontal edges are only measured if the font was loaded with `FontSettings::load_outlines`.
+    /// # Arguments
+    ///
+    /// * `character` - The character to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// * `depth` - The distance in pixels inside the outline over which the bevel rises from 0 up
+    /// to 255.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph. The bitmap has the
+    /// same bounds as rasterize, so the two can be paired.
+    /// * `Vec<u8>` - Height map for the glyph. Pixels outside the glyph are 0, and pixels inside
+    /// rise with their distance from the outline, flattening out at the depth. The vec starts at
+    /// the top left corner of the glyph.
+    #[inline]
+    pub fn rasterize_bevel(&self, character: char, px: f32, depth: f32) -> (Metrics, Vec<u8>) {
+        self.rasterize_indexed_bevel(self.lookup_glyph_index(character), px, depth)
+    }
+
+    /// Retrieves the layout metrics and bevel height map at the given index. You normally want to
+    /// be using rasterize_bevel(char, ...) instead, unless your glyphs are pre-indexed.
+    /// # Arguments
+    ///
+    /// * `index` - The glyph index in the font to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// * `depth` - The distance in pixels inside the outline over which the bevel rises from 0 up
+    /// to 255.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph. The bitmap has the
+    /// same bounds as rasterize_indexed, so the two can be paired.
+    /// * `Vec<u8>` - Height map for the glyph. Pixels outside the glyph are 0, and pixels inside
+    /// rise with their distance from the outline, flattening out at the depth. The vec starts at
+    /// the top left corner of the glyph.
+    pub fn rasterize_indexed_bevel(&self, index: u16, px: f32, depth: f32) -> (Metrics, Vec<u8>) {
+        let depth = depth.max(0.0);
+        let (metrics, distances) = self.signed_distances(index, px, 0);
+        let bitmap = distances
+            .iter()
+            .map(|&distance| {
+                if distance > 0.0 {
+                    as_i32(spread_ratio(distance, depth) * 255.0 + 0.5).min(255) as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+        (metrics, bitmap)
+    }
+
+    /// Measures the distance from each pixel center of the glyph's bitmap, padded by the given
+    /// number of pixels on every side, to the nearest point on its outline. Distances are
+    /// positive inside the glyph and negative outside. The metrics are expanded by the padding.
+    fn signed_distances(&self, index: u16, px: f32, pad: usize) -> (Metrics, Vec<f32>) {
+        if sanitize_px(px) == 0.0 {
+            return (Metrics::default(), Vec::new());
+        }
+        let glyph = &self.glyph(index);
+        let scale = self.scale_factor(px);
+        let (mut metrics, offset_x, offset_y) = self.metrics_raw(scale, glyph, 0.0);
+        let (w, h) = (metrics.width, metrics.height);
+        if w == 0 || h == 0 {
+            return (metrics, Vec::new());
+        }
+        // The coverage only decides which side of the outline a pixel center is on.
+        let mut canvas = Raster::new(w, h);
+        canvas.draw(&glyph, scale, scale, offset_x, offset_y);
+        let coverage = canvas.get_bitmap();
+        // Outline segments in the pixel space of the padded bitmap.
+        let segments = glyph_edges(glyph, scale, offset_x + pad as f32, offset_y + pad as f32);
+        let width = w + pad * 2;
+        let height = h + pad * 2;
+        let mut distances = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let center = Point::new(x as f32 + 0.5, y as f32 + 0.5);
+                let mut nearest = core::f32::MAX;
+                for &(start, end) in &segments {
+                    nearest = nearest.min(center.distance_squared_to_segment(start, end));
+                }
+                let distance = sqrt(nearest);
+                let inside = x >= pad
+                    && y >= pad
+                    && x - pad < w
+                    && y - pad < h
+                    && coverage[(y - pad) * w + (x - pad)] >= 128;
+                distances.push(if inside {
+                    distance
+                } else {
+                    -distance
+                });
+            }
+        }
+        metrics.xmin -= pad as i32;
+        metrics.ymin -= pad as i32;
+        metrics.width = width;
+        metrics.height = height;
+        (metrics, distances)
+    }
+
+    /// Retrieves the layout metrics and rasterized bitmap for the given character, with a drop
+    /// shadow baked into the bitmap. If the character isn't present in the font, then the layout
+    /// and bitmap for the font's default character is returned instead.
+    /// # Arguments
+    ///
+    /// * `character` - The character to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// * `dx` - The horizontal offset of the shadow in whole pixels. Positive values move the
+    /// shadow right.
+    /// * `dy` - The vertical offset of the shadow in whole pixels. Positive values move the
+    /// shadow down.
+    /// * `shadow_alpha` - The opacity of the shadow, where 255 is as opaque as the glyph.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph. The bitmap offsets
+    /// and dimensions are expanded to include the shadow, while the advances and outline bounds
+    /// describe the glyph alone.
+    /// * `Vec<u8>` - Coverage vector for the glyph and its shadow. Where they overlap, the higher
+    /// of the two coverages is kept. The vec starts at the top left corner of the bitmap.
+    #[inline]
+    pub fn rasterize_with_shadow(
+        &self,
+        character: char,
+        px: f32,
+        dx: i32,
+        dy: i32,
+        shadow_alpha: u8,
+    ) -> (Metrics, Vec<u8>) {
+        self.rasterize_indexed_with_shadow(self.lookup_glyph_index(character), px, dx, dy, shadow_alpha)
+    }
+
+    /// Retrieves the layout metrics and rasterized bitmap at the given index, with a drop shadow
+    /// baked into the bitmap. You normally want to be using rasterize_with_shadow(char, ...)
+    /// instead, unless your glyphs are pre-indexed.
+    /// # Arguments
+    ///
+    /// * `index` - The glyph index in the font to rasterize.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// * `dx` - The horizontal offset of the shadow in whole pixels. Positive values move the
+    /// shadow right.
+    /// * `dy` - The vertical offset of the shadow in whole pixels. Positive values move the
+    /// shadow down.
+    /// * `shadow_alpha` - The opacity of the shadow, where 255 is as opaque as the glyph.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the rasterized glyph. The bitmap offsets
+    /// and dimensions are expanded to include the shadow, while the advances and outline bounds
+    /// describe the glyph alone.
+    /// * `Vec<u8>` - Coverage vector for the glyph and its shadow. Where they overlap, the higher
+    /// of the two coverages is kept. The vec starts at the top left corner of the bitmap.
+    pub fn rasterize_indexed_with_shadow(
+        &self,
+        index: u16,
+        px: f32,
+        dx: i32,
+        dy: i32,
+        shadow_alpha: u8,
+    ) -> (Metrics, Vec<u8>) {
+        let (mut metrics, glyph) = self.rasterize_indexed(index, px);
+        if glyph.is_empty() {
+            return (metrics, glyph);
+        }
+        let (w, h) = (metrics.width, metrics.height);
+        let width = w + dx.unsigned_abs() as usize;
+        let height = h + dy.unsigned_abs() as usize;
+        // Offsets of the glyph and the shadow into the expanded bitmap.
+        let (glyph_x, shadow_x) = if dx < 0 {
+            (dx.unsigned_abs() as usize, 0)
+        } else {
+            (0, dx as usize)
+        };
+        let (glyph_y, shadow_y) = if dy < 0 {
+            (dy.unsigned_abs() as usize, 0)
+        } else {
+            (0, dy as usize)
+        };
+        let mut bitmap = vec![0; width * height];
+        for (y, row) in glyph.chunks_exact(w).enumerate() {
+            let shadow_row = (y + shadow_y) * width + shadow_x;
+            for (x, &coverage) in row.iter().enumerate() {
+                bitmap[shadow_row + x] = (coverage as u32 * shadow_alpha as u32 / 255) as u8;
+            }
+        }
+        for (y, row) in glyph.chunks_exact(w).enumerate() {
+            let glyph_row = (y + glyph_y) * width + glyph_x;
+            for (x, &coverage) in row.iter().enumerate() {
+                let pixel = &mut bitmap[glyph_row + x];
+                *pixel = (*pixel).max(coverage);
+            }
+        }
+        metrics.xmin += dx.min(0);
+        metrics.ymin -= dy.max(0);
+        metrics.width = width;
+        metrics.height = height;
+        (metrics, bitmap)
+    }
+
+    /// Computes the total ink of the given character when rasterized, which is the sum of every
+    /// coverage value in its bitmap. If the character isn't present in the font, then the ink for
+    /// the font's default character is returned instead.
+    /// # Arguments
+    ///
+    /// * `character` - The character to measure.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// # Returns
+    ///
+    /// * `u64` - The sum of the coverage bytes of the rasterized glyph.
+    #[inline]
+    pub fn ink_coverage(&self, character: char, px: f32) -> u64 {
+        self.ink_coverage_indexed(self.lookup_glyph_index(character), px)
+    }
+
+    /// Computes the total ink of the glyph at the given index when rasterized, which is the sum of
+    /// every coverage value in its bitmap. You normally want to be using ink_coverage(char, px)
+    /// instead, unless your glyphs are pre-indexed.
+    /// # Arguments
+    ///
+    /// * `index` - The glyph index in the font to measure.
+    /// * `px` - The size to render the character at. The units of the scale are pixels per Em unit.
+    /// Sizes that are negative or not finite are treated as 0.
+    /// # Returns
+    ///
+    /// * `u64` - The sum of the coverage bytes of the rasterized glyph.
+    pub fn ink_coverage_indexed(&self, index: u16, px: f32) -> u64 {
+        let (_, bitmap) = self.rasterize_indexed(index, px);
+        bitmap.iter().map(|&coverage| coverage as u64).sum()
     }
 
     /// Checks if the font has a glyph for the given character.
@@ -622,6 +2156,15 @@ impl Font {
         self.lookup_glyph_index(character) != 0
     }
 
+    /// Checks if the font can display the given character, for deciding whether to fall back to
+    /// another font. Unlike has_glyph, this is also true for characters that are never drawn, like
+    /// control characters and zero width formatting characters, even when the font maps them to
+    /// the missing glyph (`.notdef`). Characters the font maps to a real glyph are always present,
+    /// even if that glyph is blank, like most spaces.
+    pub fn glyph_is_present(&self, character: char) -> bool {
+        self.has_glyph(character) || unicode::is_default_ignorable(character)
+    }
+
     /// Finds the internal glyph index for the given character. If the character is not present in
     /// the font then 0 is returned.
     #[inline]
@@ -632,6 +2175,23 @@ impl Font {
 
     /// Gets the total glyphs in the font.
     pub fn glyph_count(&self) -> u16 {
-        self.glyphs.len() as u16
+        self.glyph_count
+    }
+
+    /// Gets the number of glyphs stored by the font. This is the glyph count, except for fonts
+    /// loaded with `Font::from_bytes_subset`, which only store the glyphs they load.
+    pub fn loaded_glyph_count(&self) -> usize {
+        match &self.glyph_slots {
+            Some(slots) => slots.len(),
+            None => self.glyphs.len(),
+        }
+    }
+
+    /// Gets the glyph at the given index. Glyphs a subset didn't load are empty.
+    fn glyph(&self, index: u16) -> &Glyph {
+        match &self.glyph_slots {
+            Some(slots) => &self.glyphs[slots.get(&index).map_or(0, |&slot| slot as usize)],
+            None => &self.glyphs[index as usize],
+        }
     }
 }
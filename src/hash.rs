@@ -121,3 +121,26 @@ fn write(initial_state: usize, mut bytes: &[u8]) -> usize {
 pub fn hash(bytes: &[u8]) -> usize {
     write(0usize, bytes)
 }
+
+/// Mixes an additional word into an existing hash.
+pub fn mix(mut hash: usize, word: usize) -> usize {
+    hash.hash_word(word);
+    hash
+}
+
+/// A `Hasher` over the Fx hash. Unlike the standard library's default hasher, it isn't randomly
+/// seeded, so hashes are stable across runs.
+#[derive(Default)]
+pub struct FxHasher {
+    hash: usize,
+}
+
+impl core::hash::Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.hash = write(self.hash, bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash as u64
+    }
+}
@@ -1,16 +1,26 @@
-pub use crate::unicode::CharacterData;
+pub use crate::unicode::{CharacterData, Script};
 
-use crate::unicode::{read_utf8, LinebreakData, Linebreaker, LINEBREAK_NONE};
+use crate::font::sanitize_px;
+use crate::hash::FxHasher;
+use crate::unicode::{
+    self, read_utf8, LinebreakData, Linebreaker, LINEBREAK_HARD, LINEBREAK_NONE, LINEBREAK_SOFT,
+};
 use crate::Font;
 use crate::{
-    platform::{ceil, floor},
-    Metrics,
+    platform::{as_i32, ceil, floor},
+    DecorationMetrics, Metrics, OutlineBounds, ScriptMetrics,
 };
+use alloc::string::String;
+use alloc::vec;
 use alloc::vec::*;
+use core::cmp::Ordering;
 use core::hash::{Hash, Hasher};
+use core::mem;
+use core::ops::Range;
+use hashbrown::{HashMap, HashSet};
 
 /// Horizontal alignment options for text when a max_width is provided.
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum HorizontalAlign {
     /// Aligns text to the left of the region defined by the max_width.
     Left,
@@ -18,7 +28,10 @@ pub enum HorizontalAlign {
     Center,
     /// Aligns text to the right of the region defined by the max_width.
     Right,
-    /// Aligns text to the left of the region defined by the max_width and justifies it.
+    /// Aligns text to the left of the region defined by the max_width and justifies it. Wrapped
+    /// lines are stretched to the max_width by widening their spaces, with wide spaces like the
+    /// ideographic space widening more than regular ones, narrow spaces less, and no-break spaces
+    /// not at all.
     Justify,
 }
 
@@ -56,9 +69,158 @@ pub enum CoordinateSystem {
     PositiveYDown,
 }
 
+/// Which user data a glyph keeps when it's formed from characters of more than one span.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UserDataMerge {
+    /// The glyph keeps the user data of the span of its first character.
+    First,
+    /// The glyph takes the user data of the span of its last character.
+    Last,
+}
+
+impl Default for UserDataMerge {
+    fn default() -> UserDataMerge {
+        UserDataMerge::First
+    }
+}
+
+/// How positions and advances are snapped to the pixel grid during layout.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RoundMode {
+    /// Positions are rounded down and advances are rounded up, so glyphs never overlap.
+    Floor,
+    /// Positions and advances are rounded to the nearest pixel.
+    Round,
+    /// Positions and advances are rounded up.
+    Ceil,
+    /// Nothing is snapped, and glyphs are placed at fractional positions.
+    None,
+}
+
+impl Default for RoundMode {
+    fn default() -> RoundMode {
+        RoundMode::Floor
+    }
+}
+
+impl RoundMode {
+    /// Snaps a position, like the x coordinate of a glyph.
+    fn position(self, value: f32) -> f32 {
+        match self {
+            RoundMode::Floor => floor(value),
+            RoundMode::Round => floor(value + 0.5),
+            RoundMode::Ceil => ceil(value),
+            RoundMode::None => value,
+        }
+    }
+
+    /// Snaps the advance of a glyph.
+    fn advance(self, value: f32) -> f32 {
+        match self {
+            RoundMode::Floor | RoundMode::Ceil => ceil(value),
+            RoundMode::Round => floor(value + 0.5),
+            RoundMode::None => value,
+        }
+    }
+
+    /// Snaps an adjustment that may be negative, like kerning, to the nearest pixel.
+    fn offset(self, value: f32) -> f32 {
+        match self {
+            RoundMode::None => value,
+            _ => floor(value + 0.5),
+        }
+    }
+}
+
+/// A set of OpenType features to apply during layout, similar to `font-feature-settings` in CSS.
+/// Features the font doesn't provide are ignored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FeatureSet {
+    bits: u8,
+}
+
+impl FeatureSet {
+    /// No features.
+    pub const NONE: FeatureSet = FeatureSet {
+        bits: 0,
+    };
+    /// `kern`: Adjusts the space between pairs of glyphs with the font's `kern` table.
+    pub const KERN: FeatureSet = FeatureSet {
+        bits: 0b0000_0001,
+    };
+    /// `liga`: Replaces sequences of glyphs with standard ligatures, like "fi".
+    pub const LIGA: FeatureSet = FeatureSet {
+        bits: 0b0000_0010,
+    };
+    /// `mark`: Places combining marks on the anchors of the glyph they follow.
+    pub const MARK: FeatureSet = FeatureSet {
+        bits: 0b0000_0100,
+    };
+    /// `smcp`: Sets all text in small capitals, as if every span had small caps enabled.
+    pub const SMCP: FeatureSet = FeatureSet {
+        bits: 0b0000_1000,
+    };
+    /// `frac`: Sets runs of digits separated by a slash, like "1/2", as fractions. Fonts without
+    /// `numr` and `dnom` forms get a raised, smaller numerator and a smaller denominator.
+    pub const FRAC: FeatureSet = FeatureSet {
+        bits: 0b0001_0000,
+    };
+
+    const TAGS: [(&'static [u8; 4], FeatureSet); 5] = [
+        (b"kern", FeatureSet::KERN),
+        (b"liga", FeatureSet::LIGA),
+        (b"mark", FeatureSet::MARK),
+        (b"smcp", FeatureSet::SMCP),
+        (b"frac", FeatureSet::FRAC),
+    ];
+
+    /// Gets the feature with the given OpenType tag, or none if the feature isn't supported.
+    pub fn from_tag(tag: &[u8; 4]) -> Option<FeatureSet> {
+        FeatureSet::TAGS.iter().find(|(t, _)| *t == tag).map(|(_, feature)| *feature)
+    }
+
+    /// Gets the OpenType tags of the features in the set.
+    pub fn tags(&self) -> Vec<&'static [u8; 4]> {
+        FeatureSet::TAGS.iter().filter(|(_, feature)| self.contains(*feature)).map(|(tag, _)| *tag).collect()
+    }
+
+    /// Checks if every feature in the given set is enabled.
+    pub fn contains(&self, features: FeatureSet) -> bool {
+        self.bits & features.bits == features.bits
+    }
+
+    /// Enables the features in the given set.
+    pub fn insert(&mut self, features: FeatureSet) {
+        self.bits |= features.bits;
+    }
+
+    /// Disables the features in the given set.
+    pub fn remove(&mut self, features: FeatureSet) {
+        self.bits &= !features.bits;
+    }
+}
+
+impl Default for FeatureSet {
+    /// Kerning, standard ligatures, and mark positioning. Ligatures weren't formed before this set
+    /// existed, so text that should keep one glyph per character needs `FeatureSet::LIGA` removed.
+    fn default() -> FeatureSet {
+        FeatureSet::KERN | FeatureSet::LIGA | FeatureSet::MARK
+    }
+}
+
+impl core::ops::BitOr for FeatureSet {
+    type Output = FeatureSet;
+
+    fn bitor(self, rhs: FeatureSet) -> FeatureSet {
+        FeatureSet {
+            bits: self.bits | rhs.bits,
+        }
+    }
+}
+
 /// Settings to configure how text layout is constrained. Text layout is considered best effort and
 /// layout may violate the constraints defined here if they prevent text from being laid out.
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone)]
 pub struct LayoutSettings {
     /// The leftmost boundary of the text region.
     pub x: f32,
@@ -69,6 +231,14 @@ pub struct LayoutSettings {
     /// max_width, the glyph will overflow past the max_width. The application is responsible for
     /// handling the overflow.
     pub max_width: Option<f32>,
+    /// The default is None. If set and the base font is monospace, the max_width is instead this
+    /// many of the base font's advances at the base px when the layout is reset, so lines wrap at
+    /// column boundaries. If the base font isn't monospace, this is ignored and max_width is used.
+    pub max_columns: Option<usize>,
+    /// The default is true. If disabled, lines are never wrapped to fit the max_width, which then
+    /// only aligns the text and bounds the region, so text longer than it overflows. Hard breaks
+    /// still start new lines if wrap_hard_breaks is enabled. Useful for single line labels.
+    pub wrap: bool,
     /// An optional bottom boundary on the text region. This is used for positioning the
     /// vertical_align option. Text that exceeds the defined max_height will overflow past it. The
     /// application is responsible for handling the overflow.
@@ -84,6 +254,101 @@ pub struct LayoutSettings {
     /// The default is true. This option enables hard breaks, like new line characters, to
     /// prematurely wrap lines. If false, hard breaks will not prematurely create a new line.
     pub wrap_hard_breaks: bool,
+    /// The default is false. If enabled, whitespace and control characters are drawn as visible
+    /// symbols while keeping their original advance: new lines as ¶, tabs as →, spaces as ·, and
+    /// anything else as ¤. Characters whose symbol is missing from the font are left invisible.
+    /// Trailing whitespace at the end of a line is kept in the output so its symbols are shown.
+    pub show_invisibles: bool,
+    /// The default is None. If set, this gap in pixels is left between the descent of one line and
+    /// the ascent of the next instead of the line gap of each span's font. This is applied before
+    /// any line height multiplier.
+    pub line_gap_override: Option<f32>,
+    /// The default is None. If set, lines extend this many pixels above their baseline instead of
+    /// the ascent of each span's font, so fallback fonts with different metrics produce the same
+    /// line boxes. This is applied before any line height multiplier.
+    pub ascent_override: Option<f32>,
+    /// The default is None. If set, lines extend to this many pixels relative to their baseline
+    /// instead of the descent of each span's font. Like the font's descent, this is usually
+    /// negative. This is applied before any line height multiplier.
+    pub descent_override: Option<f32>,
+    /// The default is None. If set, lines advance by at least this many pixels, even if all of
+    /// their spans are smaller. Extra space is left below the line's descent. This is applied
+    /// before any line height multiplier.
+    pub min_line_height: Option<f32>,
+    /// The default is false. If enabled, runs of whitespace are collapsed into their first
+    /// character, like `white-space: normal` in CSS. The omitted characters are listed by
+    /// `Layout::omitted`. Hard breaks inside a collapsed run still break the line if
+    /// wrap_hard_breaks is enabled.
+    pub collapse_whitespace: bool,
+    /// The default enables kern, liga, and mark. The OpenType features applied to the text. With
+    /// liga enabled, a ligature is a single glyph for several characters, so there can be fewer
+    /// glyphs than characters.
+    pub features: FeatureSet,
+    /// The default is Floor. How glyph positions and advances are snapped to the pixel grid.
+    pub rounding: RoundMode,
+    /// The default is None. If set, this is called with each word that would overflow the
+    /// max_width, and returns the byte offsets into the word where it may be hyphenated. The word
+    /// is then broken at the last of those points that still fits, ending the line with a hyphen.
+    /// Words split across spans aren't hyphenated. This option does nothing unless the wrap_style
+    /// is Word and the max_width is set.
+    pub hyphenator: Option<fn(&str) -> Vec<usize>>,
+    /// The default is First. Which user data a glyph formed from characters of several spans
+    /// keeps, unless a function to combine them is set with `Layout::set_user_data_merge`.
+    /// Ligatures only form within a span, so this applies to runs of whitespace collapsed across
+    /// spans.
+    pub user_data_merge: UserDataMerge,
+    /// The default is false. If enabled, text ending in a hard break, like a new line character,
+    /// ends with an empty line after it, like the line a text editor's cursor moves to. Otherwise
+    /// that line is only started once more text is appended. This does nothing if
+    /// wrap_hard_breaks is disabled.
+    pub trailing_newline_line: bool,
+    /// The default is None. If set, each line's baseline is moved down to the next multiple of
+    /// this many pixels below the y of the layout when finalized, so columns laid out from the
+    /// same y share baselines. Lines taller than the grid span several of its steps. The height of
+    /// the layout doesn't include the extra space, so vertical alignment is approximate.
+    pub baseline_grid: Option<f32>,
+    /// The default is false. If enabled, appended spans are recorded, copying their text, so
+    /// `Layout::relayout` can lay them out again with new settings.
+    pub record_spans: bool,
+}
+
+impl LayoutSettings {
+    /// Creates a builder for layout settings, starting from the defaults.
+    pub fn builder() -> LayoutSettingsBuilder {
+        LayoutSettingsBuilder {
+            settings: LayoutSettings::default(),
+        }
+    }
+}
+
+// Hyphenators are compared by address, so copies of the same function in different codegen units
+// may compare unequal.
+impl PartialEq for LayoutSettings {
+    fn eq(&self, other: &LayoutSettings) -> bool {
+        self.x == other.x
+            && self.y == other.y
+            && self.max_width == other.max_width
+            && self.max_columns == other.max_columns
+            && self.wrap == other.wrap
+            && self.max_height == other.max_height
+            && self.horizontal_align == other.horizontal_align
+            && self.vertical_align == other.vertical_align
+            && self.wrap_style == other.wrap_style
+            && self.wrap_hard_breaks == other.wrap_hard_breaks
+            && self.show_invisibles == other.show_invisibles
+            && self.line_gap_override == other.line_gap_override
+            && self.ascent_override == other.ascent_override
+            && self.descent_override == other.descent_override
+            && self.min_line_height == other.min_line_height
+            && self.collapse_whitespace == other.collapse_whitespace
+            && self.features == other.features
+            && self.rounding == other.rounding
+            && self.hyphenator.map(|f| f as usize) == other.hyphenator.map(|f| f as usize)
+            && self.user_data_merge == other.user_data_merge
+            && self.trailing_newline_line == other.trailing_newline_line
+            && self.baseline_grid == other.baseline_grid
+            && self.record_spans == other.record_spans
+    }
 }
 
 impl Default for LayoutSettings {
@@ -92,17 +357,66 @@ impl Default for LayoutSettings {
             x: 0.0,
             y: 0.0,
             max_width: None,
+            max_columns: None,
+            wrap: true,
             max_height: None,
             horizontal_align: HorizontalAlign::Left,
             vertical_align: VerticalAlign::Top,
             wrap_style: WrapStyle::Word,
             wrap_hard_breaks: true,
+            show_invisibles: false,
+            line_gap_override: None,
+            ascent_override: None,
+            descent_override: None,
+            min_line_height: None,
+            collapse_whitespace: false,
+            features: FeatureSet::default(),
+            rounding: RoundMode::Floor,
+            hyphenator: None,
+            user_data_merge: UserDataMerge::First,
+            trailing_newline_line: false,
+            baseline_grid: None,
+            record_spans: false,
+        }
+    }
+}
+
+/// Settings for a paragraph appended with `Layout::append_paragraph`, overriding some of the
+/// layout settings for its lines.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ParagraphSettings {
+    /// The default is None, which keeps the horizontal_align of the layout settings. Like that
+    /// option, this does nothing if the max_width isn't set.
+    pub horizontal_align: Option<HorizontalAlign>,
+    /// The default is 0. How many pixels the first line of the paragraph is indented by. Negative
+    /// indents are ignored.
+    pub indent: f32,
+    /// The default is 0. The extra space in pixels left above the paragraph, unless it's on the
+    /// first line of the layout.
+    pub space_before: f32,
+    /// The default is None. If set, this is the line height multiplier of spans in the paragraph
+    /// that don't set their own.
+    pub line_height: Option<f32>,
+}
+
+impl Default for ParagraphSettings {
+    fn default() -> ParagraphSettings {
+        ParagraphSettings {
+            horizontal_align: None,
+            indent: 0.0,
+            space_before: 0.0,
+            line_height: None,
         }
     }
 }
 
 /// Configuration for rasterizing a glyph. This struct is also a hashable key that can be used to
 /// uniquely identify a rasterized glyph for applications that want to cache glyphs.
+///
+/// Configs are compared by the exact value of their px, so sizes that only differ by float
+/// rounding, like 35.0 and 35.0000001, are different keys. Whole pixel sizes avoid this. The same
+/// config always rasterizes to the same bitmap with a given build, though builds with and without
+/// SIMD may differ by a level of coverage.
 #[derive(Debug, Copy, Clone)]
 pub struct GlyphRasterConfig {
     /// The glyph index represented by the glyph being positioned.
@@ -129,6 +443,19 @@ impl PartialEq for GlyphRasterConfig {
 
 impl Eq for GlyphRasterConfig {}
 
+/// Finds where rasterized glyphs are stored in a texture atlas, for `Layout::to_vertices`.
+pub trait AtlasLookup {
+    /// Gets the texture coordinates of the glyph's bitmap in the atlas, as (u_min, v_min, u_max,
+    /// v_max), with v_min at its first row. Returns None if the glyph isn't in the atlas.
+    fn uv_rect(&self, config: &GlyphRasterConfig) -> Option<(f32, f32, f32, f32)>;
+}
+
+impl<F: Fn(&GlyphRasterConfig) -> Option<(f32, f32, f32, f32)>> AtlasLookup for F {
+    fn uv_rect(&self, config: &GlyphRasterConfig) -> Option<(f32, f32, f32, f32)> {
+        self(config)
+    }
+}
+
 /// A positioned scaled glyph.
 #[derive(Debug, Copy, Clone)]
 pub struct GlyphPosition<'f, U: Copy + Clone = ()> {
@@ -151,12 +478,101 @@ pub struct GlyphPosition<'f, U: Copy + Clone = ()> {
     pub width: usize,
     /// The height of the glyph. Dimensions are in pixels.
     pub height: usize,
+    /// The distance from where this glyph was placed to where the next glyph is placed, including
+    /// kerning. For blocks, this is the block width plus kerning. Dimensions are in pixels.
+    pub advance: f32,
     /// Additional metadata associated with the character used to generate this glyph.
     pub char_data: CharacterData,
+    /// The RGBA color of the span used to generate this glyph, if it has one.
+    pub color: Option<[u8; 4]>,
+    /// The opacity to draw the glyph with, from 0.0 for invisible to 1.0 for opaque, which
+    /// renderers multiply coverage by. Glyphs are laid out opaque, and can be faded afterwards with
+    /// `Layout::set_glyph_alpha` or `Layout::fade_range`.
+    pub alpha: f32,
     /// Custom user data associated with the text styled used to generate this glyph.
     pub user_data: U,
 }
 
+impl<'f, U: Copy + Clone> GlyphPosition<'f, U> {
+    /// Gets the bounds of the glyph's bitmap as (left, top, right, bottom) on screen, in pixels,
+    /// given the coordinate system the glyph was laid out in. The top is y in PositiveYDown and
+    /// y + height in PositiveYUp, so the first row of the bitmap is always at the top and a quad
+    /// drawn from the top to the bottom is never upside down.
+    pub fn rect(&self, coordinate_system: CoordinateSystem) -> (f32, f32, f32, f32) {
+        let (left, right) = (self.x, self.x + self.width as f32);
+        match coordinate_system {
+            CoordinateSystem::PositiveYDown => (left, self.y, right, self.y + self.height as f32),
+            CoordinateSystem::PositiveYUp => (left, self.y + self.height as f32, right, self.y),
+        }
+    }
+}
+
+/// A laid out glyph or block, ready to be drawn. Created with `Layout::renderable`.
+#[derive(Debug, Copy, Clone)]
+pub enum Renderable<'f, U: Copy + Clone = ()> {
+    /// A glyph to rasterize with its font and raster config, and draw with the top left corner of
+    /// its bitmap at (x, y) in PositiveYDown, or the bottom left corner in PositiveYUp.
+    Glyph {
+        config: GlyphRasterConfig,
+        font: &'f Font,
+        x: f32,
+        y: f32,
+        width: usize,
+        height: usize,
+        color: Option<[u8; 4]>,
+        alpha: f32,
+        user_data: U,
+    },
+    /// A reserved inline block, placed the same way as a glyph's bitmap.
+    Block {
+        x: f32,
+        y: f32,
+        width: usize,
+        height: usize,
+        alpha: f32,
+        user_data: U,
+    },
+}
+
+/// The point of a glyph's bitmap that `Layout::glyphs_anchored` places glyphs by.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Anchor {
+    /// The top left corner of the bitmap. In PositiveYDown, this is where glyphs are already
+    /// placed.
+    TopLeft,
+    /// The left side of the bitmap, on the baseline of the glyph's line.
+    Baseline,
+    /// The center of the bitmap.
+    Center,
+}
+
+/// Where `Layout::append_truncated` replaces text with an ellipsis.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EllipsisPosition {
+    /// The start of the text is replaced, keeping its end.
+    Start,
+    /// The middle of the text is replaced, keeping both of its ends, like file names.
+    Middle,
+    /// The end of the text is replaced, keeping its start.
+    End,
+}
+
+/// The direction characters in a span are read in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    /// Left to right text, like Latin.
+    LeftToRight,
+    /// Right to left text, like Arabic or Hebrew. Characters with a mirrored form, like brackets,
+    /// are drawn mirrored. Characters aren't reordered.
+    RightToLeft,
+}
+
+impl Default for Direction {
+    fn default() -> Direction {
+        Direction::LeftToRight
+    }
+}
+
 /// Vertical alignment options for a block span.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum BlockAlign {
@@ -166,6 +582,48 @@ pub enum BlockAlign {
     Middle,
 }
 
+/// The kind of line a decoration draws.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DecorationKind {
+    /// A line below the baseline.
+    Underline,
+    /// A line through the middle of the text.
+    Strikethrough,
+}
+
+/// A positioned decoration line. Each rect spans contiguous decorated glyphs on a single line.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DecorationRect {
+    /// The kind of line this rect draws.
+    pub kind: DecorationKind,
+    /// The left side of the rect. Dimensions are in pixels.
+    pub x: f32,
+    /// The bottom side of the rect if your coordinate system is PositiveYUp, or the top side of
+    /// the rect if your coordinate system is PositiveYDown. Like glyphs, (y + height) always
+    /// produces the other bound.
+    pub y: f32,
+    /// The width of the rect. Dimensions are in pixels.
+    pub width: f32,
+    /// The height of the rect, which is the thickness of the line. Dimensions are in pixels, and
+    /// are always whole numbers.
+    pub height: f32,
+}
+
+/// Decoration state of an appended glyph, kept until the layout is finalized.
+#[derive(Debug, Copy, Clone)]
+struct DecoratedGlyph {
+    /// The index of the decorated glyph.
+    index: usize,
+    /// The offset from the glyph's x to the pen position the glyph was placed at.
+    pen_offset: f32,
+    /// The distance the pen advanced past the glyph.
+    advance: f32,
+    /// The snapped position and thickness of the underline, if any.
+    underline: Option<(f32, f32)>,
+    /// The snapped position and thickness of the strikethrough, if any.
+    strikethrough: Option<(f32, f32)>,
+}
+
 macro_rules! att_set {
     ($method:ident, $($q:ident)+, $att:ident, $t:ty) => {
         #[must_use]
@@ -186,6 +644,44 @@ macro_rules! att_opt_set {
     };
 }
 
+/// A builder for `LayoutSettings`, created with `LayoutSettings::builder`. Each method sets the
+/// layout setting of the same name.
+#[derive(Copy, Clone, PartialEq)]
+pub struct LayoutSettingsBuilder {
+    settings: LayoutSettings,
+}
+
+impl LayoutSettingsBuilder {
+    att_set!(x, settings x, x, f32);
+    att_set!(y, settings y, y, f32);
+    att_opt_set!(max_width, settings max_width, max_width, f32);
+    att_opt_set!(max_columns, settings max_columns, max_columns, usize);
+    att_set!(wrap, settings wrap, wrap, bool);
+    att_opt_set!(max_height, settings max_height, max_height, f32);
+    att_set!(horizontal_align, settings horizontal_align, horizontal_align, HorizontalAlign);
+    att_set!(vertical_align, settings vertical_align, vertical_align, VerticalAlign);
+    att_set!(wrap_style, settings wrap_style, wrap_style, WrapStyle);
+    att_set!(wrap_hard_breaks, settings wrap_hard_breaks, wrap_hard_breaks, bool);
+    att_set!(show_invisibles, settings show_invisibles, show_invisibles, bool);
+    att_opt_set!(line_gap_override, settings line_gap_override, line_gap_override, f32);
+    att_opt_set!(ascent_override, settings ascent_override, ascent_override, f32);
+    att_opt_set!(descent_override, settings descent_override, descent_override, f32);
+    att_opt_set!(min_line_height, settings min_line_height, min_line_height, f32);
+    att_set!(collapse_whitespace, settings collapse_whitespace, collapse_whitespace, bool);
+    att_set!(features, settings features, features, FeatureSet);
+    att_set!(rounding, settings rounding, rounding, RoundMode);
+    att_opt_set!(hyphenator, settings hyphenator, hyphenator, fn(&str) -> Vec<usize>);
+    att_set!(user_data_merge, settings user_data_merge, user_data_merge, UserDataMerge);
+    att_set!(trailing_newline_line, settings trailing_newline_line, trailing_newline_line, bool);
+    att_opt_set!(baseline_grid, settings baseline_grid, baseline_grid, f32);
+    att_set!(record_spans, settings record_spans, record_spans, bool);
+
+    /// Finishes building the layout settings.
+    pub fn build(self) -> LayoutSettings {
+        self.settings
+    }
+}
+
 /// Parameters describing a reserved inline block space.
 #[derive(Debug, Copy, Clone)]
 pub struct Block {
@@ -195,6 +691,10 @@ pub struct Block {
     height: usize,
     /// The vertical alignment option.
     align: BlockAlign,
+    /// If the block can be split across lines. A breakable block that overflows a line fills the
+    /// rest of it and continues on the next. Each piece is a separate glyph with the block's user
+    /// data, and their widths sum to the block's width.
+    breakable: bool,
 }
 
 impl Block {
@@ -203,10 +703,12 @@ impl Block {
             width,
             height,
             align: BlockAlign::Middle,
+            breakable: false,
         }
     }
 
     att_set!(with_align, align, align, BlockAlign);
+    att_set!(with_breakable, breakable, breakable, bool);
 }
 
 /// Parameters common to both span variants.
@@ -222,6 +724,25 @@ struct CommonParams<'f> {
     kerning: f32,
     /// Line height multiplier.
     line_height: Option<f32>,
+    /// If the text is underlined.
+    underline: bool,
+    /// If the text is struck through.
+    strikethrough: bool,
+    /// If lowercase letters are set as small capitals.
+    small_caps: bool,
+    /// The RGBA color of the glyphs.
+    color: Option<[u8; 4]>,
+    /// The direction the text is read in.
+    direction: Direction,
+    /// If the span is set as superscript or subscript, resolved against the font when laid out.
+    script: Option<ScriptPosition>,
+}
+
+/// A baseline position from the font's superscript or subscript metrics.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ScriptPosition {
+    Superscript,
+    Subscript,
 }
 
 /// Parameters specific to text or block.
@@ -231,7 +752,114 @@ enum SpecificParams<'t> {
     Block(Block),
 }
 
+/// Parameters specific to a recorded text or block.
+#[derive(Debug, Clone)]
+enum RecordedParams {
+    /// The range of the text in the recorded text buffer.
+    Text(Range<usize>),
+    Block(Block),
+    /// A move of the pen to a column, optionally on a new row.
+    Column {
+        x: f32,
+        new_row: bool,
+    },
+    /// The start of a paragraph with its own settings.
+    Paragraph(ParagraphSettings),
+    /// The end of a paragraph, after which the layout's settings apply again.
+    ParagraphEnd,
+    /// A character pushed on its own.
+    Char(char),
+}
+
+/// The characters pushed one at a time since anything else was appended, in the same font and
+/// size, which are laid out as a single span. Each push continues laying out the span from the
+/// first character whose layout could still change with the characters pushed after it.
+struct PushedRun<'f, U: Copy + Clone> {
+    font: &'f Font,
+    px: f32,
+    text: String,
+    /// The user data of each character.
+    user_data: Vec<U>,
+    /// The layout state to continue from, once a character has been pushed.
+    resume: Option<TextCheckpoint<'f, U>>,
+}
+
+/// The state carried from one character of a text span to the next while it's laid out.
+struct TextState {
+    /// The byte offset of the next character.
+    byte_offset: usize,
+    /// The number of characters appended before the span.
+    first_char: usize,
+    /// The glyph index and pen position of the last spacing glyph, which marks attach to and
+    /// kerning is measured from.
+    mark_base: Option<(u16, f32)>,
+    /// The byte offsets of the slash and the end of the fraction being laid out, if any.
+    fraction: Option<(usize, usize)>,
+    /// The byte offsets of the remaining hyphenation points in the current word, last first.
+    hyphen_points: Vec<usize>,
+    /// The byte offset of the end of the current word.
+    word_end: usize,
+}
+
+impl TextState {
+    fn new(first_char: usize) -> TextState {
+        TextState {
+            byte_offset: 0,
+            first_char,
+            mark_base: None,
+            fraction: None,
+            hyphen_points: Vec::new(),
+            word_end: 0,
+        }
+    }
+}
+
+/// The layout state partway through a text span, saved so the rest of the span can be laid out
+/// again from there. Laying out the rest of a span only adds to the intermediate state, except for
+/// the current line, the user data of the last glyph, and the positions of a justified line's
+/// glyphs, so only those are kept besides the lengths.
+struct TextCheckpoint<'f, U: Copy + Clone> {
+    state: TextState,
+    glyph_len: usize,
+    /// The index of the first kept glyph, and the glyphs kept from there on.
+    glyphs_start: usize,
+    glyphs: Vec<GlyphPosition<'f, U>>,
+    decorated_len: usize,
+    char_glyphs_len: usize,
+    omitted_len: usize,
+    last_omitted: Option<Range<usize>>,
+    line_count: usize,
+    last_line: LinePosition,
+    scripts: Vec<(Script, usize)>,
+    linebreaker: Linebreaker,
+    linebreak_prev: LinebreakData,
+    linebreak_pos: f32,
+    linebreak_idx: usize,
+    pending_hard_break: bool,
+    text_len: usize,
+    prev_not_whitespace: bool,
+    line_end_pos: f32,
+    line_end_idx: usize,
+    current_pos: f32,
+    start_pos: f32,
+    height: f32,
+}
+
+/// An appended span, kept so layout can be performed again with new settings.
+#[derive(Debug, Clone)]
+struct SpanRecord<'f, U: Copy + Clone> {
+    common: CommonParams<'f>,
+    params: RecordedParams,
+    user_data: U,
+}
+
 /// Parameters to define a text span or inline block in a text layout.
+///
+/// Spans don't inherit anything from the spans before them. An unset font or px falls back to
+/// the layout's base_font or base_px, and every other attribute starts at its default: no rise,
+/// kerning, decorations, small caps, or color, left to right, and no line height multiplier.
+/// A line advances by the largest line height multiplier of the spans on it that set one, or by
+/// 1.0 if none of them do.
 #[derive(Debug, Clone)]
 pub struct Span<'f, 't, U: Copy + Clone = ()> {
     common: CommonParams<'f>,
@@ -250,6 +878,12 @@ impl<'f, 't, U: Copy + Clone> Span<'f, 't, U> {
         }
     }
 
+    /// Creates a text span in the given font and size, as a shorthand for `with_font` and
+    /// `with_px`.
+    pub fn styled(text: &'t str, font: &'f Font, px: f32, user_data: U) -> Self {
+        Span::text(text, user_data).with_font(font).with_px(px)
+    }
+
     pub fn block(block: Block, user_data: U) -> Self {
         Span {
             common: CommonParams::default(),
@@ -263,6 +897,27 @@ impl<'f, 't, U: Copy + Clone> Span<'f, 't, U> {
     att_set!(with_rise, common rise, rise, f32);
     att_set!(with_kerning, common kerning, kerning, f32);
     att_opt_set!(with_line_height, common line_height, line_height, f32);
+    att_set!(with_underline, common underline, underline, bool);
+    att_set!(with_strikethrough, common strikethrough, strikethrough, bool);
+    att_set!(with_small_caps, common small_caps, small_caps, bool);
+    att_opt_set!(with_color, common color, color, [u8; 4]);
+    att_set!(with_direction, common direction, direction, Direction);
+
+    /// Sets the span as superscript. When it's laid out, the span's size is reduced and its rise
+    /// is increased by the font's superscript metrics for the span's size. Fonts without the
+    /// metrics fall back to 0.7 times the size, raised by 0.3 times the size.
+    pub fn superscript(mut self) -> Self {
+        self.common.script = Some(ScriptPosition::Superscript);
+        self
+    }
+
+    /// Sets the span as subscript. When it's laid out, the span's size is reduced and its rise
+    /// is decreased by the font's subscript metrics for the span's size. Fonts without the metrics
+    /// fall back to 0.7 times the size, lowered by 0.3 times the size.
+    pub fn subscript(mut self) -> Self {
+        self.common.script = Some(ScriptPosition::Subscript);
+        self
+    }
 }
 
 /// Metrics about a positioned line.
@@ -273,6 +928,11 @@ pub struct LinePosition {
     /// How much empty space is left at the end of the line before any alignment. If no max width is
     /// specified, f32::MAX is used.
     pub padding: f32,
+    /// The width of the line in pixels, from its start to the end of its last glyph's advance,
+    /// before any alignment. Trailing whitespace is only counted on the last line. This doesn't
+    /// depend on the max width, so it measures unconstrained text too. Justified lines are as wide
+    /// as the max width.
+    pub width: f32,
     /// The highest point that any glyph in the font extends to above the baseline. Typically
     /// positive. If there are multiple styles on this line, this is their max value.
     pub max_ascent: f32,
@@ -292,8 +952,15 @@ pub struct LinePosition {
     pub glyph_start: usize,
     /// The GlyphPosition index of the last glyph in the line.
     pub glyph_end: usize,
+    /// If the line started after a hard break, like a new line character, rather than being
+    /// wrapped. False for the first line.
+    pub started_by_hard_break: bool,
     /// The x offset into the first layout pass.
     tracking_x: f32,
+    /// A multiplier for how this line fills unused horizontal space.
+    align: f32,
+    /// The extra space left above this line, for the start of a paragraph.
+    space_before: f32,
 }
 
 impl Default for LinePosition {
@@ -301,6 +968,7 @@ impl Default for LinePosition {
         LinePosition {
             baseline_y: 0.0,
             padding: 0.0,
+            width: 0.0,
             max_ascent: 0.0,
             min_descent: 0.0,
             max_line_gap: 0.0,
@@ -308,7 +976,10 @@ impl Default for LinePosition {
             line_height: None,
             glyph_start: 0,
             glyph_end: 0,
+            started_by_hard_break: false,
             tracking_x: 0.0,
+            align: 0.0,
+            space_before: 0.0,
         }
     }
 }
@@ -328,6 +999,8 @@ pub struct Layout<'f, U: Copy + Clone = ()> {
     wrap_mask: LinebreakData,
     /// The max width of the region text is being laid out in.
     max_width: f32,
+    /// The width lines are wrapped at, which is the max width unless wrapping is disabled.
+    wrap_width: f32,
     /// The max height of the region text is being laid out in.
     max_height: f32,
     /// A multiplier for how text fills unused vertical space.
@@ -338,13 +1011,38 @@ pub struct Layout<'f, U: Copy + Clone = ()> {
     height: f32,
     /// The default font for the text.
     pub base_font: &'f Font,
-    /// The default size for the text.
+    /// The default size for the text. Sizes that are negative or not finite, here or on a span,
+    /// are laid out as 0.
     pub base_px: f32,
+    /// Characters that are always drawn with a specific font and glyph, instead of the glyph their
+    /// span's font maps them to.
+    glyph_overrides: HashMap<char, (&'f Font, u16)>,
+    /// Combines the user data of glyphs formed from characters of several spans, in place of the
+    /// user_data_merge setting.
+    user_data_merger: Option<fn(U, U) -> U>,
 
     /// Finalized glyph state.
     output: Vec<GlyphPosition<'f, U>>,
+    /// Finalized line state.
+    output_lines: Vec<LinePosition>,
+    /// Finalized decoration state.
+    decorations: Vec<DecorationRect>,
+    /// Intermediate decoration state, in glyph order.
+    decorated: Vec<DecoratedGlyph>,
     /// Intermediate glyph state.
     glyphs: Vec<GlyphPosition<'f, U>>,
+    /// The number of glyphs appended so far. This matches the length of the intermediate glyph
+    /// state, unless glyphs are only being measured.
+    glyph_len: usize,
+    /// Marks if appended glyphs should only be measured for line breaking and sizing, without
+    /// storing them.
+    dry_run: bool,
+    /// The spans appended since the last clear, in order, if spans are recorded.
+    history: Vec<SpanRecord<'f, U>>,
+    /// The text of every recorded text span, concatenated.
+    history_text: String,
+    /// The number of appended characters of each script, in order of first appearance.
+    scripts: Vec<(Script, usize)>,
 
     /// Linebreak state. Used to derive linebreaks from past glyphs.
     linebreaker: Linebreaker,
@@ -356,8 +1054,20 @@ pub struct Layout<'f, U: Copy + Clone = ()> {
     /// The index of the glyph that has the current highest priority linebreak status. This glyph is
     /// the last glyph on a line if a linebreak is required.
     linebreak_idx: usize,
-    /// If the previous glyph was not whitespace.
+    /// If a hard break was found in collapsed whitespace, and still needs to be applied to the
+    /// next glyph.
+    pending_hard_break: bool,
+    /// The total length in bytes of the text appended so far.
+    text_len: usize,
+    /// Byte ranges of the appended text that were omitted from layout, like collapsed whitespace.
+    omitted: Vec<Range<usize>>,
+    /// The index of the glyph each appended character produced, or none if it was omitted.
+    char_glyphs: Vec<Option<usize>>,
+    /// If the previous glyph was not whitespace. Like the linebreak state, this carries over
+    /// between appended spans so a word split across spans is wrapped as a single word.
     prev_not_whitespace: bool,
+    /// The characters pushed one at a time since anything else was appended, if any.
+    pushed_run: Option<PushedRun<'f, U>>,
     /// The x position that the last rasterizable glyph ends at.
     line_end_pos: f32,
     /// The index of the last rasterizable glyph.
@@ -383,6 +1093,8 @@ pub struct Layout<'f, U: Copy + Clone = ()> {
     justify: bool,
     /// If the text should wrap by letter.
     wrap_by_letter: bool,
+    /// The line height multiplier of the current paragraph, for spans that don't set one.
+    paragraph_line_height: Option<f32>,
 
     /// The settings currently being used for layout.
     pub settings: LayoutSettings,
@@ -400,19 +1112,35 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
             y: 0.0,
             wrap_mask: LINEBREAK_NONE,
             max_width: 0.0,
+            wrap_width: 0.0,
             max_height: 0.0,
             base_font: font,
-            base_px: px,
+            base_px: sanitize_px(px),
+            glyph_overrides: HashMap::new(),
+            user_data_merger: None,
             vertical_align: 0.0,
             horizontal_align: 0.0,
             output: Vec::new(),
+            output_lines: Vec::new(),
+            decorations: Vec::new(),
+            decorated: Vec::new(),
             glyphs: Vec::new(),
+            glyph_len: 0,
+            dry_run: false,
+            history: Vec::new(),
+            history_text: String::new(),
+            scripts: Vec::new(),
             line_metrics: Vec::new(),
             linebreaker: Linebreaker::new(),
             linebreak_prev: LINEBREAK_NONE,
             linebreak_pos: 0.0,
             linebreak_idx: 0,
+            pending_hard_break: false,
+            text_len: 0,
+            omitted: Vec::new(),
+            char_glyphs: Vec::new(),
             prev_not_whitespace: false,
+            pushed_run: None,
             line_end_pos: 0.0,
             line_end_idx: 0,
             current_pos: 0.0,
@@ -425,6 +1153,7 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
             height: 0.0,
             justify: false,
             wrap_by_letter: false,
+            paragraph_line_height: None,
             settings,
         };
         layout.reset(&settings);
@@ -434,7 +1163,28 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
     /// Changes the default font and scale settings
     pub fn set_base(&mut self, font: &'f Font, px: f32) {
         self.base_font = font;
-        self.base_px = px;
+        self.base_px = sanitize_px(px);
+        self.pushed_run = None;
+    }
+
+    /// Sets the characters that are always drawn with a specific font and glyph index, bypassing
+    /// the lookup in their span's font. This is useful for icon fonts that use the private use
+    /// area. Overridden glyphs use the metrics of their own font, but aren't substituted, kerned,
+    /// or joined into ligatures. This replaces any previous overrides, and is kept when the layout
+    /// is reset or cleared, so it applies to text appended afterwards.
+    pub fn set_glyph_overrides<I: IntoIterator<Item = (char, (&'f Font, u16))>>(&mut self, overrides: I) {
+        self.glyph_overrides = overrides.into_iter().collect();
+        self.pushed_run = None;
+    }
+
+    /// Sets the function that combines user data when a glyph is formed from characters of more
+    /// than one span, in place of `LayoutSettings::user_data_merge`. It's called with the glyph's
+    /// user data so far and the user data of the span of each character merged into it, which can
+    /// be the same span, and returns the glyph's new user data. None goes back to the setting. This
+    /// is kept when the layout is reset or cleared, so it applies to text appended afterwards.
+    pub fn set_user_data_merge(&mut self, merge: Option<fn(U, U) -> U>) {
+        self.user_data_merger = merge;
+        self.pushed_run = None;
     }
 
     /// Resets the current layout settings and clears all appended text.
@@ -442,12 +1192,26 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
         self.settings = *settings;
         self.x = settings.x;
         self.y = settings.y;
+        let column_advance = settings.max_columns.and_then(|_| {
+            let advance = self.base_font.monospace_advance(self.base_px)?;
+            Some(settings.rounding.advance(advance))
+        });
+        let columns_width =
+            settings.max_columns.zip(column_advance).map(|(columns, advance)| columns as f32 * advance);
+        let max_width = columns_width.or(settings.max_width);
         self.wrap_mask = LinebreakData::from_mask(
             settings.wrap_style == WrapStyle::Word,
             settings.wrap_hard_breaks,
-            settings.max_width.is_some(),
+            max_width.is_some() && settings.wrap,
         );
-        self.max_width = settings.max_width.unwrap_or(core::f32::MAX);
+        self.max_width = max_width.unwrap_or(core::f32::MAX);
+        self.wrap_width = if settings.wrap {
+            // Advances summed one at a time can round past the product of the columns, so lines wrap
+            // by whole columns with half a column to spare.
+            self.max_width + column_advance.map_or(0.0, |advance| advance * 0.5)
+        } else {
+            core::f32::MAX
+        };
         self.max_height = settings.max_height.unwrap_or(core::f32::MAX);
         self.vertical_align = if settings.max_height.is_none() {
             0.0
@@ -458,16 +1222,6 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
                 VerticalAlign::Bottom => 1.0,
             }
         };
-        self.horizontal_align = if settings.max_width.is_none() {
-            0.0
-        } else {
-            match settings.horizontal_align {
-                HorizontalAlign::Left | HorizontalAlign::Justify => 0.0,
-                HorizontalAlign::Center => 0.5,
-                HorizontalAlign::Right => 1.0,
-            }
-        };
-        self.justify = settings.horizontal_align == HorizontalAlign::Justify;
         self.wrap_by_letter = settings.wrap_style == WrapStyle::Letter;
         self.clear();
     }
@@ -475,15 +1229,31 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
     /// Keeps current layout settings but clears all appended text.
     pub fn clear(&mut self) {
         self.glyphs.clear();
+        self.glyph_len = 0;
+        self.history.clear();
+        self.history_text.clear();
+        self.scripts.clear();
         self.output.clear();
+        self.output_lines.clear();
+        self.decorations.clear();
+        self.decorated.clear();
+        self.end_paragraph();
         self.line_metrics.clear();
-        self.line_metrics.push(LinePosition::default());
+        self.line_metrics.push(LinePosition {
+            align: self.horizontal_align,
+            ..LinePosition::default()
+        });
 
         self.linebreaker.reset();
         self.linebreak_prev = LINEBREAK_NONE;
         self.linebreak_pos = 0.0;
         self.linebreak_idx = 0;
+        self.pending_hard_break = false;
+        self.text_len = 0;
+        self.omitted.clear();
+        self.char_glyphs.clear();
         self.prev_not_whitespace = false;
+        self.pushed_run = None;
         self.line_end_pos = 0.0;
         self.line_end_idx = 0;
         self.current_pos = 0.0;
@@ -496,13 +1266,211 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
         self.height = 0.0;
     }
 
-    /// Gets the current height of the appended text.
+    /// Gets the current height of the appended text. A layout with nothing appended, or only
+    /// empty spans, has a height of 0 rather than the height of an empty line.
     pub fn height(&self) -> f32 {
-        if let Some(line) = self.line_metrics.last() {
-            self.height + line.max_new_line_size
+        match self.line_metrics.last() {
+            Some(line) if self.glyph_len > 0 => self.height + line.max_new_line_size,
+            _ => 0.0,
+        }
+    }
+
+    /// Gets the number of lines of the appended text. This is available before finalizing, and is
+    /// 0 if nothing has been appended.
+    pub fn line_count(&self) -> usize {
+        if self.glyph_len > 0 {
+            self.line_metrics.len()
         } else {
-            0.0
+            0
+        }
+    }
+
+    /// Checks if the given spans would fit in the region defined by the given settings, without
+    /// storing any glyphs. Text fits when no line exceeds the max_width and the total height
+    /// doesn't exceed the max_height. This is cheaper than a full layout, and is useful when
+    /// searching for a font size that fits a region. The spans are measured with this layout's
+    /// base font, base size, and coordinate system; this layout is left untouched.
+    pub fn would_fit<'t>(&self, spans: &[Span<'f, 't, U>], settings: &LayoutSettings) -> bool {
+        let coordinate_system = if self.flip {
+            CoordinateSystem::PositiveYDown
+        } else {
+            CoordinateSystem::PositiveYUp
+        };
+        let mut layout = Layout::new(self.base_font, self.base_px, coordinate_system);
+        layout.dry_run = true;
+        layout.glyph_overrides = self.glyph_overrides.clone();
+        layout.reset(settings);
+        for span in spans {
+            layout.append(span.clone());
+        }
+        layout.fits()
+    }
+
+    /// Lays out the given spans at the largest base size between min_px and max_px that fits in
+    /// the region defined by the given settings, to within half a pixel, and finalizes the layout.
+    /// Spans with their own px keep it. If the spans don't fit even at min_px, they're laid out at
+    /// min_px regardless. The base_px is left at the chosen size, which is returned.
+    pub fn fit<'t>(
+        &mut self,
+        spans: &[Span<'f, 't, U>],
+        settings: &LayoutSettings,
+        min_px: f32,
+        max_px: f32,
+    ) -> f32 {
+        let (mut low, mut high) = (min_px.min(max_px), max_px.max(min_px));
+        let fits = |layout: &mut Self, px: f32| {
+            layout.base_px = px;
+            layout.would_fit(spans, settings)
+        };
+        let px = if fits(self, high) {
+            high
+        } else {
+            // The low end is assumed to fit, and the high end is known not to.
+            while high - low > 0.5 {
+                let mid = (low + high) / 2.0;
+                if fits(self, mid) {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            low
+        };
+        self.base_px = px;
+        self.reset(settings);
+        for span in spans {
+            self.append(span.clone());
         }
+        self.finalize();
+        px
+    }
+
+    /// Checks if nothing has been laid out since the layout was last reset or cleared. Empty
+    /// spans don't produce glyphs, while whitespace and blocks do.
+    pub fn is_empty(&self) -> bool {
+        self.glyph_len == 0
+    }
+
+    /// Gets the number of glyphs appended since the layout was last reset or cleared. This is
+    /// available before finalizing, and is an upper bound on the glyph_count once finalized, so
+    /// it's suitable for sizing buffers ahead of time.
+    pub fn appended_glyph_count(&self) -> usize {
+        self.glyph_len
+    }
+
+    /// Gets the number of laid out glyphs, matching the length of glyphs(). This is 0 before
+    /// finalizing, and reflects the last call to finalize until the layout is reset or cleared.
+    pub fn glyph_count(&self) -> usize {
+        self.output.len()
+    }
+
+    /// Checks if the appended text overflows the max_height, or has a line overflowing the
+    /// max_width because a glyph didn't fit even on a line of its own. This is available before
+    /// finalizing, and is useful for deciding when to truncate text.
+    pub fn overflows(&self) -> bool {
+        !self.fits()
+    }
+
+    /// Gets how far the appended text extends past the max_height, in pixels. This is 0 if the
+    /// text fits or no max_height is set, and doesn't depend on the coordinate system or the
+    /// vertical alignment. This is available before finalizing.
+    pub fn vertical_overflow(&self) -> f32 {
+        (self.height() - self.max_height).max(0.0)
+    }
+
+    /// Checks if the appended text extends past the max_height, meaning some of it is cut off by
+    /// the region. This is available before finalizing.
+    pub fn overflows_vertically(&self) -> bool {
+        self.vertical_overflow() > 0.0
+    }
+
+    /// Checks if appending the given character next would allow a line break before it, given the
+    /// text appended so far and the wrap_style. The layout itself is left untouched.
+    pub fn can_break_before(&self, c: char) -> bool {
+        let mut linebreaker = self.linebreaker;
+        let linebreak = linebreaker.next(c).mask(self.wrap_mask);
+        linebreak.is_soft() || linebreak.is_hard()
+    }
+
+    /// Checks if the appended text fits within the max_width and max_height.
+    fn fits(&self) -> bool {
+        self.height() <= self.max_height && self.line_metrics.iter().all(|line| line.padding >= 0.0)
+    }
+
+    /// Gets the y coordinate of the baseline of the first line. This is available before
+    /// finalizing, and even if nothing has been appended yet, in which case the base font and size
+    /// are used for the line's metrics. This is where text will appear once it's appended, which
+    /// is useful for placing a caret in an empty text field.
+    pub fn first_baseline(&self) -> f32 {
+        let dir = if self.flip {
+            -1.0 // PositiveYDown
+        } else {
+            1.0 // PositiveYUp
+        };
+        let (ascent, height) = match self.line_metrics.first() {
+            Some(line) if line.max_new_line_size > 0.0 => (line.max_ascent, self.height()),
+            _ => match self.base_font.horizontal_line_metrics(self.base_px) {
+                Some(metrics) => {
+                    let (ascent, _, _, new_line) = self.override_metrics(
+                        metrics.ascent,
+                        metrics.descent,
+                        metrics.line_gap,
+                        metrics.new_line_size,
+                    );
+                    (ceil(ascent), ceil(self.clamp_new_line(new_line)))
+                }
+                None => (0.0, 0.0),
+            },
+        };
+        let rounding = self.settings.rounding;
+        self.y - dir * rounding.position((self.max_height - height) * self.vertical_align) - dir * ascent
+    }
+
+    /// Gets the position the next appended glyph's pen will start at, as (x, baseline y). This is
+    /// available before finalizing, and accounts for alignment of the text appended so far, so it
+    /// matches where finalize would place it. This is useful for placing a caret while text is
+    /// appended incrementally.
+    pub fn cursor(&self) -> (f32, f32) {
+        let dir = if self.flip {
+            -1.0 // PositiveYDown
+        } else {
+            1.0 // PositiveYUp
+        };
+        let line = &self.line_metrics[self.line_metrics.len() - 1];
+        let rounding = self.settings.rounding;
+        let x = self.x + self.current_pos - self.start_pos + rounding.position(line.padding * line.align);
+        let y = if self.line_metrics.len() == 1 {
+            self.first_baseline()
+        } else {
+            let top =
+                self.y - dir * rounding.position((self.max_height - self.height()) * self.vertical_align);
+            top - dir * (self.height + line.max_ascent)
+        };
+        (x, y)
+    }
+
+    /// Gets the byte ranges of appended text that were omitted from layout, like collapsed
+    /// whitespace. Offsets are into all of the text appended since the last clear, as if the text
+    /// of every span were concatenated. Adjacent omitted ranges are merged.
+    pub fn omitted(&self) -> &[Range<usize>] {
+        &self.omitted
+    }
+
+    /// Gets the index of the glyph produced by the appended character at the given index, counting
+    /// characters rather than bytes as if the text of every span were concatenated. Characters
+    /// combined into a ligature share its glyph. Returns none for characters omitted from layout,
+    /// and before the layout is finalized.
+    pub fn glyph_for_char_index(&self, char_idx: usize) -> Option<usize> {
+        self.char_glyphs.get(char_idx).copied().flatten().filter(|&glyph| glyph < self.output.len())
+    }
+
+    /// Gets the scripts of the appended text, along with the number of characters of each. The
+    /// most common script comes first, and ties are ordered by first appearance. Inline blocks
+    /// aren't counted.
+    pub fn scripts(&self) -> Vec<(Script, usize)> {
+        let mut scripts = self.scripts.clone();
+        scripts.sort_by(|a, b| b.1.cmp(&a.1));
+        scripts
     }
 
     /// Gets the currently positioned lines. If there are no lines positioned, this returns none.
@@ -526,174 +1494,1144 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
     /// Custom inline blocks are also allowed, and are treated as single non whitespace glyphs
     /// with the specified width and height, and it is up to the application to decide what
     /// to do with this reserved space.
-    pub fn append<'t>(&mut self, span: Span<'f, 't, U>) {
+    ///
+    /// Returns the range of indices into `Layout::glyphs` of the glyphs the span produced, which
+    /// is empty if it produced none. Glyphs keep their order, so the range stays valid after the
+    /// layout is finalized, until it's cleared or reset.
+    pub fn append<'t>(&mut self, span: Span<'f, 't, U>) -> Range<usize> {
+        self.append_with(span, |_, advance| advance)
+    }
+
+    /// Performs layout for text horizontally like `Layout::append`, letting the given closure
+    /// change the advance of each glyph. The closure is given the glyph's index in its font and
+    /// its default advance in pixels, and returns the advance to use. It isn't called for blocks.
+    /// The closure isn't kept, so `Layout::relayout` uses the default advances.
+    pub fn append_with<'t, F: FnMut(u16, f32) -> f32>(
+        &mut self,
+        span: Span<'f, 't, U>,
+        advance_override: F,
+    ) -> Range<usize> {
+        let start = self.glyph_len;
+        if self.records_spans() {
+            let params = match span.params {
+                SpecificParams::Text(p) => {
+                    let start = self.history_text.len();
+                    self.history_text.push_str(p);
+                    RecordedParams::Text(start..self.history_text.len())
+                }
+                SpecificParams::Block(p) => RecordedParams::Block(p),
+            };
+            self.history.push(SpanRecord {
+                common: span.common.clone(),
+                params,
+                user_data: span.user_data,
+            });
+        }
         match span.params {
-            SpecificParams::Text(p) => self.append_text(span.common, p, span.user_data),
+            SpecificParams::Text(p) => {
+                self.append_text(span.common, p, &[span.user_data], advance_override);
+            }
             SpecificParams::Block(p) => self.append_block(span.common, p, span.user_data),
         }
+        start..self.glyph_len
     }
 
-    /// Performs layout for text horizontally, and wrapping vertically. This makes a best effort
-    /// attempt at laying out the text defined in the given styles with the provided layout
-    /// settings. Text may overflow out of the bounds defined in the layout settings and it's up
-    /// to the application to decide how to deal with this.
-    ///
-    /// Characters from the input string can only be omitted from the output, they are never
-    /// reordered. The output buffer will always contain characters in the order they were defined
-    /// in the styles.
-    fn append_text<'t>(&mut self, params: CommonParams<'f>, text: &'t str, user_data: U) {
-        // The first layout pass requires some text.
-        if text.is_empty() {
-            return;
+    /// Performs layout for text horizontally like `Layout::append`, but if the span's text doesn't
+    /// fit in the rest of the current line, characters at the given position are replaced with an
+    /// ellipsis until it does. This is meant for single line labels, and does nothing special if
+    /// the max_width isn't set or the span is a block. The text is measured without kerning pairs
+    /// or ligatures. If the font doesn't have an ellipsis character, three periods are used.
+    /// Returns the range of glyphs produced, like `Layout::append`.
+    pub fn append_truncated<'t>(
+        &mut self,
+        span: Span<'f, 't, U>,
+        position: EllipsisPosition,
+    ) -> Range<usize> {
+        let text = match span.params {
+            SpecificParams::Text(text) => text,
+            SpecificParams::Block(_) => return self.append(span),
+        };
+        let common = self.resolve_script(span.common.clone());
+        let font = common.font.unwrap_or(self.base_font);
+        let px = sanitize_px(common.px.unwrap_or(self.base_px));
+        let (kerning, rounding) = (common.kerning, self.settings.rounding);
+        let available = self.max_width - (self.current_pos - self.start_pos);
+        if measure_text(font, text, px, kerning, rounding) <= available {
+            return self.append(span);
         }
 
-        let font = params.font.unwrap_or(self.base_font);
-        let px = params.px.unwrap_or(self.base_px);
-
-        if let Some(metrics) = font.horizontal_line_metrics(px) {
-            self.current_ascent = ceil(metrics.ascent);
-            self.current_new_line = ceil(metrics.new_line_size);
-            self.current_descent = ceil(metrics.descent);
-            self.current_line_gap = ceil(metrics.line_gap);
-            self.current_line_height = params.line_height;
-            self.update_last_line_metrics();
-        }
-
-        let mut byte_offset = 0;
-        while byte_offset < text.len() {
-            let character = read_utf8(text.as_bytes(), &mut byte_offset);
-            let linebreak = self.linebreaker.next(character).mask(self.wrap_mask);
-            let glyph_index = font.lookup_glyph_index(character);
-            let char_data = CharacterData::classify(character, glyph_index);
-            let whitespace = char_data.is_whitespace();
-            let metrics = if !char_data.is_control() {
-                font.metrics_indexed(glyph_index, px)
-            } else {
-                Metrics::default()
+        let ellipsis = if font.has_glyph('…') {
+            "…"
+        } else {
+            "..."
+        };
+        let advance = |c: char| rounding.advance(font.metrics(c, px).advance_width + kerning);
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        // Characters are kept from each end while they fit, alternating between the ends for the
+        // middle position.
+        let (mut head, mut tail) = (0, 0);
+        let mut width = measure_text(font, ellipsis, px, kerning, rounding);
+        loop {
+            // The ends characters may be kept from, in the order they're tried.
+            let ends: &[bool] = match position {
+                EllipsisPosition::Start => &[false],
+                EllipsisPosition::Middle if head <= tail => &[true, false],
+                EllipsisPosition::Middle => &[false, true],
+                EllipsisPosition::End => &[true],
             };
-            let advance = ceil(metrics.advance_width + params.kerning);
+            let fits = ends.iter().copied().find(|&from_head| {
+                head + tail < chars.len() && {
+                    let c = if from_head {
+                        chars[head].1
+                    } else {
+                        chars[chars.len() - 1 - tail].1
+                    };
+                    width + advance(c) <= available
+                }
+            });
+            match fits {
+                Some(true) => {
+                    width += advance(chars[head].1);
+                    head += 1;
+                }
+                Some(false) => {
+                    width += advance(chars[chars.len() - 1 - tail].1);
+                    tail += 1;
+                }
+                None => break,
+            }
+        }
 
-            if linebreak >= self.linebreak_prev {
-                self.linebreak_prev = linebreak;
-                self.linebreak_pos = self.current_pos;
-                self.linebreak_idx = self.glyphs.len().saturating_sub(1); // Mark the previous glyph
+        let head_end = chars.get(head).map_or(text.len(), |(index, _)| *index);
+        let tail_start = chars.get(chars.len() - tail).map_or(text.len(), |(index, _)| *index);
+        let mut truncated = String::with_capacity(head_end + ellipsis.len() + text.len() - tail_start);
+        truncated.push_str(&text[..head_end]);
+        truncated.push_str(ellipsis);
+        truncated.push_str(&text[tail_start..]);
+        self.append(Span {
+            common: span.common,
+            params: SpecificParams::Text(&truncated),
+            user_data: span.user_data,
+        })
+    }
+
+    /// Lays out the given span right aligned to the max_width, filling the space before it on the
+    /// current line with repeated leader characters, like the dots between a heading and its page
+    /// number in a table of contents. The leaders are laid out as text in the span's style and
+    /// with its user data, so they're positioned glyphs like any other. If the max_width isn't set
+    /// or the span doesn't fit in the rest of the current line, the span is appended as is. Text is
+    /// measured without kerning pairs or ligatures. Returns the range of glyphs produced, including
+    /// the leaders, like `Layout::append`.
+    pub fn append_leader<'t>(&mut self, leader: char, span: Span<'f, 't, U>) -> Range<usize> {
+        let common = self.resolve_script(span.common.clone());
+        let font = common.font.unwrap_or(self.base_font);
+        let px = sanitize_px(common.px.unwrap_or(self.base_px));
+        let (kerning, rounding) = (common.kerning, self.settings.rounding);
+        let width = match span.params {
+            SpecificParams::Text(text) => measure_text(font, text, px, kerning, rounding),
+            SpecificParams::Block(block) => block.width as f32,
+        };
+        let end_x = self.max_width - width;
+        let available = end_x - (self.current_pos - self.start_pos);
+        let advance = rounding.advance(font.metrics(leader, px).advance_width + kerning);
+        if self.max_width == core::f32::MAX || available < 0.0 || advance <= 0.0 {
+            return self.append(span);
+        }
+
+        let leaders: String = core::iter::repeat(leader).take((available / advance) as usize).collect();
+        let start = self.glyph_len;
+        self.append(Span {
+            common: span.common.clone(),
+            params: SpecificParams::Text(&leaders),
+            user_data: span.user_data,
+        });
+        if self.records_spans() {
+            self.history.push(SpanRecord {
+                common: CommonParams::default(),
+                params: RecordedParams::Column {
+                    x: end_x,
+                    new_row: false,
+                },
+                user_data: span.user_data,
+            });
+        }
+        self.move_to_column(end_x, false);
+        start..self.append(span).end
+    }
+
+    /// Lays out the given cells in columns, like `Layout::append` does for each span. Each cell
+    /// starts at the x of its column, measured from the left side of the region text is laid out
+    /// in, and a new line is started after every column is filled. If a cell reaches past the start
+    /// of the next column, the next cell starts where it ends instead. The first row starts on the
+    /// current line.
+    pub fn append_columns<'t>(&mut self, cells: &[Span<'f, 't, U>], column_x: &[f32]) {
+        if column_x.is_empty() {
+            return;
+        }
+        for (index, cell) in cells.iter().enumerate() {
+            let x = column_x[index % column_x.len()];
+            let new_row = index > 0 && index % column_x.len() == 0;
+            if self.records_spans() {
+                self.history.push(SpanRecord {
+                    common: CommonParams::default(),
+                    params: RecordedParams::Column {
+                        x,
+                        new_row,
+                    },
+                    user_data: cell.user_data,
+                });
+            }
+            self.move_to_column(x, new_row);
+            self.append(cell.clone());
+        }
+    }
+
+    /// Lays out the given spans as a paragraph, like `Layout::append` does for each span. The
+    /// paragraph starts on a new line, unless the current line is empty, and its lines use the
+    /// given settings in place of the layout's. Text appended afterwards continues the paragraph's
+    /// last line, and the lines after it and the spans appended afterwards use the layout's
+    /// settings again. An empty list of spans does nothing. Returns the range of glyphs produced,
+    /// like `Layout::append`.
+    pub fn append_paragraph<'t>(
+        &mut self,
+        spans: &[Span<'f, 't, U>],
+        settings: ParagraphSettings,
+    ) -> Range<usize> {
+        let first = match spans.first() {
+            Some(first) => first,
+            None => return self.glyph_len..self.glyph_len,
+        };
+        if self.records_spans() {
+            self.history.push(SpanRecord {
+                common: CommonParams::default(),
+                params: RecordedParams::Paragraph(settings),
+                user_data: first.user_data,
+            });
+        }
+        self.start_paragraph(settings);
+        let start = self.glyph_len;
+        for span in spans {
+            self.append(span.clone());
+        }
+        if self.records_spans() {
+            self.history.push(SpanRecord {
+                common: CommonParams::default(),
+                params: RecordedParams::ParagraphEnd,
+                user_data: first.user_data,
+            });
+        }
+        self.end_paragraph();
+        start..self.glyph_len
+    }
+
+    /// Lays out a single character in the given font and size, like appending a span of it
+    /// without building one, which is useful for text typed a character at a time. Characters
+    /// pushed one after another in the same font and size are laid out exactly like a single span
+    /// of them, so they're kerned, joined into ligatures and fractions, and hyphenated together.
+    /// Each push only lays out again the characters at the end of the run whose ligature, fraction,
+    /// or hyphenated word could still change, so it costs about as much as appending the character.
+    /// Like after appending, the layout needs to be finalized afterwards.
+    pub fn push_char(&mut self, character: char, font: &'f Font, px: f32, user_data: U) {
+        let common = CommonParams {
+            font: Some(font),
+            px: Some(px),
+            ..CommonParams::default()
+        };
+        if self.records_spans() {
+            self.history.push(SpanRecord {
+                common: common.clone(),
+                params: RecordedParams::Char(character),
+                user_data,
+            });
+        }
+        self.append_char(common, character, user_data);
+    }
+
+    /// Changes the layout settings and lays out every span appended since the last clear again,
+    /// without having to append them again. Like after appending, the layout needs to be
+    /// finalized afterwards. Spans are only recorded while `LayoutSettings::record_spans` is
+    /// enabled, and the new settings need it enabled to relayout again later.
+    pub fn relayout(&mut self, settings: &LayoutSettings) {
+        let history = mem::take(&mut self.history);
+        let history_text = mem::take(&mut self.history_text);
+        self.reset(settings);
+        for record in &history {
+            let common = record.common.clone();
+            match &record.params {
+                RecordedParams::Text(range) => {
+                    let text = &history_text[range.clone()];
+                    self.append_text(common, text, &[record.user_data], |_, advance| advance);
+                }
+                RecordedParams::Char(character) => self.append_char(common, *character, record.user_data),
+                RecordedParams::Block(block) => self.append_block(common, *block, record.user_data),
+                RecordedParams::Column {
+                    x,
+                    new_row,
+                } => self.move_to_column(*x, *new_row),
+                RecordedParams::Paragraph(settings) => self.start_paragraph(*settings),
+                RecordedParams::ParagraphEnd => self.end_paragraph(),
+            }
+        }
+        if self.settings.record_spans {
+            self.history = history;
+            self.history_text = history_text;
+        }
+    }
+
+    /// If appended spans are recorded for `Layout::relayout`. Nothing is recorded while glyphs are
+    /// only being measured.
+    fn records_spans(&self) -> bool {
+        self.settings.record_spans && !self.dry_run
+    }
+
+    /// Replaces a superscript or subscript preset with the size and rise it stands for, using the
+    /// metrics of the span's font at the span's size.
+    fn resolve_script(&self, mut params: CommonParams<'f>) -> CommonParams<'f> {
+        if let Some(position) = params.script.take() {
+            let font = params.font.unwrap_or(self.base_font);
+            let px = sanitize_px(params.px.unwrap_or(self.base_px));
+            let (metrics, direction) = match position {
+                ScriptPosition::Superscript => (font.superscript_metrics(px), 1.0),
+                ScriptPosition::Subscript => (font.subscript_metrics(px), -1.0),
+            };
+            let metrics = metrics.unwrap_or(ScriptMetrics {
+                px: px * 0.7,
+                rise: direction * px * 0.3,
+            });
+            params.px = Some(metrics.px);
+            params.rise += metrics.rise;
+        }
+        params
+    }
+
+    /// Performs layout for text horizontally, and wrapping vertically. This makes a best effort
+    /// attempt at laying out the text defined in the given styles with the provided layout
+    /// settings. Text may overflow out of the bounds defined in the layout settings and it's up
+    /// to the application to decide how to deal with this.
+    ///
+    /// Characters from the input string can only be omitted from the output, they are never
+    /// reordered. The output buffer will always contain characters in the order they were defined
+    /// in the styles.
+    ///
+    /// The user data is either the span's, for every character, or one for each character. Glyphs
+    /// formed from several characters with their own user data merge it like characters of
+    /// separate spans.
+    fn append_text<'t, F: FnMut(u16, f32) -> f32>(
+        &mut self,
+        params: CommonParams<'f>,
+        text: &'t str,
+        char_user_data: &[U],
+        advance_override: F,
+    ) {
+        self.pushed_run = None;
+        let state = TextState::new(self.char_glyphs.len());
+        self.append_text_from(params, text, char_user_data, state, None, advance_override);
+    }
+
+    /// Lays out the text like `Layout::append_text`, continuing from the given state partway through
+    /// it. If a checkpoint is given, it's saved before the first character whose layout could
+    /// change if more text followed, or at the end of the text if there's none, so laying out the
+    /// text again with more after it can continue from there.
+    fn append_text_from<'t, F: FnMut(u16, f32) -> f32>(
+        &mut self,
+        params: CommonParams<'f>,
+        text: &'t str,
+        char_user_data: &[U],
+        mut state: TextState,
+        mut resume: Option<&mut Option<TextCheckpoint<'f, U>>>,
+        mut advance_override: F,
+    ) {
+        // The first layout pass requires some text.
+        if text.is_empty() {
+            return;
+        }
+
+        let params = self.resolve_script(params);
+        let font = params.font.unwrap_or(self.base_font);
+        let px = sanitize_px(params.px.unwrap_or(self.base_px));
+        let rounding = self.settings.rounding;
+        // The rise is snapped once for the span rather than per glyph, otherwise glyphs with
+        // different fractional bounds would round to different offsets from the baseline.
+        let rise = rounding.position(params.rise);
+        let underline = if params.underline {
+            let metrics = font.underline_metrics(px);
+            Some(snap_decoration(metrics, px, -0.1))
+        } else {
+            None
+        };
+        let strikethrough = if params.strikethrough {
+            let metrics = font.strikeout_metrics(px);
+            Some(snap_decoration(metrics, px, 0.3))
+        } else {
+            None
+        };
+
+        if let Some(metrics) = font.horizontal_line_metrics(px) {
+            self.set_current_metrics(
+                metrics.ascent,
+                metrics.descent,
+                metrics.line_gap,
+                metrics.new_line_size,
+                params.line_height,
+            );
+        }
+
+        let features = self.settings.features;
+        let hyphenator = self.settings.hyphenator.filter(|_| self.wrap_mask.mask(LINEBREAK_SOFT).is_soft());
+        // Checkpoints are saved until a character is found that looks past the end of the text.
+        let mut saving = resume.is_some();
+        while state.byte_offset < text.len() {
+            if let (true, Some(resume)) = (saving, resume.as_mut()) {
+                self.save_checkpoint(&state, resume);
+            }
+            let char_start = state.byte_offset;
+            let character = read_utf8(text.as_bytes(), &mut state.byte_offset);
+            self.count_script(Script::of(character));
+            let glyph_override = self.glyph_overrides.get(&character).copied();
+            let mut linebreak = self.linebreaker.next(character).mask(self.wrap_mask);
+            // Mirrored characters fall back to their own glyph if the font lacks the mirror.
+            let mirrored_index = match unicode::mirror(character) {
+                Some(mirrored) if params.direction == Direction::RightToLeft => {
+                    font.lookup_glyph_index(mirrored)
+                }
+                _ => 0,
+            };
+            let glyph_index = if mirrored_index != 0 {
+                mirrored_index
+            } else {
+                font.lookup_glyph_index(character)
+            };
+            let (glyph_index, glyph_px) = if params.small_caps || features.contains(FeatureSet::SMCP) {
+                small_cap_glyph(font, character, glyph_index, px)
+            } else {
+                (glyph_index, px)
+            };
+            if state.fraction.map_or(false, |(_, end)| char_start >= end) {
+                state.fraction = None;
+            }
+            if state.fraction.is_none()
+                && features.contains(FeatureSet::FRAC)
+                && (char_start == 0 || !text.as_bytes()[char_start - 1].is_ascii_digit())
+            {
+                state.fraction = find_fraction(&text[char_start..])
+                    .map(|(slash, end)| (char_start + slash, char_start + end));
+                saving = saving && !fraction_may_extend(&text[char_start..]);
+            }
+            let (glyph_index, glyph_px, fraction_rise) = match state.fraction {
+                Some((slash, _)) => {
+                    let part = if char_start < slash {
+                        FractionPart::Numerator
+                    } else if char_start == slash {
+                        FractionPart::Slash
+                    } else {
+                        FractionPart::Denominator
+                    };
+                    fraction_glyph(font, part, glyph_index, px)
+                }
+                None => (glyph_index, glyph_px, 0.0),
+            };
+            let (glyph_font, glyph_index, glyph_px, fraction_rise) = match glyph_override {
+                Some((override_font, override_index)) => (override_font, override_index, px, 0.0),
+                None => (font, glyph_index, glyph_px, fraction_rise),
+            };
+            // A ligature consumes the characters it's formed from, which still pass through the
+            // linebreaker so later break opportunities are found.
+            let ligature = if features.contains(FeatureSet::LIGA) && glyph_override.is_none() {
+                saving = saving && !ligature_may_extend(font, glyph_index, text, state.byte_offset);
+                match_ligature(font, glyph_index, text, state.byte_offset)
+            } else {
+                None
+            };
+            let mut char_count = 1;
+            let glyph_index = match ligature {
+                Some((ligature_index, end)) => {
+                    while state.byte_offset < end {
+                        char_count += 1;
+                        let component = read_utf8(text.as_bytes(), &mut state.byte_offset);
+                        self.count_script(Script::of(component));
+                        self.linebreaker.next(component);
+                    }
+                    ligature_index
+                }
+                None => glyph_index,
+            };
+            let user_data = match char_user_data {
+                [user_data] => *user_data,
+                _ => {
+                    let chars = &char_user_data[self.char_glyphs.len() - state.first_char..][..char_count];
+                    chars[1..].iter().fold(chars[0], |kept, &next| self.merge_user_data(kept, next))
+                }
+            };
+            let char_data = CharacterData::classify(character, glyph_index);
+            let whitespace = char_data.is_whitespace();
+
+            // Collapsed whitespace is omitted, but a hard break it carries is kept for the next
+            // glyph that's laid out.
+            if whitespace
+                && self.settings.collapse_whitespace
+                && !self.prev_not_whitespace
+                && self.glyph_len > 0
+            {
+                self.pending_hard_break |= linebreak.is_hard();
+                let omitted = self.text_len + char_start..self.text_len + state.byte_offset;
+                match self.omitted.last_mut() {
+                    Some(last) if last.end == omitted.start => last.end = omitted.end,
+                    _ => self.omitted.push(omitted),
+                }
+                self.char_glyphs.extend(core::iter::repeat(None).take(char_count));
+                if let Some(kept) = self.glyphs.last().map(|glyph| glyph.user_data) {
+                    let merged = self.merge_user_data(kept, user_data);
+                    if let Some(glyph) = self.glyphs.last_mut() {
+                        glyph.user_data = merged;
+                    }
+                }
+                continue;
+            }
+            if self.pending_hard_break {
+                self.pending_hard_break = false;
+                linebreak = LINEBREAK_HARD;
+            }
+
+            let metrics = if !char_data.is_control() {
+                glyph_font.metrics_indexed(glyph_index, glyph_px)
+            } else {
+                Metrics::default()
+            };
+            let advance =
+                advance_override(glyph_index, rounding.advance(metrics.advance_width + params.kerning));
+            // Invisible characters keep their advance, but are drawn with their symbol's glyph.
+            let symbol_index = match invisible_symbol(character) {
+                Some(symbol) if self.settings.show_invisibles => glyph_font.lookup_glyph_index(symbol),
+                _ => 0,
+            };
+            let (glyph_index, char_data, metrics) = if symbol_index != 0 {
+                (symbol_index, char_data.with_symbol(), glyph_font.metrics_indexed(symbol_index, glyph_px))
+            } else {
+                (glyph_index, char_data, metrics)
+            };
+            // Shown whitespace is treated like any other visible glyph when finding line ends.
+            let trailing = whitespace && !char_data.is_symbol();
+            // Zero advance marks sit on the preceding glyph's anchor when the font positions them.
+            // The base is in the span's font, so overridden glyphs are neither kerned nor marks.
+            if glyph_override.is_some() {
+                state.mark_base = None;
+            }
+            let mark_offset = match state.mark_base {
+                Some((base, origin))
+                    if metrics.advance_width == 0.0
+                        && !char_data.is_control()
+                        && features.contains(FeatureSet::MARK) =>
+                {
+                    font.mark_offset(base, glyph_index, glyph_px)
+                        .map(|(x, y)| (origin + x, rounding.offset(y)))
+                }
+                _ => None,
+            };
+            if let Some(hyphenator) = hyphenator {
+                if char_start >= state.word_end && !whitespace {
+                    state.word_end = text[char_start..]
+                        .find(char::is_whitespace)
+                        .map_or(text.len(), |end| char_start + end);
+                    saving &= state.word_end < text.len();
+                    let word = &text[char_start..state.word_end];
+                    let line_width = self.current_pos - self.start_pos;
+                    state.hyphen_points.clear();
+                    if line_width + measure_text(font, word, px, params.kerning, rounding) > self.wrap_width {
+                        state.hyphen_points.extend(
+                            hyphenator(word)
+                                .into_iter()
+                                .filter(|&point| {
+                                    point > 0 && point < word.len() && word.is_char_boundary(point)
+                                })
+                                .map(|point| char_start + point),
+                        );
+                        state.hyphen_points.sort_unstable_by(|a, b| b.cmp(a));
+                        state.hyphen_points.dedup();
+                    }
+                }
+                // Points inside a ligature or before a mark are skipped.
+                while state.hyphen_points.last().map_or(false, |&point| point < char_start) {
+                    state.hyphen_points.pop();
+                }
+                if state.hyphen_points.last() == Some(&char_start)
+                    && metrics.advance_width != 0.0
+                    && !char_data.is_control()
+                {
+                    state.hyphen_points.pop();
+                    // Break here if the line can't reach the next point, along with the hyphen
+                    // that would end it there.
+                    let hyphen = measure_text(font, "-", px, params.kerning, rounding);
+                    let (fragment_end, fragment_hyphen) = match state.hyphen_points.last() {
+                        Some(&point) => (point, hyphen),
+                        None => (state.word_end, 0.0),
+                    };
+                    let fragment =
+                        measure_text(font, &text[char_start..fragment_end], px, params.kerning, rounding);
+                    let line_width = self.current_pos - self.start_pos;
+                    if line_width + fragment + fragment_hyphen > self.wrap_width
+                        && line_width + hyphen <= self.wrap_width
+                    {
+                        self.break_with_hyphen(
+                            font,
+                            px,
+                            rise,
+                            &params,
+                            (underline, strikethrough),
+                            user_data,
+                        );
+                        state.mark_base = None;
+                    }
+                }
+            }
+            if let Some((left, _)) = state.mark_base {
+                if metrics.advance_width != 0.0 && features.contains(FeatureSet::KERN) {
+                    if let Some(kern) = font.horizontal_kern_indexed(left, glyph_index, glyph_px) {
+                        self.current_pos += rounding.offset(kern);
+                    }
+                }
             }
 
-            if self.prev_not_whitespace && (self.wrap_by_letter || whitespace) {
+            self.mark_linebreak(linebreak);
+
+            if self.prev_not_whitespace && (self.wrap_by_letter || trailing) {
                 self.line_end_pos = self.current_pos;
-                self.line_end_idx = self.glyphs.len().saturating_sub(!whitespace as usize);
+                self.line_end_idx = self.glyph_len.saturating_sub(!trailing as usize);
             }
 
             // Perform a linebreak
             if linebreak.is_hard()
-                || (self.current_pos - self.start_pos + advance > self.max_width && !whitespace)
+                || (self.current_pos - self.start_pos + advance > self.wrap_width && !whitespace)
             {
                 self.perform_linebreak(&linebreak);
             }
 
+            // Positioned from the whole pixel bitmap bounds so glyphs of every size share the
+            // baseline exactly.
+            let (pen, rise) = match mark_offset {
+                Some((pen, mark_rise)) => (pen, rise + fraction_rise + mark_rise),
+                None => (self.current_pos, rise + fraction_rise),
+            };
             let y = if self.flip {
-                floor(-metrics.bounds.height - metrics.bounds.ymin - params.rise)
-            // PositiveYDown
+                -(metrics.ymin as f32 + metrics.height as f32) - rise // PositiveYDown
             } else {
-                floor(metrics.bounds.ymin + params.rise) // PositiveYUp
+                metrics.ymin as f32 + rise // PositiveYUp
             };
 
-            self.glyphs.push(GlyphPosition {
+            // Unsnapped glyphs are placed by their bitmap, which already offsets the outline by the
+            // fractional part of its bounds. Snapped glyphs are placed by their outline, so the
+            // bitmap's padding is moved out to the left of it.
+            let x = match rounding {
+                RoundMode::None => pen + metrics.xmin as f32,
+                _ => rounding.position(pen + metrics.bounds.xmin) - glyph_font.raster_padding() as f32,
+            };
+            if (underline.is_some() || strikethrough.is_some()) && !self.dry_run {
+                self.decorated.push(DecoratedGlyph {
+                    index: self.glyph_len,
+                    pen_offset: self.current_pos - x,
+                    advance,
+                    underline,
+                    strikethrough,
+                });
+            }
+            self.char_glyphs.extend(core::iter::repeat(Some(self.glyph_len)).take(char_count));
+            self.push_glyph(GlyphPosition {
                 key: Some(GlyphRasterConfig {
                     glyph_index: glyph_index as u16,
-                    px,
-                    font_hash: font.file_hash(),
+                    px: glyph_px,
+                    font_hash: glyph_font.file_hash(),
                 }),
-                font,
+                font: glyph_font,
                 parent: character,
-                x: floor(self.current_pos + metrics.bounds.xmin),
+                x,
                 y,
                 width: metrics.width,
                 height: metrics.height,
+                advance,
                 char_data,
+                color: params.color,
+                alpha: 1.0,
                 user_data,
             });
+            if whitespace || char_data.is_control() || glyph_override.is_some() {
+                state.mark_base = None;
+            } else if metrics.advance_width != 0.0 {
+                state.mark_base = Some((glyph_index, self.current_pos));
+            }
             self.current_pos += advance;
-            self.prev_not_whitespace = !whitespace;
+            self.prev_not_whitespace = !trailing;
+        }
+        if let (true, Some(resume)) = (saving, resume) {
+            self.save_checkpoint(&state, resume);
         }
+        self.text_len += text.len();
 
         if let Some(line) = self.line_metrics.last_mut() {
-            line.padding = self.max_width - (self.current_pos - self.start_pos);
-            line.glyph_end = self.glyphs.len().saturating_sub(1);
+            line.width = self.current_pos - self.start_pos;
+            line.padding = self.max_width - line.width;
+            line.glyph_end = self.glyph_len.saturating_sub(1);
         }
+
+        if self.settings.trailing_newline_line {
+            self.start_trailing_line();
+        }
+    }
+
+    /// Combines the user data a glyph has so far with the user data of another character merged
+    /// into it, using the merge function if one is set, otherwise the user_data_merge setting.
+    fn merge_user_data(&self, kept: U, next: U) -> U {
+        match self.user_data_merger {
+            Some(merge) => merge(kept, next),
+            None if self.settings.user_data_merge == UserDataMerge::Last => next,
+            None => kept,
+        }
+    }
+
+    /// Lays out a single character with the given params, continuing the run of characters laid
+    /// out this way before it if it's in the same font and size. The layout is restored to the
+    /// run's checkpoint, and the run is laid out again as a single span from there.
+    fn append_char(&mut self, params: CommonParams<'f>, character: char, user_data: U) {
+        let font = params.font.unwrap_or(self.base_font);
+        let px = params.px.unwrap_or(self.base_px);
+        let mut run = match self.pushed_run.take() {
+            Some(run) if core::ptr::eq(run.font, font) && run.px == px => run,
+            _ => PushedRun {
+                font,
+                px,
+                text: String::new(),
+                user_data: Vec::new(),
+                resume: None,
+            },
+        };
+        let state = match &run.resume {
+            Some(checkpoint) => self.restore_checkpoint(checkpoint),
+            None => TextState::new(self.char_glyphs.len()),
+        };
+        run.text.push(character);
+        run.user_data.push(user_data);
+        self.append_text_from(
+            params,
+            &run.text,
+            &run.user_data,
+            state,
+            Some(&mut run.resume),
+            |_, advance| advance,
+        );
+        self.pushed_run = Some(run);
+    }
+
+    /// Saves the layout state before the character the given state is at, reusing the buffers of
+    /// the checkpoint saved before it, if any.
+    fn save_checkpoint(&self, state: &TextState, checkpoint: &mut Option<TextCheckpoint<'f, U>>) {
+        let (mut glyphs, mut scripts, mut hyphen_points) = match checkpoint.take() {
+            Some(old) => (old.glyphs, old.scripts, old.state.hyphen_points),
+            None => (Vec::new(), Vec::new(), Vec::new()),
+        };
+        // Collapsed whitespace merges its user data into the last glyph, and breaking a justified
+        // line moves every glyph on it.
+        let last_line = self.line_metrics[self.line_metrics.len() - 1];
+        let mut glyphs_start = self.glyph_len.saturating_sub(1);
+        if self.justify {
+            glyphs_start = glyphs_start.min(last_line.glyph_start);
+        }
+        let glyphs_start = glyphs_start.min(self.glyphs.len());
+        glyphs.clear();
+        glyphs.extend_from_slice(&self.glyphs[glyphs_start..]);
+        scripts.clone_from(&self.scripts);
+        hyphen_points.clone_from(&state.hyphen_points);
+        *checkpoint = Some(TextCheckpoint {
+            state: TextState {
+                hyphen_points,
+                ..*state
+            },
+            glyph_len: self.glyph_len,
+            glyphs_start,
+            glyphs,
+            decorated_len: self.decorated.len(),
+            char_glyphs_len: self.char_glyphs.len(),
+            omitted_len: self.omitted.len(),
+            last_omitted: self.omitted.last().cloned(),
+            line_count: self.line_metrics.len(),
+            last_line,
+            scripts,
+            linebreaker: self.linebreaker,
+            linebreak_prev: self.linebreak_prev,
+            linebreak_pos: self.linebreak_pos,
+            linebreak_idx: self.linebreak_idx,
+            pending_hard_break: self.pending_hard_break,
+            text_len: self.text_len,
+            prev_not_whitespace: self.prev_not_whitespace,
+            line_end_pos: self.line_end_pos,
+            line_end_idx: self.line_end_idx,
+            current_pos: self.current_pos,
+            start_pos: self.start_pos,
+            height: self.height,
+        });
+    }
+
+    /// Restores the layout state saved in the checkpoint, and returns the state to continue laying
+    /// out the text from. The lines keep the baselines they were last finalized with.
+    fn restore_checkpoint(&mut self, checkpoint: &TextCheckpoint<'f, U>) -> TextState {
+        self.glyph_len = checkpoint.glyph_len;
+        self.glyphs.truncate(checkpoint.glyphs_start);
+        self.glyphs.extend_from_slice(&checkpoint.glyphs);
+        self.decorated.truncate(checkpoint.decorated_len);
+        self.char_glyphs.truncate(checkpoint.char_glyphs_len);
+        self.omitted.truncate(checkpoint.omitted_len);
+        if let (Some(last), Some(saved)) = (self.omitted.last_mut(), &checkpoint.last_omitted) {
+            *last = saved.clone();
+        }
+        self.line_metrics.truncate(checkpoint.line_count);
+        if let Some(line) = self.line_metrics.last_mut() {
+            *line = LinePosition {
+                baseline_y: line.baseline_y,
+                ..checkpoint.last_line
+            };
+        }
+        self.scripts.clone_from(&checkpoint.scripts);
+        self.linebreaker = checkpoint.linebreaker;
+        self.linebreak_prev = checkpoint.linebreak_prev;
+        self.linebreak_pos = checkpoint.linebreak_pos;
+        self.linebreak_idx = checkpoint.linebreak_idx;
+        self.pending_hard_break = checkpoint.pending_hard_break;
+        self.text_len = checkpoint.text_len;
+        self.prev_not_whitespace = checkpoint.prev_not_whitespace;
+        self.line_end_pos = checkpoint.line_end_pos;
+        self.line_end_idx = checkpoint.line_end_idx;
+        self.current_pos = checkpoint.current_pos;
+        self.start_pos = checkpoint.start_pos;
+        self.height = checkpoint.height;
+        let state = &checkpoint.state;
+        TextState {
+            hyphen_points: state.hyphen_points.clone(),
+            ..*state
+        }
+    }
+
+    /// Starts the empty line after a hard break at the end of the appended text, if there is one.
+    /// The linebreaker is reset like it's at the start of the text, so the break isn't reported
+    /// again on the next character.
+    fn start_trailing_line(&mut self) {
+        let mut linebreaker = self.linebreaker;
+        if !linebreaker.next('a').mask(self.wrap_mask).is_hard() {
+            return;
+        }
+        self.linebreaker.reset();
+        self.pending_hard_break = false;
+        self.linebreak_pos = self.current_pos;
+        self.linebreak_idx = self.glyph_len.saturating_sub(1);
+        self.perform_linebreak(&LINEBREAK_HARD);
+        if let Some(line) = self.line_metrics.last_mut() {
+            line.width = 0.0;
+            line.padding = self.max_width;
+        }
+    }
+
+    /// Ends the current line with a hyphen at the pen position, for breaking a word at a point
+    /// chosen by the hyphenator.
+    fn break_with_hyphen(
+        &mut self,
+        font: &'f Font,
+        px: f32,
+        rise: f32,
+        params: &CommonParams<'f>,
+        (underline, strikethrough): (Option<(f32, f32)>, Option<(f32, f32)>),
+        user_data: U,
+    ) {
+        let rounding = self.settings.rounding;
+        let glyph_index = font.lookup_glyph_index('-');
+        let metrics = font.metrics_indexed(glyph_index, px);
+        let advance = rounding.advance(metrics.advance_width + params.kerning);
+        let y = if self.flip {
+            -(metrics.ymin as f32 + metrics.height as f32) - rise // PositiveYDown
+        } else {
+            metrics.ymin as f32 + rise // PositiveYUp
+        };
+        let x = match rounding {
+            RoundMode::None => self.current_pos + metrics.xmin as f32,
+            _ => rounding.position(self.current_pos + metrics.bounds.xmin) - font.raster_padding() as f32,
+        };
+        if (underline.is_some() || strikethrough.is_some()) && !self.dry_run {
+            self.decorated.push(DecoratedGlyph {
+                index: self.glyph_len,
+                pen_offset: self.current_pos - x,
+                advance,
+                underline,
+                strikethrough,
+            });
+        }
+        self.push_glyph(GlyphPosition {
+            key: Some(GlyphRasterConfig {
+                glyph_index,
+                px,
+                font_hash: font.file_hash(),
+            }),
+            font,
+            parent: '-',
+            x,
+            y,
+            width: metrics.width,
+            height: metrics.height,
+            advance,
+            char_data: CharacterData::classify('-', glyph_index),
+            color: params.color,
+            alpha: 1.0,
+            user_data,
+        });
+        self.current_pos += advance;
+        // The hyphen is the last glyph on the line, and the word continues on the next.
+        self.line_end_pos = self.current_pos;
+        self.line_end_idx = self.glyph_len - 1;
+        self.linebreak_pos = self.current_pos;
+        self.linebreak_idx = self.glyph_len - 1;
+        self.perform_linebreak(&LINEBREAK_SOFT);
     }
 
     /// Performs layout for an inline block horizontally, and wrapping vertically. An inline
     /// block is treated as a single empty glyph with the specified width and height, and
     /// it is up to the application to decide what to do with this reserved space.
     fn append_block(&mut self, params: CommonParams<'f>, block: Block, user_data: U) {
+        self.pushed_run = None;
         if block.width == 0 || block.height == 0 {
             return;
         }
 
+        let params = self.resolve_script(params);
         let font = params.font.unwrap_or(self.base_font);
-        let px = params.px.unwrap_or(self.base_px);
+        let px = sanitize_px(params.px.unwrap_or(self.base_px));
 
         if let (Some(metrics), BlockAlign::Middle) = (font.horizontal_line_metrics(px), block.align) {
             let font_height = metrics.ascent - metrics.descent;
             let block_ascent = metrics.ascent / font_height * block.height as f32;
             let block_descent = metrics.descent / font_height * block.height as f32;
-            self.current_ascent = ceil(block_ascent);
-            self.current_descent = ceil(block_descent);
-            self.current_new_line = ceil(block_ascent - block_descent + metrics.line_gap);
-            self.current_line_gap = ceil(metrics.line_gap);
+            self.set_current_metrics(
+                block_ascent,
+                block_descent,
+                metrics.line_gap,
+                block_ascent - block_descent + metrics.line_gap,
+                params.line_height,
+            );
         } else {
-            self.current_ascent = block.height as f32;
-            self.current_descent = 0.0;
-            self.current_new_line = self.current_ascent;
-            self.current_line_gap = 0.0;
+            let height = block.height as f32;
+            self.set_current_metrics(height, 0.0, 0.0, height, params.line_height);
         }
-        self.current_line_height = params.line_height;
-        self.update_last_line_metrics();
 
         let character = 'x';
-        let linebreak = self.linebreaker.next(character).mask(self.wrap_mask);
-        let char_data = CharacterData::classify(character, 0);
-        let advance = block.width as f32 + params.kerning;
-
-        if linebreak >= self.linebreak_prev {
-            self.linebreak_prev = linebreak;
-            self.linebreak_pos = self.current_pos;
-            self.linebreak_idx = self.glyphs.len().saturating_sub(1); // Mark the previous glyph
+        let mut linebreak = self.linebreaker.next(character).mask(self.wrap_mask);
+        if self.pending_hard_break {
+            self.pending_hard_break = false;
+            linebreak = LINEBREAK_HARD;
         }
 
+        self.mark_linebreak(linebreak);
+
         if self.prev_not_whitespace && self.wrap_by_letter {
             self.line_end_pos = self.current_pos;
-            self.line_end_idx = self.glyphs.len().saturating_sub(1);
+            self.line_end_idx = self.glyph_len.saturating_sub(1);
         }
 
-        if self.current_pos - self.start_pos + advance > self.max_width {
+        let mut width = block.width;
+        if block.breakable {
+            let room = |layout: &Self| floor(layout.wrap_width - (layout.current_pos - layout.start_pos));
+            if room(self) < 1.0 && width as f32 + params.kerning > room(self) {
+                self.perform_linebreak(&linebreak);
+            }
+            // Split off pieces that fill the rest of each line the block overflows.
+            loop {
+                let room = room(self);
+                if width as f32 + params.kerning <= room || room < 1.0 || room as usize >= width {
+                    break;
+                }
+                let piece = room as usize;
+                self.push_block(font, piece, block.height, piece as f32, &params, user_data);
+                self.line_end_pos = self.current_pos;
+                self.line_end_idx = self.glyph_len - 1;
+                self.linebreak_pos = self.current_pos;
+                self.linebreak_idx = self.glyph_len - 1;
+                self.perform_linebreak(&LINEBREAK_SOFT);
+                width -= piece;
+            }
+        }
+        let advance = width as f32 + params.kerning;
+
+        if self.current_pos - self.start_pos + advance > self.wrap_width {
             self.perform_linebreak(&linebreak);
         }
 
-        let y = if self.flip {
-            -self.current_ascent
+        self.push_block(font, width, block.height, advance, &params, user_data);
+        self.prev_not_whitespace = true;
+
+        if let Some(line) = self.line_metrics.last_mut() {
+            line.width = self.current_pos - self.start_pos;
+            line.padding = self.max_width - line.width;
+            line.glyph_end = self.glyph_len.saturating_sub(1);
+        }
+    }
+
+    /// Moves the pen to the given x on the current line, or on a new line if requested. The pen
+    /// never moves backwards.
+    fn move_to_column(&mut self, x: f32, new_row: bool) {
+        self.pushed_run = None;
+        if new_row {
+            if self.prev_not_whitespace {
+                self.line_end_pos = self.current_pos;
+                self.line_end_idx = self.glyph_len.saturating_sub(1);
+            }
+            self.linebreak_pos = self.current_pos;
+            self.linebreak_idx = self.glyph_len.saturating_sub(1);
+            self.perform_linebreak(&LINEBREAK_HARD);
+        }
+        self.current_pos = self.current_pos.max(self.start_pos + x);
+        if let Some(line) = self.line_metrics.last_mut() {
+            line.width = self.current_pos - self.start_pos;
+            line.padding = self.max_width - line.width;
+        }
+    }
+
+    /// Starts a paragraph with the given settings on a new line, unless the current line is empty.
+    /// The paragraph's first character is treated like the start of the text, so a hard break at
+    /// the end of the text before it doesn't start another line.
+    fn start_paragraph(&mut self, settings: ParagraphSettings) {
+        let line_start = self.line_metrics[self.line_metrics.len() - 1].glyph_start;
+        if self.glyph_len > line_start {
+            self.move_to_column(0.0, true);
+        }
+        self.linebreaker.reset();
+        self.pending_hard_break = false;
+        self.set_horizontal_align(settings.horizontal_align.unwrap_or(self.settings.horizontal_align));
+        self.paragraph_line_height = settings.line_height;
+        let space_before = if self.line_metrics.len() > 1 {
+            settings.space_before
+        } else {
+            0.0
+        };
+        self.height += space_before;
+        if let Some(line) = self.line_metrics.last_mut() {
+            line.align = self.horizontal_align;
+            line.space_before = space_before;
+        }
+        self.move_to_column(settings.indent, false);
+    }
+
+    /// Ends the current paragraph, so lines started from now on and spans appended afterwards use
+    /// the layout's alignment and line height. The current line keeps the paragraph's alignment.
+    fn end_paragraph(&mut self) {
+        self.set_horizontal_align(self.settings.horizontal_align);
+        self.paragraph_line_height = None;
+    }
+
+    /// Sets how lines started from now on are aligned. Alignment does nothing without a max_width.
+    fn set_horizontal_align(&mut self, align: HorizontalAlign) {
+        self.horizontal_align = if self.max_width == core::f32::MAX {
+            0.0
         } else {
-            self.current_descent
+            match align {
+                HorizontalAlign::Left | HorizontalAlign::Justify => 0.0,
+                HorizontalAlign::Center => 0.5,
+                HorizontalAlign::Right => 1.0,
+            }
         };
+        self.justify = align == HorizontalAlign::Justify;
+    }
 
-        self.glyphs.push(GlyphPosition {
+    /// Adds a block, or a piece of a block, at the pen position and advances the pen past it. Blocks
+    /// are treated as an "x" character.
+    fn push_block(
+        &mut self,
+        font: &'f Font,
+        width: usize,
+        height: usize,
+        advance: f32,
+        params: &CommonParams<'f>,
+        user_data: U,
+    ) {
+        // Both coordinate systems are derived from the ascent so the block lands on the same
+        // pixels either way.
+        let y = if self.flip {
+            -self.current_ascent // PositiveYDown
+        } else {
+            self.current_ascent - height as f32 // PositiveYUp
+        };
+        self.push_glyph(GlyphPosition {
             key: None,
             font,
-            parent: character,
-            x: floor(self.current_pos),
+            parent: 'x',
+            x: self.settings.rounding.position(self.current_pos),
             y,
-            width: block.width,
-            height: block.height,
-            char_data,
+            width,
+            height,
+            advance,
+            char_data: CharacterData::classify('x', 0),
+            color: params.color,
+            alpha: 1.0,
             user_data,
         });
         self.current_pos += advance;
-        self.prev_not_whitespace = true;
+    }
 
-        if let Some(line) = self.line_metrics.last_mut() {
-            line.padding = self.max_width - (self.current_pos - self.start_pos);
-            line.glyph_end = self.glyphs.len().saturating_sub(1);
+    /// Adds a positioned glyph to the intermediate glyph state. When only measuring, the glyph is
+    /// counted but not stored.
+    fn push_glyph(&mut self, glyph: GlyphPosition<'f, U>) {
+        self.glyph_len += 1;
+        if !self.dry_run {
+            self.glyphs.push(glyph);
+        }
+    }
+
+    /// Adds a character of the given script to the script tally.
+    fn count_script(&mut self, script: Script) {
+        match self.scripts.iter_mut().find(|(s, _)| *s == script) {
+            Some((_, count)) => *count += 1,
+            None => self.scripts.push((script, 1)),
+        }
+    }
+
+    /// Sets the metrics of the current style from unrounded metrics, applying the layout's
+    /// overrides and the current paragraph's line height, and updates the current line with them.
+    fn set_current_metrics(
+        &mut self,
+        ascent: f32,
+        descent: f32,
+        line_gap: f32,
+        new_line: f32,
+        line_height: Option<f32>,
+    ) {
+        let (ascent, descent, line_gap, new_line) =
+            self.override_metrics(ascent, descent, line_gap, new_line);
+        self.current_ascent = ceil(ascent);
+        self.current_descent = ceil(descent);
+        self.current_line_gap = ceil(line_gap);
+        self.current_new_line = ceil(self.clamp_new_line(new_line));
+        self.current_line_height = line_height.or(self.paragraph_line_height);
+        self.update_last_line_metrics();
+    }
+
+    /// Replaces a font's ascent, descent, and line gap with their overrides, if any are set, and
+    /// recomputes the distance a line advances from them.
+    fn override_metrics(
+        &self,
+        ascent: f32,
+        descent: f32,
+        line_gap: f32,
+        new_line: f32,
+    ) -> (f32, f32, f32, f32) {
+        let settings = &self.settings;
+        if settings.ascent_override.is_none()
+            && settings.descent_override.is_none()
+            && settings.line_gap_override.is_none()
+        {
+            return (ascent, descent, line_gap, new_line);
+        }
+        let ascent = settings.ascent_override.unwrap_or(ascent);
+        let descent = settings.descent_override.unwrap_or(descent);
+        let line_gap = settings.line_gap_override.unwrap_or(line_gap);
+        (ascent, descent, line_gap, ascent - descent + line_gap)
+    }
+
+    /// Raises the distance a line advances to the min_line_height, if set.
+    fn clamp_new_line(&self, new_line: f32) -> f32 {
+        match self.settings.min_line_height {
+            Some(min_line_height) => new_line.max(min_line_height),
+            None => new_line,
         }
     }
 
@@ -717,33 +2655,53 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
         }
     }
 
+    /// Records the break opportunity before the next glyph, if it's at least as high priority as
+    /// the current one. A line broken there ends after the previous glyph, unless that's
+    /// whitespace, in which case it ends where the whitespace started, as already marked.
+    fn mark_linebreak(&mut self, linebreak: LinebreakData) {
+        if linebreak >= self.linebreak_prev {
+            self.linebreak_prev = linebreak;
+            self.linebreak_pos = self.current_pos;
+            self.linebreak_idx = self.glyph_len.saturating_sub(1); // Mark the previous glyph
+            if self.prev_not_whitespace {
+                self.line_end_pos = self.current_pos;
+                self.line_end_idx = self.glyph_len.saturating_sub(1);
+            }
+        }
+    }
+
     fn perform_linebreak(&mut self, linebreak: &LinebreakData) {
         self.linebreak_prev = LINEBREAK_NONE;
-        let mut next_glyph_start = self.glyphs().len();
+        let mut next_glyph_start = self.glyph_len;
         if let Some(line) = self.line_metrics.last_mut() {
             line.glyph_end = self.line_end_idx;
-            line.padding = self.max_width - (self.line_end_pos - self.start_pos);
+            line.width = self.line_end_pos - self.start_pos;
+            line.padding = self.max_width - line.width;
             self.height += line.max_new_line_size * line.line_height.unwrap_or(1.0);
             next_glyph_start = self.linebreak_idx + 1;
-            if self.justify && !linebreak.is_hard() {
-                let n_spaces = self.glyphs[line.glyph_start..line.glyph_end]
+            if self.justify && !linebreak.is_hard() && !self.dry_run {
+                let total_weight: f32 = self.glyphs[line.glyph_start..line.glyph_end]
                     .iter()
-                    .filter(|g| g.char_data.is_whitespace())
-                    .count();
-                let extra_space = line.padding / n_spaces as f32;
+                    .map(|g| CharacterData::justification_weight(g.parent))
+                    .sum();
+                let extra_space = if total_weight > 0.0 {
+                    line.padding / total_weight
+                } else {
+                    0.0
+                };
                 let mut dx = 0.0;
                 for glyph in &mut self.glyphs[line.glyph_start..line.glyph_end] {
                     glyph.x = ceil(glyph.x + dx);
-                    if glyph.char_data.is_whitespace() {
-                        dx += extra_space;
-                    }
+                    dx += extra_space * CharacterData::justification_weight(glyph.parent);
                 }
+                line.width += line.padding;
                 line.padding = 0.0;
             }
         }
         self.line_metrics.push(LinePosition {
             baseline_y: 0.0,
             padding: 0.0,
+            width: 0.0,
             max_ascent: self.current_ascent,
             min_descent: self.current_descent,
             max_line_gap: self.current_line_gap,
@@ -751,7 +2709,10 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
             line_height: self.current_line_height,
             glyph_start: next_glyph_start,
             glyph_end: 0,
+            started_by_hard_break: linebreak.is_hard(),
             tracking_x: self.linebreak_pos,
+            align: self.horizontal_align,
+            space_before: 0.0,
         });
         self.start_pos = self.linebreak_pos;
     }
@@ -764,37 +2725,631 @@ impl<'f, U: Copy + Clone> Layout<'f, U> {
 
         unsafe { self.output.set_len(0) };
         self.output.reserve(self.glyphs.len());
+        let mut output = mem::take(&mut self.output);
 
-        let dir = if self.flip {
-            -1.0 // PositiveYDown
-        } else {
-            1.0 // PositiveYUp
-        };
+        self.decorations.clear();
+        let mut next_decoration = 0;
+        self.position_lines(&mut output, |layout, line, x_padding, _| {
+            layout.decorate_line(line, x_padding, &mut next_decoration);
+        });
+        self.output = output;
+        self.output_lines.clear();
+        self.output_lines.extend_from_slice(&self.line_metrics);
+    }
 
-        let mut baseline_y = self.y - dir * floor((self.max_height - self.height()) * self.vertical_align);
+    /// Positions the appended glyphs like `Layout::finalize`, but passes each line to the given
+    /// closure as it's positioned instead of keeping the glyphs. This avoids keeping a second,
+    /// positioned copy of every glyph for very long text, though the appended glyphs themselves are
+    /// still kept until the layout is cleared. The closure is given the line and its glyphs at
+    /// their final positions, which start after the glyphs given with the previous line, so they
+    /// may begin with whitespace dropped from the end of that line. Decorations aren't built, and
+    /// any previously finalized output is cleared.
+    pub fn finalize_streaming<F: FnMut(&LinePosition, &[GlyphPosition<'f, U>])>(&mut self, mut f: F) {
+        self.output.clear();
+        self.output_lines.clear();
+        self.decorations.clear();
+        if self.glyphs.is_empty() {
+            return;
+        }
+
+        let mut line_glyphs = Vec::new();
+        self.position_lines(&mut line_glyphs, |_, line, _, glyphs| {
+            f(line, glyphs);
+            glyphs.clear();
+        });
+    }
+
+    /// Positions each line's baseline and moves its glyphs into place, adding them to the given
+    /// Vec. After each line, the given closure is called with the layout, the line, the horizontal
+    /// offset its glyphs were moved by, and the Vec. The glyphs of a line start after the glyphs of
+    /// the previous line, so they may begin with whitespace dropped from the end of that line.
+    fn position_lines<F>(&mut self, glyphs: &mut Vec<GlyphPosition<'f, U>>, mut f: F)
+    where
+        F: FnMut(&mut Self, &LinePosition, f32, &mut Vec<GlyphPosition<'f, U>>),
+    {
+        let rounding = self.settings.rounding;
+        let (dir, mut baseline_y) = self.vertical_origin();
         let mut idx = 0;
-        for line in &mut self.line_metrics {
-            let x_padding = self.x - line.tracking_x + floor(line.padding * self.horizontal_align);
-            baseline_y -= dir * line.max_ascent;
+        for line_index in 0..self.line_metrics.len() {
+            let line = &mut self.line_metrics[line_index];
+            let x_padding = self.x - line.tracking_x + rounding.position(line.padding * line.align);
+            baseline_y = snap_baseline(
+                self.settings.baseline_grid,
+                self.y,
+                dir,
+                baseline_y - dir * (line.space_before + line.max_ascent),
+            );
             line.baseline_y = baseline_y;
+            let line = *line;
             while idx <= line.glyph_end {
                 let mut glyph = self.glyphs[idx];
                 glyph.x += x_padding;
                 glyph.y += baseline_y;
-                self.output.push(glyph);
+                glyphs.push(glyph);
                 idx += 1;
             }
+            f(self, &line, x_padding, glyphs);
             baseline_y -= dir * (line.max_new_line_size * line.line_height.unwrap_or(1.0) - line.max_ascent);
         }
     }
 
+    /// Builds the decorations of a positioned line, starting from the decorated glyph at the given
+    /// index, which is moved past the line. Decorations are built from the aligned glyphs so they
+    /// line up with them.
+    fn decorate_line(&mut self, line: &LinePosition, x_padding: f32, next: &mut usize) {
+        // Each kind keeps the rect it's extending and the index of the glyph that rect ends at.
+        let mut open: [Option<(usize, usize)>; 2] = [None, None];
+        while let Some(&decoration) = self.decorated.get(*next).filter(|d| d.index <= line.glyph_end) {
+            *next += 1;
+            if decoration.index < line.glyph_start {
+                continue; // Trailing whitespace that was dropped from a previous line.
+            }
+            let start = self.glyphs[decoration.index].x + x_padding + decoration.pen_offset;
+            let end = start + decoration.advance;
+            let kinds = [
+                (DecorationKind::Underline, decoration.underline),
+                (DecorationKind::Strikethrough, decoration.strikethrough),
+            ];
+            for (slot, (kind, snapped)) in kinds.iter().enumerate() {
+                let (position, thickness) = match snapped {
+                    Some(snapped) => *snapped,
+                    None => {
+                        open[slot] = None;
+                        continue;
+                    }
+                };
+                let y = if self.flip {
+                    line.baseline_y - position // PositiveYDown
+                } else {
+                    line.baseline_y + position - thickness // PositiveYUp
+                };
+                if let Some((rect_idx, glyph_idx)) = open[slot] {
+                    let rect = &mut self.decorations[rect_idx];
+                    if glyph_idx + 1 == decoration.index && rect.y == y && rect.height == thickness {
+                        rect.width = end - rect.x;
+                        open[slot] = Some((rect_idx, decoration.index));
+                        continue;
+                    }
+                }
+                open[slot] = Some((self.decorations.len(), decoration.index));
+                self.decorations.push(DecorationRect {
+                    kind: *kind,
+                    x: start,
+                    y,
+                    width: end - start,
+                    height: thickness,
+                });
+            }
+        }
+    }
+
+    /// Gets the direction the y coordinate moves in down the lines, and where the top of the first
+    /// line is after vertical alignment.
+    fn vertical_origin(&self) -> (f32, f32) {
+        let dir = if self.flip {
+            -1.0 // PositiveYDown
+        } else {
+            1.0 // PositiveYUp
+        };
+        let rounding = self.settings.rounding;
+        (dir, self.y - dir * rounding.position((self.max_height - self.height()) * self.vertical_align))
+    }
+
+    /// Appends the finalized glyphs, lines, and decorations of another layout to this layout's
+    /// finalized output, shifted vertically. This is useful for stacking separately laid out
+    /// blocks of text, like a header and a body, so they can be drawn together. Finalizing this
+    /// layout again replaces the appended output with its own. The appended lines are used by
+    /// `Layout::visual_order` and `Layout::glyphs_anchored`, but `Layout::lines` only has this
+    /// layout's own lines.
+    /// # Arguments
+    ///
+    /// * `other` - The finalized layout to append.
+    /// * `y_offset` - The offset in pixels added to the Y coordinate of everything appended.
+    pub fn append_layout(&mut self, other: &Layout<'f, U>, y_offset: f32) {
+        let glyph_offset = self.output.len();
+        self.output.extend(other.output.iter().map(|glyph| GlyphPosition {
+            y: glyph.y + y_offset,
+            ..*glyph
+        }));
+        self.output_lines.extend(other.output_lines.iter().map(|line| LinePosition {
+            baseline_y: line.baseline_y + y_offset,
+            glyph_start: line.glyph_start + glyph_offset,
+            glyph_end: line.glyph_end + glyph_offset,
+            ..*line
+        }));
+        self.decorations.extend(other.decorations.iter().map(|decoration| DecorationRect {
+            y: decoration.y + y_offset,
+            ..*decoration
+        }));
+    }
+
     /// Gets the currently laid out glyphs.
     pub fn glyphs(&self) -> &Vec<GlyphPosition<U>> {
         &self.output
     }
 
+    /// Gets the currently laid out glyphs in the given range of indices, clamped to the glyphs
+    /// that exist. Glyphs are in logical order, the order they were appended in, which may differ
+    /// from their visual order on each line.
+    pub fn glyphs_range(&self, range: Range<usize>) -> &[GlyphPosition<'f, U>] {
+        let end = range.end.min(self.output.len());
+        &self.output[range.start.min(end)..end]
+    }
+
+    /// Gets the currently laid out glyphs up to and including the given number of drawn glyphs
+    /// and blocks, for revealing text glyph by glyph. Glyphs without a bitmap, like whitespace,
+    /// aren't counted, so each increase of the count reveals something. Glyphs are revealed in
+    /// logical order, the order they were appended in.
+    pub fn reveal(&self, count: usize) -> &[GlyphPosition<'f, U>] {
+        if count == 0 {
+            return &[];
+        }
+        let mut drawn = 0;
+        for (index, glyph) in self.output.iter().enumerate() {
+            if glyph.key.is_none() || (glyph.width > 0 && glyph.height > 0) {
+                drawn += 1;
+                if drawn == count {
+                    return &self.output[..=index];
+                }
+            }
+        }
+        &self.output
+    }
+
+    /// Sets the opacity of the currently laid out glyph at the given index, clamped to 0.0..=1.0.
+    /// Glyphs are laid out opaque again when the layout is finalized. Does nothing if the glyph
+    /// doesn't exist.
+    pub fn set_glyph_alpha(&mut self, index: usize, alpha: f32) {
+        if let Some(glyph) = self.output.get_mut(index) {
+            glyph.alpha = alpha.max(0.0).min(1.0);
+        }
+    }
+
+    /// Fades the currently laid out glyphs in the given range of indices, clamped to the glyphs
+    /// that exist, by interpolating their opacity from the first glyph to the last. Opacities are
+    /// clamped to 0.0..=1.0. Glyphs are laid out opaque again when the layout is finalized.
+    /// # Arguments
+    ///
+    /// * `range` - The range of glyphs to fade, indexing into `Layout::glyphs`.
+    /// * `from` - The opacity of the first glyph in the range.
+    /// * `to` - The opacity of the last glyph in the range.
+    pub fn fade_range(&mut self, range: Range<usize>, from: f32, to: f32) {
+        let end = range.end.min(self.output.len());
+        let start = range.start.min(end);
+        let steps = (end - start).saturating_sub(1).max(1) as f32;
+        for (i, glyph) in self.output[start..end].iter_mut().enumerate() {
+            let alpha = from + (to - from) * (i as f32 / steps);
+            glyph.alpha = alpha.max(0.0).min(1.0);
+        }
+    }
+
+    /// Gets the currently laid out glyphs and blocks as the things a renderer needs to draw.
+    /// Glyphs without a bitmap, like whitespace, are skipped.
+    pub fn renderable(&self) -> impl Iterator<Item = Renderable<'f, U>> + '_ {
+        self.output.iter().filter_map(|glyph| match glyph.key {
+            Some(config) if glyph.width > 0 && glyph.height > 0 => Some(Renderable::Glyph {
+                config,
+                font: glyph.font,
+                x: glyph.x,
+                y: glyph.y,
+                width: glyph.width,
+                height: glyph.height,
+                color: glyph.color,
+                alpha: glyph.alpha,
+                user_data: glyph.user_data,
+            }),
+            Some(_) => None,
+            None => Some(Renderable::Block {
+                x: glyph.x,
+                y: glyph.y,
+                width: glyph.width,
+                height: glyph.height,
+                alpha: glyph.alpha,
+                user_data: glyph.user_data,
+            }),
+        })
+    }
+
+    /// Gets the currently laid out glyphs as quads for uploading straight to the GPU. Each glyph
+    /// is two triangles of interleaved [x, y, u, v] vertices, so 24 floats, in the order
+    /// top left, top right, bottom left, bottom left, top right, bottom right. Positions are in
+    /// the layout's coordinate system and texture coordinates come from the atlas. Glyphs without
+    /// a bitmap or missing from the atlas are skipped, as are blocks, which `Layout::renderable`
+    /// lists.
+    pub fn to_vertices<A: AtlasLookup + ?Sized>(&self, atlas: &A) -> Vec<f32> {
+        let mut vertices = Vec::new();
+        for glyph in &self.output {
+            let config = match glyph.key {
+                Some(config) if glyph.width > 0 && glyph.height > 0 => config,
+                _ => continue,
+            };
+            let (u0, v0, u1, v1) = match atlas.uv_rect(&config) {
+                Some(rect) => rect,
+                None => continue,
+            };
+            let (x0, x1) = (glyph.x, glyph.x + glyph.width as f32);
+            // The first row of the bitmap is at the top of the glyph.
+            let (top, bottom) = if self.flip {
+                (glyph.y, glyph.y + glyph.height as f32) // PositiveYDown
+            } else {
+                (glyph.y + glyph.height as f32, glyph.y) // PositiveYUp
+            };
+            let top_left = [x0, top, u0, v0];
+            let top_right = [x1, top, u1, v0];
+            let bottom_left = [x0, bottom, u0, v1];
+            let bottom_right = [x1, bottom, u1, v1];
+            for corner in &[top_left, top_right, bottom_left, bottom_left, top_right, bottom_right] {
+                vertices.extend_from_slice(corner);
+            }
+        }
+        vertices
+    }
+
+    /// Gets the currently laid out glyphs, with each glyph's x and y moved to the given anchor
+    /// point instead of the corner of its bitmap. Positions stay in the layout's coordinate system.
+    pub fn glyphs_anchored(&self, anchor: Anchor) -> Vec<GlyphPosition<'f, U>> {
+        let mut lines = self.output_lines.iter().peekable();
+        let mut anchored = Vec::with_capacity(self.output.len());
+        for (index, glyph) in self.output.iter().enumerate() {
+            let (width, height) = (glyph.width as f32, glyph.height as f32);
+            let (x, y) = match anchor {
+                Anchor::TopLeft if self.flip => (glyph.x, glyph.y),
+                Anchor::TopLeft => (glyph.x, glyph.y + height),
+                Anchor::Baseline => {
+                    // Glyphs dropped from the end of a line are positioned with the next line.
+                    while lines.peek().map_or(false, |line| line.glyph_end < index) {
+                        lines.next();
+                    }
+                    (glyph.x, lines.peek().map_or(glyph.y, |line| line.baseline_y))
+                }
+                Anchor::Center => (glyph.x + width / 2.0, glyph.y + height / 2.0),
+            };
+            anchored.push(GlyphPosition {
+                x,
+                y,
+                ..*glyph
+            });
+        }
+        anchored
+    }
+
+    /// Gets the indices of the glyphs on the given line in visual order, from left to right on
+    /// screen. Glyphs are otherwise in logical order, the order they were appended in, which is
+    /// what copying text wants. The two are the same for left to right text. If the line doesn't
+    /// exist, this is empty.
+    pub fn visual_order(&self, line: usize) -> Vec<usize> {
+        let line = match self.output_lines.get(line) {
+            Some(line) => line,
+            None => return Vec::new(),
+        };
+        let end = (line.glyph_end + 1).min(self.output.len());
+        let mut order: Vec<usize> = (line.glyph_start..end).collect();
+        order.sort_by(|a, b| self.output[*a].x.partial_cmp(&self.output[*b].x).unwrap_or(Ordering::Equal));
+        order
+    }
+
+    /// Gets the distinct raster configs used by the currently laid out glyphs, in the order they
+    /// first appear. Blocks don't have a raster config and are skipped. This is the set of glyphs
+    /// that need to be rasterized to draw the layout.
+    pub fn unique_configs(&self) -> Vec<GlyphRasterConfig> {
+        let mut seen = HashSet::new();
+        self.output.iter().filter_map(|glyph| glyph.key).filter(|key| seen.insert(*key)).collect()
+    }
+
+    /// Hashes the currently laid out glyphs and decorations, along with the region they're laid
+    /// out in, for detecting when a relayout produced identical output. Glyphs are hashed by their
+    /// raster config, position, size, and color, but not their user data. The hash is stable
+    /// across runs, though it may differ between platforms with different pointer widths.
+    pub fn content_hash(&self) -> u64 {
+        let mut state = FxHasher::default();
+        for value in [self.settings.x, self.settings.y, self.max_width, self.max_height] {
+            value.to_bits().hash(&mut state);
+        }
+        for glyph in &self.output {
+            glyph.key.hash(&mut state);
+            glyph.x.to_bits().hash(&mut state);
+            glyph.y.to_bits().hash(&mut state);
+            glyph.width.hash(&mut state);
+            glyph.height.hash(&mut state);
+            glyph.color.hash(&mut state);
+            glyph.alpha.to_bits().hash(&mut state);
+        }
+        for decoration in &self.decorations {
+            (decoration.kind as u8).hash(&mut state);
+            for value in [decoration.x, decoration.y, decoration.width, decoration.height] {
+                value.to_bits().hash(&mut state);
+            }
+        }
+        state.finish()
+    }
+
+    /// Rasterizes a run of the currently laid out glyphs into a single bitmap, with every glyph
+    /// composited at its position relative to the others. Where glyphs overlap the greater
+    /// coverage is kept. Blocks and glyphs without a bitmap are skipped. This trades memory for
+    /// fewer draw calls, and is intended for short text that rarely changes.
+    /// # Arguments
+    ///
+    /// * `glyph_range` - The range of glyphs to rasterize, indexing into `Layout::glyphs`, clamped
+    /// to the glyphs that exist.
+    /// # Returns
+    ///
+    /// * `Metrics` - Sizing and positioning metadata for the run's bitmap. `xmin` and `ymin`
+    /// place the bitmap in the layout the same way `GlyphPosition::x` and `GlyphPosition::y` place
+    /// a glyph's bitmap, and `advance_width` is the sum of the glyphs' advances.
+    /// * `Vec<u8>` - Coverage vector for the run. Coverage is a linear scale where 0 represents
+    /// 0% coverage of that pixel by the run and 255 represents 100% coverage. The vector starts at
+    /// the top left corner of the run.
+    pub fn rasterize_run(&self, glyph_range: Range<usize>) -> (Metrics, Vec<u8>) {
+        let glyphs = self.glyphs_range(glyph_range);
+        // Bitmap edges of a glyph in whole pixels, with the Y axis pointing down.
+        let flip = self.flip;
+        let edges = |glyph: &GlyphPosition<U>| -> (i32, i32) {
+            let x = as_i32(floor(glyph.x));
+            let y = as_i32(floor(glyph.y));
+            let top = if flip {
+                y
+            } else {
+                -(y + glyph.height as i32)
+            };
+            (x, top)
+        };
+        let drawn =
+            || glyphs.iter().filter(|glyph| glyph.key.is_some() && glyph.width > 0 && glyph.height > 0);
+
+        let (mut left, mut top, mut right, mut bottom) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for glyph in drawn() {
+            let (x, y) = edges(glyph);
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x + glyph.width as i32);
+            bottom = bottom.max(y + glyph.height as i32);
+        }
+        let advance_width = glyphs.iter().map(|glyph| glyph.advance).sum();
+        if left > right {
+            let metrics = Metrics {
+                advance_width,
+                ..Metrics::default()
+            };
+            return (metrics, Vec::new());
+        }
+
+        let width = (right - left) as usize;
+        let height = (bottom - top) as usize;
+        let mut bitmap = vec![0u8; width * height];
+        for glyph in drawn() {
+            let (x, y) = edges(glyph);
+            let (metrics, coverage) = glyph.font.rasterize_config(glyph.key.unwrap());
+            let offset_x = (x - left) as usize;
+            let offset_y = (y - top) as usize;
+            for (row, source) in coverage.chunks(metrics.width).take(glyph.height).enumerate() {
+                let start = (offset_y + row) * width + offset_x;
+                let target = &mut bitmap[start..start + glyph.width.min(source.len())];
+                for (dst, &src) in target.iter_mut().zip(source) {
+                    *dst = (*dst).max(src);
+                }
+            }
+        }
+
+        let ymin = if flip {
+            top
+        } else {
+            -bottom
+        };
+        let metrics = Metrics {
+            xmin: left,
+            ymin,
+            width,
+            height,
+            advance_width,
+            advance_height: 0.0,
+            bounds: OutlineBounds {
+                xmin: left as f32,
+                ymin: ymin as f32,
+                width: width as f32,
+                height: height as f32,
+            },
+        };
+        (metrics, bitmap)
+    }
+
+    /// Gets the decoration lines for the currently laid out glyphs. These are positioned when the
+    /// layout is finalized.
+    pub fn decorations(&self) -> &[DecorationRect] {
+        &self.decorations
+    }
+
     /// Gets the settings currently being used for layout.
     pub fn settings(&self) -> &LayoutSettings {
         &self.settings
     }
 }
+
+/// Finds the first ligature the font forms from the glyph and the text after it, returning the
+/// ligature glyph and the byte offset after its last character.
+fn match_ligature(font: &Font, first: u16, text: &str, byte_offset: usize) -> Option<(u16, usize)> {
+    font.ligatures(first).iter().find_map(|(components, ligature)| {
+        let mut offset = byte_offset;
+        let matches = components.iter().all(|&component| {
+            offset < text.len()
+                && font.lookup_glyph_index(read_utf8(text.as_bytes(), &mut offset)) == component
+        });
+        if matches {
+            Some((*ligature, offset))
+        } else {
+            None
+        }
+    })
+}
+
+/// True if more text after the given text could change the ligature `match_ligature` finds for the
+/// given first glyph, because one of the ligatures starting with it matches up to the end of the
+/// text.
+fn ligature_may_extend(font: &Font, first: u16, text: &str, byte_offset: usize) -> bool {
+    font.ligatures(first).iter().any(|(components, _)| {
+        let mut offset = byte_offset;
+        for &component in components {
+            if offset >= text.len() {
+                return true;
+            }
+            if font.lookup_glyph_index(read_utf8(text.as_bytes(), &mut offset)) != component {
+                return false;
+            }
+        }
+        false
+    })
+}
+
+/// The scale of synthetic fraction digits relative to the span's size, for fonts without `numr`
+/// and `dnom`.
+const SYNTHETIC_FRACTION_SCALE: f32 = 0.6;
+/// How far synthetic numerators are raised, as a fraction of the span's size.
+const SYNTHETIC_NUMERATOR_RISE: f32 = 0.4;
+
+/// The role of a character in a fraction.
+#[derive(Copy, Clone, PartialEq)]
+enum FractionPart {
+    Numerator,
+    Slash,
+    Denominator,
+}
+
+/// True if more text after the given text could change the fraction `find_fraction` finds at its
+/// start, because the digits and slash it reads run to the end of the text.
+fn fraction_may_extend(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let numerator = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    match bytes.get(numerator) {
+        _ if numerator == 0 => false,
+        None => true,
+        Some(b'/') => {
+            let denominator = bytes[numerator + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+            numerator + 1 + denominator == bytes.len()
+        }
+        Some(_) => false,
+    }
+}
+
+/// Finds a fraction of ASCII digits, a slash, and more ASCII digits at the start of the text,
+/// returning the byte offsets of the slash and of the end of the fraction.
+fn find_fraction(text: &str) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let numerator = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    if numerator == 0 || bytes.get(numerator) != Some(&b'/') {
+        return None;
+    }
+    let denominator = bytes[numerator + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+    if denominator == 0 {
+        None
+    } else {
+        Some((numerator, numerator + 1 + denominator))
+    }
+}
+
+/// Picks the glyph, size, and rise to draw part of a fraction with. The slash is drawn as a
+/// fraction slash when the font has one. Fonts with `numr` and `dnom` substitute their own digit
+/// forms, while others draw smaller digits with the numerator raised.
+fn fraction_glyph(font: &Font, part: FractionPart, glyph_index: u16, px: f32) -> (u16, f32, f32) {
+    if part == FractionPart::Slash {
+        let slash_index = font.lookup_glyph_index('\u{2044}');
+        let glyph_index = if slash_index != 0 {
+            slash_index
+        } else {
+            glyph_index
+        };
+        return (glyph_index, px, 0.0);
+    }
+    let numerator = part == FractionPart::Numerator;
+    if font.has_fractions() {
+        return (font.fraction_index(glyph_index, numerator).unwrap_or(glyph_index), px, 0.0);
+    }
+    let rise = if numerator {
+        floor(px * SYNTHETIC_NUMERATOR_RISE)
+    } else {
+        0.0
+    };
+    (glyph_index, px * SYNTHETIC_FRACTION_SCALE, rise)
+}
+
+/// The scale of synthetic small capitals relative to the span's size, for fonts without `smcp`.
+const SYNTHETIC_SMALL_CAPS_SCALE: f32 = 0.75;
+
+/// Picks the glyph and size to draw a character with in small capitals. Fonts with the `smcp`
+/// feature substitute their own small capitals, while others draw lowercase letters as their
+/// capital at a reduced size.
+fn small_cap_glyph(font: &Font, character: char, glyph_index: u16, px: f32) -> (u16, f32) {
+    if font.has_small_caps() {
+        return (font.small_cap_index(character).unwrap_or(glyph_index), px);
+    }
+    if character.is_lowercase() {
+        let mut upper = character.to_uppercase();
+        if let (Some(capital), None) = (upper.next(), upper.next()) {
+            let capital_index = font.lookup_glyph_index(capital);
+            if capital_index != 0 {
+                return (capital_index, px * SYNTHETIC_SMALL_CAPS_SCALE);
+            }
+        }
+    }
+    (glyph_index, px)
+}
+
+/// Measures the advance of the given text, without kerning pairs or ligatures.
+fn measure_text(font: &Font, text: &str, px: f32, kerning: f32, rounding: RoundMode) -> f32 {
+    text.chars().map(|c| rounding.advance(font.metrics(c, px).advance_width + kerning)).sum()
+}
+
+/// Moves a baseline down to the next line of a baseline grid starting at the given y, if there is
+/// a grid. Lines are only ever moved down, so they never overlap the line above.
+fn snap_baseline(grid: Option<f32>, y: f32, dir: f32, baseline_y: f32) -> f32 {
+    match grid {
+        Some(step) if step > 0.0 => {
+            let distance = dir * (y - baseline_y);
+            // Baselines already on the grid aren't pushed to the next line by float error.
+            let cells = ceil(distance / step - 0.001);
+            y - dir * cells * step
+        }
+        _ => baseline_y,
+    }
+}
+
+/// Snaps a decoration's position and thickness to whole pixels, with the thickness being at least
+/// 1 pixel. Fonts without the metrics fall back to a thickness of 1/16 of the size, and the given
+/// fraction of the size for the position.
+fn snap_decoration(metrics: Option<DecorationMetrics>, px: f32, fallback_position: f32) -> (f32, f32) {
+    let (position, thickness) = match metrics {
+        Some(metrics) => (metrics.position, metrics.thickness),
+        None => (px * fallback_position, px / 16.0),
+    };
+    (floor(position + 0.5), floor(thickness + 0.5).max(1.0))
+}
+
+/// Gets the symbol an invisible character is drawn as when showing invisibles, or none if the
+/// character is visible.
+fn invisible_symbol(character: char) -> Option<char> {
+    match character {
+        '\n' => Some('¶'),
+        '\t' => Some('→'),
+        ' ' => Some('·'),
+        '\0'..='\x1F' | '\x7F' => Some('¤'),
+        _ => None,
+    }
+}
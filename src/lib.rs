@@ -1,5 +1,8 @@
 //! Fontdue is a font parser, rasterizer, and layout tool.
 //!
+//! Both TrueType (`glyf`) and OpenType CFF (`CFF ` and `CFF2`) outlines are supported. Cubic
+//! curves from CFF charstrings are flattened the same way as quadratic curves.
+//!
 //! This is a no_std crate, but still requires the alloc crate.
 
 #![no_std]
@@ -21,6 +24,8 @@ mod table;
 mod unicode;
 
 pub use crate::font::*;
+pub use crate::math::{OutlineSink, Point};
+pub use crate::raster::Rasterizer;
 
 /// Alias for Result<T, &'static str>.
 pub type FontResult<T> = Result<T, &'static str>;
@@ -2,6 +2,7 @@ use crate::platform::{self, abs, atan2, f32x4, sqrt};
 use crate::{Glyph, OutlineBounds};
 use alloc::vec;
 use alloc::vec::*;
+use core::mem;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 struct AABB {
@@ -180,6 +181,7 @@ impl QuadCurve {
     }
 }
 
+/// A point in the 2D space outlines are drawn in.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Point {
     /// Absolute X coordinate.
@@ -212,18 +214,41 @@ impl Point {
         }
     }
 
+    /// The squared distance to the other point, which avoids a square root when only comparing
+    /// distances.
     pub fn distance_squared(&self, other: Point) -> f32 {
         let x = self.x - other.x;
         let y = self.y - other.y;
         x * x + y * y
     }
 
+    /// The distance to the other point.
     pub fn distance(&self, other: Point) -> f32 {
         let x = self.x - other.x;
         let y = self.y - other.y;
         sqrt(x * x + y * y)
     }
 
+    /// The squared distance to the nearest point on the segment from start to end.
+    pub fn distance_squared_to_segment(&self, start: Point, end: Point) -> f32 {
+        let dx = end.x - start.x;
+        let dy = end.y - start.y;
+        let length_squared = dx * dx + dy * dy;
+        let t = if length_squared > 0.0 {
+            (((self.x - start.x) * dx + (self.y - start.y) * dy) / length_squared).max(0.0).min(1.0)
+        } else {
+            0.0
+        };
+        self.distance_squared(Point::new(start.x + t * dx, start.y + t * dy))
+    }
+
+    /// The distance to the nearest point on the segment from start to end. This is the
+    /// perpendicular distance if the point is beside the segment, otherwise it's the distance to
+    /// the closest end.
+    pub fn distance_to_segment(&self, start: Point, end: Point) -> f32 {
+        sqrt(self.distance_squared_to_segment(start, end))
+    }
+
     pub fn midpoint(&self, other: Point) -> Point {
         Point {
             x: (self.x + other.x) / 2.0,
@@ -290,6 +315,12 @@ impl Line {
         }
     }
 
+    /// The start and end points of the line.
+    pub fn points(&self) -> (Point, Point) {
+        let (x0, y0, x1, y1) = self.coords.copied();
+        (Point::new(x0, y0), Point::new(x1, y1))
+    }
+
     fn reposition(&mut self, bounds: AABB, reverse: bool) {
         let (mut x0, mut y0, mut x1, mut y1) = if !reverse {
             self.coords.copied()
@@ -320,6 +351,7 @@ pub struct Geometry {
     area: f32,
     reverse_points: bool,
     max_area: f32,
+    contours: u16,
 }
 
 struct Segment {
@@ -342,6 +374,7 @@ impl Segment {
 
 impl ttf_parser::OutlineBuilder for Geometry {
     fn move_to(&mut self, x0: f32, y0: f32) {
+        self.contours = self.contours.saturating_add(1);
         let next_point = Point::new(x0, y0);
         self.start_point = next_point;
         self.previous_point = next_point;
@@ -425,6 +458,7 @@ impl Geometry {
             area: 0.0,
             reverse_points: false,
             max_area,
+            contours: 0,
         }
     }
 
@@ -455,6 +489,7 @@ impl Geometry {
         }
         glyph.v_lines = self.v_lines;
         glyph.m_lines = self.m_lines;
+        glyph.contours = self.contours;
         glyph.bounds = OutlineBounds {
             xmin: self.effective_bounds.xmin,
             ymin: self.effective_bounds.ymin,
@@ -478,3 +513,144 @@ impl Geometry {
         }
     }
 }
+
+/// Receives the drawing commands of a glyph's outline, as the font describes it before the curves
+/// are flattened into lines. Points are in font units from the glyph's origin with y increasing
+/// upwards.
+pub trait OutlineSink {
+    /// Starts a new contour at the given point.
+    fn move_to(&mut self, to: Point);
+    /// Draws a line from the current point to the given point.
+    fn line_to(&mut self, to: Point);
+    /// Draws a quadratic curve from the current point to the given point, bent towards the
+    /// control point.
+    fn quad_to(&mut self, control: Point, to: Point);
+    /// Draws a cubic curve from the current point to the given point, bent towards the two
+    /// control points.
+    fn curve_to(&mut self, control1: Point, control2: Point, to: Point);
+    /// Closes the current contour by connecting it back to its first point.
+    fn close(&mut self);
+}
+
+impl OutlineCommand {
+    /// Sends the command to the sink.
+    pub fn visit(&self, sink: &mut impl OutlineSink) {
+        match *self {
+            OutlineCommand::MoveTo(to) => sink.move_to(to),
+            OutlineCommand::LineTo(to) => sink.line_to(to),
+            OutlineCommand::QuadTo(control, to) => sink.quad_to(control, to),
+            OutlineCommand::CurveTo(control1, control2, to) => sink.curve_to(control1, control2, to),
+            OutlineCommand::Close => sink.close(),
+        }
+    }
+}
+
+/// A drawing command of a glyph's outline, in font units.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum OutlineCommand {
+    MoveTo(Point),
+    LineTo(Point),
+    /// A control point and the end point.
+    QuadTo(Point, Point),
+    /// Two control points and the end point.
+    CurveTo(Point, Point, Point),
+    Close,
+}
+
+/// Records the commands of an outline as they're read from the font, before they're flattened.
+#[derive(Default)]
+pub(crate) struct OutlineRecorder {
+    pub commands: Vec<OutlineCommand>,
+}
+
+impl ttf_parser::OutlineBuilder for OutlineRecorder {
+    fn move_to(&mut self, x0: f32, y0: f32) {
+        self.commands.push(OutlineCommand::MoveTo(Point::new(x0, y0)));
+    }
+
+    fn line_to(&mut self, x0: f32, y0: f32) {
+        self.commands.push(OutlineCommand::LineTo(Point::new(x0, y0)));
+    }
+
+    fn quad_to(&mut self, x0: f32, y0: f32, x1: f32, y1: f32) {
+        self.commands.push(OutlineCommand::QuadTo(Point::new(x0, y0), Point::new(x1, y1)));
+    }
+
+    fn curve_to(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.commands.push(OutlineCommand::CurveTo(
+            Point::new(x0, y0),
+            Point::new(x1, y1),
+            Point::new(x2, y2),
+        ));
+    }
+
+    fn close(&mut self) {
+        self.commands.push(OutlineCommand::Close);
+    }
+}
+
+/// Flattens recorded outline commands into a ring of points per contour, scaled by the given
+/// factor. Curves are split until the middle of each piece is within the tolerance of the line
+/// replacing it, in scaled units. Each ring ends before returning to its first point.
+pub(crate) fn flatten_contours(commands: &[OutlineCommand], scale: f32, tolerance: f32) -> Vec<Vec<Point>> {
+    // Splitting stops at this depth, so a tiny or invalid tolerance still terminates.
+    const MAX_DEPTH: u32 = 16;
+    let tolerance = if tolerance > 0.0 {
+        tolerance
+    } else {
+        0.0
+    };
+    let mut rings: Vec<Vec<Point>> = Vec::new();
+    let mut ring: Vec<Point> = Vec::new();
+    let mut previous = Point::default();
+    let flatten = |ring: &mut Vec<Point>, start: Point, end: Point, point: &dyn Fn(f32) -> Point| {
+        let mut stack = vec![(start, 0.0, end, 1.0, 0)];
+        while let Some((a, at, c, ct, depth)) = stack.pop() {
+            let bt = (at + ct) * 0.5;
+            let b = point(bt);
+            if depth < MAX_DEPTH && b.distance_to_segment(a, c) > tolerance {
+                // The second half is pushed first so the first half is emitted first.
+                stack.push((b, bt, c, ct, depth + 1));
+                stack.push((a, at, b, bt, depth + 1));
+            } else {
+                ring.push(c);
+            }
+        }
+    };
+    let mut finish = |ring: &mut Vec<Point>| {
+        if ring.len() > 1 && ring.first() == ring.last() {
+            ring.pop();
+        }
+        if !ring.is_empty() {
+            rings.push(mem::take(ring));
+        }
+    };
+    for command in commands {
+        match *command {
+            OutlineCommand::MoveTo(p) => {
+                finish(&mut ring);
+                previous = p.scale(scale);
+                ring.push(previous);
+            }
+            OutlineCommand::LineTo(p) => {
+                previous = p.scale(scale);
+                ring.push(previous);
+            }
+            OutlineCommand::QuadTo(b, c) => {
+                let curve = QuadCurve::new(previous, b.scale(scale), c.scale(scale));
+                let end = curve.c;
+                flatten(&mut ring, previous, end, &|t| curve.point(t));
+                previous = end;
+            }
+            OutlineCommand::CurveTo(b, c, d) => {
+                let curve = CubeCurve::new(previous, b.scale(scale), c.scale(scale), d.scale(scale));
+                let end = curve.d;
+                flatten(&mut ring, previous, end, &|t| curve.point(t));
+                previous = end;
+            }
+            OutlineCommand::Close => finish(&mut ring),
+        }
+    }
+    finish(&mut ring);
+    rings
+}
@@ -4,9 +4,10 @@
  * is safe. Please be aware of this.
  */
 
-use crate::math::Line;
-use crate::platform::{abs, as_i32, copysign, f32x4, fract};
-use crate::Glyph;
+use crate::font::{darken_stems, glyph_edges};
+use crate::math::{Line, Point};
+use crate::platform::{abs, as_i32, clamp, copysign, f32x4, fract, sqrt};
+use crate::{Glyph, Metrics};
 use alloc::vec;
 use alloc::vec::*;
 
@@ -26,6 +27,10 @@ impl Raster {
     }
 
     pub(crate) fn draw(&mut self, glyph: &Glyph, scale_x: f32, scale_y: f32, offset_x: f32, offset_y: f32) {
+        // Glyphs too large to rasterize get an empty canvas, which they can't be drawn into.
+        if self.w == 0 || self.h == 0 {
+            return;
+        }
         let params = f32x4::new(1.0 / scale_x, 1.0 / scale_y, scale_x, scale_y);
         let scale = f32x4::new(scale_x, scale_y, scale_x, scale_y);
         let offset = f32x4::new(offset_x, offset_y, offset_x, offset_y);
@@ -122,4 +127,127 @@ impl Raster {
     pub fn get_bitmap(&self) -> Vec<u8> {
         crate::platform::get_bitmap(&self.a, self.w * self.h)
     }
+
+    /// Accumulates the coverage of each pixel into a region of a larger bitmap, which starts at the
+    /// top left corner of the region and has the given row stride. The region must fit.
+    pub fn write_bitmap(&self, output: &mut [u8], stride: usize) {
+        let mut height = 0.0;
+        for (row, areas) in self.a[..self.w * self.h].chunks_exact(self.w.max(1)).enumerate() {
+            let start = row * stride;
+            for (pixel, area) in output[start..start + self.w].iter_mut().zip(areas) {
+                height += area;
+                // Clamping because as u8 is undefined outside of its range in rustc.
+                *pixel = clamp(abs(height) * 255.9, 0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    /// Accumulates the coverage of each pixel without quantizing it, clamped to 0.0..=1.0.
+    pub fn get_coverage(&self) -> Vec<f32> {
+        let mut height = 0.0;
+        self.a[..self.w * self.h]
+            .iter()
+            .map(|area| {
+                height += area;
+                abs(height).min(1.0)
+            })
+            .collect()
+    }
+}
+
+/// A glyph's outline scan converted at a given size, for building custom effects on top of
+/// fontdue's coverage. Where `Font::rasterize` only returns the final coverage, this also exposes
+/// the signed area the coverage is accumulated from, the winding of each pixel, and the outline's
+/// edges. Created with `Font::rasterizer` and `Font::rasterizer_indexed`, as the scan converter
+/// relies on the outline sanitization fontdue does when loading a font.
+#[derive(Clone)]
+pub struct Rasterizer {
+    metrics: Metrics,
+    accumulation: Vec<f32>,
+    edges: Vec<(f32, f32, f32, f32)>,
+    /// The strength of the stem darkening applied to the coverage.
+    stem_darkening: f32,
+}
+
+impl Rasterizer {
+    pub(crate) fn new(
+        glyph: &Glyph,
+        metrics: Metrics,
+        scale: f32,
+        offset_x: f32,
+        offset_y: f32,
+        stem_darkening: f32,
+    ) -> Rasterizer {
+        let mut canvas = Raster::new(metrics.width, metrics.height);
+        canvas.draw(glyph, scale, scale, offset_x, offset_y);
+        let edges = glyph_edges(glyph, scale, offset_x, offset_y)
+            .into_iter()
+            .map(|(start, end)| (start.x, start.y, end.x, end.y))
+            .collect();
+        Rasterizer {
+            metrics,
+            accumulation: canvas.a,
+            edges,
+            stem_darkening,
+        }
+    }
+
+    /// Sizing and positioning metadata for the rasterized glyph.
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+
+    /// The signed area each pixel accumulated from the outline's edges, starting at the top left
+    /// corner of the glyph. A running sum over these values gives `Rasterizer::winding`.
+    pub fn accumulation(&self) -> &[f32] {
+        &self.accumulation[..self.metrics.width * self.metrics.height]
+    }
+
+    /// The signed coverage of each pixel, starting at the top left corner of the glyph. The
+    /// magnitude is the coverage, where 1.0 is fully covered, and can exceed 1.0 where contours
+    /// overlap. The sign follows the winding direction of the contours covering the pixel.
+    pub fn winding(&self) -> Vec<f32> {
+        let mut height = 0.0;
+        self.accumulation()
+            .iter()
+            .map(|area| {
+                height += area;
+                height
+            })
+            .collect()
+    }
+
+    /// The coverage of each pixel, identical to the bitmap returned by `Font::rasterize`. Like it,
+    /// this applies `FontSettings::stem_darkening`, which the accumulation and winding don't.
+    pub fn coverage(&self) -> Vec<u8> {
+        let mut bitmap =
+            crate::platform::get_bitmap(&self.accumulation, self.metrics.width * self.metrics.height);
+        darken_stems(&mut bitmap, self.stem_darkening);
+        bitmap
+    }
+
+    /// The outline's edges as line segments `(x0, y0, x1, y1)`, in pixels relative to the top left
+    /// corner of the glyph with the Y axis pointing down. Curves are already flattened. Horizontal
+    /// edges are only included if the font was loaded with `FontSettings::load_outlines`.
+    pub fn edges(&self) -> &[(f32, f32, f32, f32)] {
+        &self.edges
+    }
+
+    /// The distance in pixels from the center of each pixel to the nearest edge of the outline,
+    /// starting at the top left corner of the glyph. This is computed on each call.
+    pub fn edge_distances(&self) -> Vec<f32> {
+        let mut distances = Vec::with_capacity(self.metrics.width * self.metrics.height);
+        for y in 0..self.metrics.height {
+            for x in 0..self.metrics.width {
+                let center = Point::new(x as f32 + 0.5, y as f32 + 0.5);
+                let mut nearest = core::f32::MAX;
+                for &(x0, y0, x1, y1) in &self.edges {
+                    nearest = nearest
+                        .min(center.distance_squared_to_segment(Point::new(x0, y0), Point::new(x1, y1)));
+                }
+                distances.push(sqrt(nearest));
+            }
+        }
+        distances
+    }
 }
@@ -0,0 +1,29 @@
+use alloc::vec::*;
+use ttf_parser::Face;
+
+/// Lists the tags of the features in the `GSUB` and `GPOS` tables, sorted and without duplicates,
+/// along with if any language system declares the feature as its required feature.
+pub fn load_features(face: &Face) -> Vec<([u8; 4], bool)> {
+    let mut features: Vec<([u8; 4], bool)> = Vec::new();
+    for table in [face.tables().gsub, face.tables().gpos].iter().flatten() {
+        let mut required = Vec::new();
+        for script in table.scripts {
+            for language in script.default_language.into_iter().chain(script.languages) {
+                required.extend(language.required_feature);
+            }
+        }
+        for (index, feature) in table.features.into_iter().enumerate() {
+            features.push((feature.tag.to_bytes(), required.contains(&(index as u16))));
+        }
+    }
+    features.sort_by_key(|&(tag, _)| tag);
+    features.dedup_by(|(tag, required), (kept_tag, kept_required)| {
+        if tag == kept_tag {
+            *kept_required |= *required;
+            true
+        } else {
+            false
+        }
+    });
+    features
+}
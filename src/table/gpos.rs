@@ -0,0 +1,84 @@
+use super::gsub::for_each_covered;
+use alloc::vec::*;
+use hashbrown::HashMap;
+use ttf_parser::gpos::{AnchorMatrix, MarkArray, PositioningSubtable};
+use ttf_parser::opentype_layout::Coverage;
+use ttf_parser::{Face, Tag};
+
+/// The anchors of a single mark attachment subtable, in font units.
+#[derive(Clone, Debug, Default)]
+pub struct MarkAttachment {
+    /// Maps a mark glyph to its mark class and anchor.
+    pub marks: HashMap<u16, (u16, (f32, f32))>,
+    /// Maps a base glyph to its anchor for each mark class, indexed by the class.
+    pub bases: HashMap<u16, Vec<Option<(f32, f32)>>>,
+}
+
+impl MarkAttachment {
+    /// Returns the offset from the base glyph's origin to the mark glyph's origin that places the
+    /// mark's anchor on the base's anchor, or none if the pair isn't covered.
+    pub fn offset(&self, base: u16, mark: u16) -> Option<(f32, f32)> {
+        let &(class, (mark_x, mark_y)) = self.marks.get(&mark)?;
+        let (base_x, base_y) = (*self.bases.get(&base)?.get(class as usize)?)?;
+        Some((base_x - mark_x, base_y - mark_y))
+    }
+}
+
+/// Collects the mark-to-base and mark-to-ligature attachments of the `mark` feature. Marks
+/// attach to the last component of a ligature, as that is the component they follow.
+pub fn load_mark_attachments(face: &Face) -> Vec<MarkAttachment> {
+    let mut attachments = Vec::new();
+    if let Some(table) = face.tables().gpos {
+        for feature in table.features {
+            if feature.tag != Tag::from_bytes(b"mark") {
+                continue;
+            }
+            for lookup_index in feature.lookup_indices {
+                let lookup = match table.lookups.get(lookup_index) {
+                    Some(lookup) => lookup,
+                    None => continue,
+                };
+                for subtable in lookup.subtables.into_iter::<PositioningSubtable>() {
+                    let mut attachment = MarkAttachment::default();
+                    match subtable {
+                        PositioningSubtable::MarkToBase(adjustment) => {
+                            load_marks(&mut attachment, adjustment.mark_coverage, adjustment.marks);
+                            for_each_covered(adjustment.base_coverage, |glyph, row| {
+                                let anchors = load_anchor_row(adjustment.anchors, row);
+                                attachment.bases.insert(glyph, anchors);
+                            });
+                        }
+                        PositioningSubtable::MarkToLigature(adjustment) => {
+                            load_marks(&mut attachment, adjustment.mark_coverage, adjustment.marks);
+                            for_each_covered(adjustment.ligature_coverage, |glyph, index| {
+                                if let Some(components) = adjustment.ligature_array.get(index) {
+                                    if components.rows > 0 {
+                                        let anchors = load_anchor_row(components, components.rows - 1);
+                                        attachment.bases.insert(glyph, anchors);
+                                    }
+                                }
+                            });
+                        }
+                        _ => continue,
+                    }
+                    attachments.push(attachment);
+                }
+            }
+        }
+    }
+    attachments
+}
+
+fn load_marks(attachment: &mut MarkAttachment, coverage: Coverage, marks: MarkArray) {
+    for_each_covered(coverage, |glyph, index| {
+        if let Some((class, anchor)) = marks.get(index) {
+            attachment.marks.insert(glyph, (class, (anchor.x as f32, anchor.y as f32)));
+        }
+    });
+}
+
+fn load_anchor_row(matrix: AnchorMatrix, row: u16) -> Vec<Option<(f32, f32)>> {
+    (0..matrix.cols)
+        .map(|class| matrix.get(row, class).map(|anchor| (anchor.x as f32, anchor.y as f32)))
+        .collect()
+}
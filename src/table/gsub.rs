@@ -1,5 +1,7 @@
-use hashbrown::HashSet;
-use ttf_parser::Face;
+use alloc::vec::*;
+use hashbrown::{HashMap, HashSet};
+use ttf_parser::opentype_layout::Coverage;
+use ttf_parser::{Face, Tag};
 
 pub fn load_gsub(face: &Face, indices_to_load: &mut HashSet<u16>) {
     if let Some(subtable) = face.tables().gsub {
@@ -72,3 +74,101 @@ pub fn load_gsub(face: &Face, indices_to_load: &mut HashSet<u16>) {
         }
     }
 }
+
+/// Collects the single substitutions of every feature with the given tag into a map from the
+/// covered glyph to its substitute. Substitutions from every script and language are merged,
+/// with the first lookup to cover a glyph taking priority.
+pub fn load_single_substitutions(face: &Face, feature_tag: Tag) -> HashMap<u16, u16> {
+    let mut substitutions = HashMap::new();
+    if let Some(table) = face.tables().gsub {
+        use ttf_parser::gsub::{SingleSubstitution, SubstitutionSubtable};
+        for feature in table.features {
+            if feature.tag != feature_tag {
+                continue;
+            }
+            for lookup_index in feature.lookup_indices {
+                let lookup = match table.lookups.get(lookup_index) {
+                    Some(lookup) => lookup,
+                    None => continue,
+                };
+                for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+                    match subtable {
+                        SubstitutionSubtable::Single(SingleSubstitution::Format1 {
+                            coverage,
+                            delta,
+                        }) => for_each_covered(coverage, |glyph, _| {
+                            substitutions.entry(glyph).or_insert((glyph as i32 + delta as i32) as u16);
+                        }),
+                        SubstitutionSubtable::Single(SingleSubstitution::Format2 {
+                            coverage,
+                            substitutes,
+                        }) => for_each_covered(coverage, |glyph, coverage_index| {
+                            if let Some(substitute) = substitutes.get(coverage_index) {
+                                substitutions.entry(glyph).or_insert(substitute.0);
+                            }
+                        }),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    substitutions
+}
+
+/// Collects the ligature substitutions of every feature with the given tag into a map from the
+/// first glyph of a ligature to the remaining glyphs it's formed from and the ligature glyph. The
+/// candidates for a glyph are kept in the order the font prefers them.
+pub fn load_ligatures(face: &Face, feature_tag: Tag) -> HashMap<u16, Vec<(Vec<u16>, u16)>> {
+    let mut ligatures: HashMap<u16, Vec<(Vec<u16>, u16)>> = HashMap::new();
+    if let Some(table) = face.tables().gsub {
+        use ttf_parser::gsub::SubstitutionSubtable;
+        for feature in table.features {
+            if feature.tag != feature_tag {
+                continue;
+            }
+            for lookup_index in feature.lookup_indices {
+                let lookup = match table.lookups.get(lookup_index) {
+                    Some(lookup) => lookup,
+                    None => continue,
+                };
+                for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+                    if let SubstitutionSubtable::Ligature(substitution) = subtable {
+                        for_each_covered(substitution.coverage, |glyph, coverage_index| {
+                            if let Some(set) = substitution.ligature_sets.get(coverage_index) {
+                                let candidates = ligatures.entry(glyph).or_default();
+                                for ligature in set {
+                                    let components = ligature.components.into_iter().map(|g| g.0).collect();
+                                    candidates.push((components, ligature.glyph.0));
+                                }
+                            }
+                        });
+                    }
+                }
+            }
+        }
+    }
+    ligatures
+}
+
+/// Calls `f` with every glyph in the coverage table along with its coverage index.
+pub(super) fn for_each_covered<F: FnMut(u16, u16)>(coverage: Coverage, mut f: F) {
+    match coverage {
+        Coverage::Format1 {
+            glyphs,
+        } => {
+            for (coverage_index, glyph) in glyphs.into_iter().enumerate() {
+                f(glyph.0, coverage_index as u16);
+            }
+        }
+        Coverage::Format2 {
+            records,
+        } => {
+            for record in records {
+                for id in record.start.0..=record.end.0 {
+                    f(id, record.value + (id - record.start.0));
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,10 @@
+mod features;
+mod gpos;
 mod gsub;
 mod kern;
 pub mod parse;
 
-pub use self::gsub::load_gsub;
+pub use self::features::load_features;
+pub use self::gpos::{load_mark_attachments, MarkAttachment};
+pub use self::gsub::{load_gsub, load_ligatures, load_single_substitutions};
 pub use self::kern::*;
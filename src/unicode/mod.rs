@@ -32,29 +32,120 @@ pub fn read_utf16(bytes: &[u8], offset: &mut usize) -> char {
     }
 }
 
-/// Returns (length, character). Cannot be run at the end of the string.
+/// Decodes the character starting at the byte offset and advances the offset past it. Cannot be
+/// run at the end of the string. Malformed sequences (invalid or truncated sequences, overlong
+/// encodings, surrogates, and values past U+10FFFF) decode as U+FFFD and advance past a single
+/// byte, so arbitrary bytes can be decoded safely.
 pub fn read_utf8(bytes: &[u8], byte_offset: &mut usize) -> char {
     let x = bytes[*byte_offset];
     *byte_offset += 1;
     if x < 128 {
         return unsafe { core::char::from_u32_unchecked(x as u32) };
     }
-    let init = (x & (0x7F >> 2)) as u32;
-    let y = bytes[*byte_offset];
-    *byte_offset += 1;
-    let mut ch = utf8_acc_cont_byte(init, y);
-    if x >= 0xE0 {
-        let z = bytes[*byte_offset];
-        *byte_offset += 1;
-        let y_z = utf8_acc_cont_byte((y & CONT_MASK) as u32, z);
-        ch = init << 12 | y_z;
-        if x >= 0xF0 {
-            let w = bytes[*byte_offset];
-            *byte_offset += 1;
-            ch = (init & 7) << 18 | utf8_acc_cont_byte(y_z, w);
-        }
-    }
-    unsafe { core::char::from_u32_unchecked(ch) }
+    // The number of continuation bytes, and the smallest value that needs this many bytes.
+    let (len, min) = match x {
+        0xC2..=0xDF => (1, 0x80),
+        0xE0..=0xEF => (2, 0x800),
+        0xF0..=0xF4 => (3, 0x1_0000),
+        _ => return char::REPLACEMENT_CHARACTER,
+    };
+    let start = *byte_offset;
+    let mut ch = (x & (0x7F >> (len + 1))) as u32;
+    for i in 0..len {
+        match bytes.get(start + i) {
+            Some(&y) if y & !CONT_MASK == 0x80 => ch = utf8_acc_cont_byte(ch, y),
+            _ => return char::REPLACEMENT_CHARACTER,
+        }
+    }
+    if ch < min {
+        return char::REPLACEMENT_CHARACTER;
+    }
+    match core::char::from_u32(ch) {
+        Some(c) => {
+            *byte_offset = start + len;
+            c
+        }
+        None => char::REPLACEMENT_CHARACTER,
+    }
+}
+
+/// Checks if the character is never drawn, so a font missing it doesn't need a fallback. This
+/// covers control characters and the common default ignorable formatting characters, like zero
+/// width spaces and joiners, bidi controls, and variation selectors.
+pub fn is_default_ignorable(c: char) -> bool {
+    match c as u32 {
+        0x0..=0x1F | 0x7F..=0x9F => true,
+        0xAD | 0x34F | 0x61C | 0x180B..=0x180F => true,
+        0x200B..=0x200F | 0x202A..=0x202E | 0x2060..=0x206F => true,
+        0xFE00..=0xFE0F | 0xFEFF | 0xE0000..=0xE0FFF => true,
+        _ => false,
+    }
+}
+
+/// Pairs of characters that mirror each other in right to left text, sorted by the first
+/// character. This is the commonly used subset of the Bidi_Mirroring_Glyph property.
+static MIRRORED_PAIRS: [(char, char); 53] = [
+    ('(', ')'),
+    ('<', '>'),
+    ('[', ']'),
+    ('{', '}'),
+    ('\u{AB}', '\u{BB}'),
+    ('\u{2039}', '\u{203A}'),
+    ('\u{2045}', '\u{2046}'),
+    ('\u{207D}', '\u{207E}'),
+    ('\u{208D}', '\u{208E}'),
+    ('\u{2208}', '\u{220B}'),
+    ('\u{2209}', '\u{220C}'),
+    ('\u{220A}', '\u{220D}'),
+    ('\u{223C}', '\u{223D}'),
+    ('\u{2243}', '\u{22CD}'),
+    ('\u{2264}', '\u{2265}'),
+    ('\u{2266}', '\u{2267}'),
+    ('\u{226A}', '\u{226B}'),
+    ('\u{226E}', '\u{226F}'),
+    ('\u{2270}', '\u{2271}'),
+    ('\u{2272}', '\u{2273}'),
+    ('\u{2276}', '\u{2277}'),
+    ('\u{227A}', '\u{227B}'),
+    ('\u{2282}', '\u{2283}'),
+    ('\u{2286}', '\u{2287}'),
+    ('\u{228F}', '\u{2290}'),
+    ('\u{2291}', '\u{2292}'),
+    ('\u{22A2}', '\u{22A3}'),
+    ('\u{2308}', '\u{2309}'),
+    ('\u{230A}', '\u{230B}'),
+    ('\u{2329}', '\u{232A}'),
+    ('\u{2768}', '\u{2769}'),
+    ('\u{276A}', '\u{276B}'),
+    ('\u{276C}', '\u{276D}'),
+    ('\u{276E}', '\u{276F}'),
+    ('\u{2770}', '\u{2771}'),
+    ('\u{2772}', '\u{2773}'),
+    ('\u{2774}', '\u{2775}'),
+    ('\u{27E6}', '\u{27E7}'),
+    ('\u{27E8}', '\u{27E9}'),
+    ('\u{27EA}', '\u{27EB}'),
+    ('\u{2983}', '\u{2984}'),
+    ('\u{3008}', '\u{3009}'),
+    ('\u{300A}', '\u{300B}'),
+    ('\u{300C}', '\u{300D}'),
+    ('\u{300E}', '\u{300F}'),
+    ('\u{3010}', '\u{3011}'),
+    ('\u{3014}', '\u{3015}'),
+    ('\u{3016}', '\u{3017}'),
+    ('\u{3018}', '\u{3019}'),
+    ('\u{301A}', '\u{301B}'),
+    ('\u{FF08}', '\u{FF09}'),
+    ('\u{FF1C}', '\u{FF1E}'),
+    ('\u{FF3B}', '\u{FF3D}'),
+];
+
+/// Gets the character that mirrors the given character in right to left text, if it has one.
+pub fn mirror(c: char) -> Option<char> {
+    if let Ok(i) = MIRRORED_PAIRS.binary_search_by(|pair| pair.0.cmp(&c)) {
+        return Some(MIRRORED_PAIRS[i].1);
+    }
+    MIRRORED_PAIRS.iter().find(|pair| pair.1 == c).map(|pair| pair.0)
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -159,6 +250,7 @@ impl CharacterData {
     const WHITESPACE: u8 = 0b0000_0001;
     const CONTROL: u8 = 0b0000_0010;
     const MISSING: u8 = 0b0000_0100;
+    const SYMBOL: u8 = 0b0000_1000;
 
     /// Classifies a character given its index in the font.
     pub fn classify(c: char, index: u16) -> CharacterData {
@@ -179,10 +271,33 @@ impl CharacterData {
         }
     }
 
+    /// Gets how much a character stretches relative to a regular space when a line is justified.
+    /// Spaces stretch in proportion to their width, so the em quad and em space take twice a
+    /// regular space's share of the line's padding and the narrow spaces less. No-break spaces,
+    /// including the figure space that keeps columns of digits aligned, keep their width, as do
+    /// all other characters.
+    pub(crate) fn justification_weight(c: char) -> f32 {
+        match c {
+            '\t' | '\n' | '\x0C' | '\r' | ' ' | '\u{1680}' | '\u{2000}' | '\u{2002}' => 1.0,
+            '\u{2001}' | '\u{2003}' | '\u{3000}' => 2.0,
+            '\u{2004}' | '\u{2005}' | '\u{205F}' => 0.75,
+            '\u{2006}' | '\u{2008}' | '\u{2009}' | '\u{200A}' => 0.5,
+            _ => 0.0,
+        }
+    }
+
+    /// Marks an invisible character as being drawn with a visible symbol's glyph, which is
+    /// present in the font.
+    pub(crate) fn with_symbol(self) -> CharacterData {
+        CharacterData {
+            bits: (self.bits | CharacterData::SYMBOL) & !CharacterData::MISSING,
+        }
+    }
+
     /// A heuristic for if the glpyh this was classified from should be rasterized. Missing glyphs,
-    /// whitespace, and control characters will return false.
+    /// whitespace, and control characters will return false, unless they're drawn as a symbol.
     pub fn rasterize(&self) -> bool {
-        self.bits == 0
+        self.bits == 0 || self.is_symbol()
     }
 
     /// Marks if the character is an ASCII whitespace character.
@@ -199,4 +314,185 @@ impl CharacterData {
     pub fn is_missing(&self) -> bool {
         self.bits & CharacterData::MISSING != 0
     }
+
+    /// Marks if the whitespace or control character is drawn as a visible symbol.
+    pub fn is_symbol(&self) -> bool {
+        self.bits & CharacterData::SYMBOL != 0
+    }
+}
+
+/// A coarse classification of the writing system a character belongs to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Script {
+    /// Characters shared between scripts, such as whitespace, digits, punctuation, symbols, and
+    /// combining marks.
+    Common,
+    /// Latin, including its extended and fullwidth forms.
+    Latin,
+    /// Greek, including its extended forms.
+    Greek,
+    /// Cyrillic, including its extended forms.
+    Cyrillic,
+    /// Armenian.
+    Armenian,
+    /// Hebrew, including its presentation forms.
+    Hebrew,
+    /// Arabic, including its presentation forms.
+    Arabic,
+    /// Devanagari.
+    Devanagari,
+    /// Thai.
+    Thai,
+    /// Georgian.
+    Georgian,
+    /// Korean Hangul jamo and syllables.
+    Hangul,
+    /// Japanese Hiragana.
+    Hiragana,
+    /// Japanese Katakana, including its halfwidth forms.
+    Katakana,
+    /// CJK ideographs.
+    Han,
+    /// Characters from any script not listed here.
+    Unknown,
+}
+
+impl Script {
+    /// Classifies a character by the script it belongs to.
+    pub fn of(c: char) -> Script {
+        match c as u32 {
+            0x41..=0x5A | 0x61..=0x7A | 0xAA | 0xBA => Script::Latin,
+            0xD7 | 0xF7 => Script::Common,
+            0xC0..=0x24F | 0x250..=0x2AF | 0x1E00..=0x1EFF | 0x2C60..=0x2C7F | 0xA720..=0xA7FF => {
+                Script::Latin
+            }
+            0xFF21..=0xFF3A | 0xFF41..=0xFF5A => Script::Latin,
+            0x370..=0x3FF | 0x1F00..=0x1FFF => Script::Greek,
+            0x400..=0x52F | 0x1C80..=0x1C8F | 0x2DE0..=0x2DFF | 0xA640..=0xA69F => Script::Cyrillic,
+            0x530..=0x58F => Script::Armenian,
+            0x590..=0x5FF | 0xFB1D..=0xFB4F => Script::Hebrew,
+            0x600..=0x6FF | 0x750..=0x77F | 0x8A0..=0x8FF | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => {
+                Script::Arabic
+            }
+            0x900..=0x97F | 0xA8E0..=0xA8FF => Script::Devanagari,
+            0xE00..=0xE7F => Script::Thai,
+            0x10A0..=0x10FF | 0x2D00..=0x2D2F => Script::Georgian,
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xA960..=0xA97F | 0xAC00..=0xD7FF => Script::Hangul,
+            0xFFA0..=0xFFDC => Script::Hangul,
+            0x3040..=0x309F => Script::Hiragana,
+            0x30A0..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => Script::Katakana,
+            0x3005 | 0x3007 | 0x3021..=0x3029 | 0x3038..=0x303B => Script::Han,
+            0x2E80..=0x2FDF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF => Script::Han,
+            0x20000..=0x2FA1F | 0x30000..=0x3134F => Script::Han,
+            // ASCII and Latin-1 symbols, spacing modifiers, and combining diacritical marks.
+            0x0..=0xBF | 0x2B0..=0x36F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF => Script::Common,
+            // Punctuation, superscripts, currency, letterlike and other symbols, and arrows.
+            0x2000..=0x2BFF | 0x2E00..=0x2E7F => Script::Common,
+            0x3000..=0x303F | 0xFE00..=0xFE6F | 0xFF00..=0xFFEF | 0xFFF0..=0xFFFF => Script::Common,
+            0x1F000..=0x1FAFF | 0xE0000..=0xE007F | 0xE0100..=0xE01EF => Script::Common,
+            _ => Script::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> alloc::vec::Vec<char> {
+        let mut output = alloc::vec::Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            output.push(read_utf8(bytes, &mut offset));
+        }
+        output
+    }
+
+    #[test]
+    fn justification_weight() {
+        // The quads stretch like the en and em spaces they're equivalent to.
+        assert_eq!(
+            CharacterData::justification_weight('\u{2000}'),
+            CharacterData::justification_weight('\u{2002}')
+        );
+        assert_eq!(
+            CharacterData::justification_weight('\u{2001}'),
+            CharacterData::justification_weight('\u{2003}')
+        );
+        assert_eq!(
+            CharacterData::justification_weight('\u{2001}'),
+            2.0 * CharacterData::justification_weight(' ')
+        );
+        assert_eq!(CharacterData::justification_weight('\u{1680}'), CharacterData::justification_weight(' '));
+        assert!(CharacterData::justification_weight('\u{2008}') > 0.0);
+        // No-break spaces keep their width.
+        for c in ['\u{A0}', '\u{2007}', '\u{202F}', 'a'] {
+            assert_eq!(CharacterData::justification_weight(c), 0.0, "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn script_of() {
+        assert_eq!(Script::of('a'), Script::Latin);
+        assert_eq!(Script::of('\u{E9}'), Script::Latin);
+        assert_eq!(Script::of('\u{416}'), Script::Cyrillic);
+        assert_eq!(Script::of('\u{915}'), Script::Devanagari);
+        assert_eq!(Script::of('\u{E01}'), Script::Thai);
+        assert_eq!(Script::of('\u{FFA1}'), Script::Hangul);
+        for c in [' ', '1', '!', '\u{D7}', '\u{301}', '\u{2014}', '\u{20AC}', '\u{3001}', '\u{1F600}'] {
+            assert_eq!(Script::of(c), Script::Common, "{:?}", c);
+        }
+        // Indic and Southeast Asian scripts without a variant of their own aren't Common.
+        let unlisted = [
+            '\u{985}',  // Bengali
+            '\u{A05}',  // Gurmukhi
+            '\u{A85}',  // Gujarati
+            '\u{B85}',  // Tamil
+            '\u{C05}',  // Telugu
+            '\u{C85}',  // Kannada
+            '\u{D05}',  // Malayalam
+            '\u{D85}',  // Sinhala
+            '\u{E81}',  // Lao
+            '\u{F40}',  // Tibetan
+            '\u{1000}', // Myanmar
+            '\u{1200}', // Ethiopic
+            '\u{1780}', // Khmer
+            '\u{1A20}', // Tai Tham
+            '\u{1B05}', // Balinese
+        ];
+        for c in unlisted {
+            assert_eq!(Script::of(c), Script::Unknown, "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn read_utf8_valid() {
+        let text = "a\u{E9}\u{20AC}\u{1F600}\u{10FFFF}";
+        assert_eq!(decode(text.as_bytes()), text.chars().collect::<alloc::vec::Vec<char>>());
+    }
+
+    #[test]
+    fn read_utf8_malformed() {
+        const R: char = char::REPLACEMENT_CHARACTER;
+        // Lone continuation byte.
+        assert_eq!(decode(&[0x80]), [R]);
+        // Truncated sequences, with the following byte still decoded.
+        assert_eq!(decode(&[0xE2, 0x82]), [R, R]);
+        assert_eq!(decode(&[0xE2, b'a']), [R, 'a']);
+        // Overlong encodings.
+        assert_eq!(decode(&[0xC0, 0xAF]), [R, R]);
+        assert_eq!(decode(&[0xE0, 0x80, 0xAF]), [R, R, R]);
+        // Surrogates and values past U+10FFFF.
+        assert_eq!(decode(&[0xED, 0xA0, 0x80]), [R, R, R]);
+        assert_eq!(decode(&[0xF4, 0x90, 0x80, 0x80]), [R, R, R, R]);
+    }
+
+    #[test]
+    fn mirror_pairs() {
+        assert!(MIRRORED_PAIRS.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(mirror('('), Some(')'));
+        assert_eq!(mirror(')'), Some('('));
+        assert_eq!(mirror('\u{226B}'), Some('\u{226A}'));
+        assert_eq!(mirror('a'), None);
+    }
 }